@@ -0,0 +1,8 @@
+-- Add migration script here
+CREATE TABLE controller_mappings
+(
+    id        BIGSERIAL NOT NULL PRIMARY KEY,
+    uri       TEXT      NOT NULL,
+    device_id BIGINT REFERENCES devices (id) ON DELETE CASCADE ON UPDATE NO ACTION,
+    host      TEXT
+)
\ No newline at end of file
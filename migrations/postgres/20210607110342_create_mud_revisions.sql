@@ -0,0 +1,9 @@
+-- Add migration script here
+CREATE TABLE mud_revisions
+(
+    id         BIGSERIAL NOT NULL PRIMARY KEY,
+    url        TEXT      NOT NULL REFERENCES mud_data (url) ON DELETE CASCADE ON UPDATE NO ACTION,
+    data       TEXT      NOT NULL,
+    created_at TIMESTAMP NOT NULL
+);
+ALTER TABLE mud_data ADD COLUMN pinned_revision_id BIGINT
\ No newline at end of file
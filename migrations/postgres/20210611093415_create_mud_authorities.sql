@@ -0,0 +1,8 @@
+-- Add migration script here
+CREATE TABLE mud_authorities
+(
+    authority TEXT NOT NULL PRIMARY KEY,
+    policy    TEXT NOT NULL
+);
+ALTER TABLE mud_data ADD COLUMN approved_at TIMESTAMP;
+UPDATE mud_data SET approved_at = created_at
\ No newline at end of file
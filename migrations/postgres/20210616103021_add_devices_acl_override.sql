@@ -0,0 +1,2 @@
+-- Add migration script here
+ALTER TABLE devices ADD COLUMN acl_override text;
\ No newline at end of file
@@ -0,0 +1,13 @@
+-- Add migration script here
+CREATE TABLE policy_exceptions
+(
+    id         BIGSERIAL NOT NULL PRIMARY KEY,
+    device_id  BIGINT REFERENCES devices (id) ON DELETE CASCADE ON UPDATE NO ACTION,
+    room_id    BIGINT REFERENCES rooms (room_id) ON DELETE CASCADE ON UPDATE NO ACTION,
+    mud_url    TEXT REFERENCES mud_data (url) ON DELETE CASCADE ON UPDATE NO ACTION,
+    acls       TEXT      NOT NULL,
+    reason     TEXT      NOT NULL,
+    created_by TEXT      NOT NULL,
+    created_at TIMESTAMP NOT NULL,
+    expires_at TIMESTAMP NOT NULL
+)
\ No newline at end of file
@@ -0,0 +1,2 @@
+-- Add migration script here
+ALTER TABLE policy_exceptions ADD COLUMN retired_at TIMESTAMP;
\ No newline at end of file
@@ -0,0 +1,8 @@
+-- Add migration script here
+CREATE TABLE controller_mappings
+(
+    id        INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    uri       TEXT    NOT NULL,
+    device_id INTEGER REFERENCES devices (id) ON DELETE CASCADE ON UPDATE NO ACTION,
+    host      TEXT
+)
\ No newline at end of file
@@ -0,0 +1,3 @@
+-- Add migration script here
+ALTER TABLE mud_data ADD COLUMN etag TEXT;
+ALTER TABLE mud_data ADD COLUMN last_modified TEXT;
\ No newline at end of file
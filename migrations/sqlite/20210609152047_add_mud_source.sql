@@ -0,0 +1,2 @@
+-- Add migration script here
+ALTER TABLE mud_data ADD COLUMN source TEXT;
\ No newline at end of file
@@ -0,0 +1,10 @@
+-- Add migration script here
+CREATE TABLE mud_fetch_status
+(
+    url                  TEXT      NOT NULL PRIMARY KEY REFERENCES mud_data (url) ON DELETE CASCADE ON UPDATE NO ACTION,
+    last_success         TIMESTAMP,
+    last_error           TEXT,
+    last_error_at        TIMESTAMP,
+    consecutive_failures INTEGER   NOT NULL DEFAULT 0,
+    next_refresh         TIMESTAMP NOT NULL
+)
\ No newline at end of file
@@ -0,0 +1,2 @@
+-- Add migration script here
+ALTER TABLE devices ADD COLUMN acl_override TEXT;
\ No newline at end of file
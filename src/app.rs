@@ -162,8 +162,10 @@ pub fn start_app(
                 .service(web::scope("/devices").configure(routes::device_controller::init))
                 .service(web::scope("/mud").configure(routes::mud_controller::init))
                 .service(web::scope("/config").configure(routes::config_controller::init))
+                .service(web::scope("/controllers").configure(routes::controller_mapping_controller::init))
                 .service(web::scope("/roles").configure(routes::role_manager_controller::init))
                 .service(web::scope("/rooms").configure(routes::room_controller::init))
+                .service(web::scope("/exceptions").configure(routes::policy_exception_controller::init))
                 .with_json_spec_at("/api/spec")
                 .build()
                 .route(
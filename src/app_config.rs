@@ -1,8 +1,11 @@
 // Copyright 2020-2021, Benjamin Ludewig, Florian Bonetti, Jeffrey Munstermann, Luca Nittscher, Hugo Damer, Michael Bach
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
+use ipnetwork::IpNetwork;
 use lazy_static::lazy_static;
 
+use crate::services::mud_service::source::MudSourceKind;
+
 lazy_static! {
     pub static ref APP_CONFIG: AppConfig = envy::from_env::<AppConfig>().expect("Missing environment variables");
 }
@@ -52,6 +55,40 @@ pub struct AppConfig {
     /// `NAMIB_ACME_DIR`: The directory that ACME generated certs (letsencrypt certs) are saved
     #[serde(default = "default_acme_dir")]
     pub namib_acme_dir: String,
+    /// `LOCAL_NETWORKS`: Comma separated list of the networks managed by the enforcers, used to resolve the
+    /// `local-networks` matches of MUD-Files (default `10.0.0.0/8,172.16.0.0/12,192.168.0.0/16,fc00::/7,fe80::/10`).
+    #[serde(default = "default_local_networks")]
+    pub local_networks: Vec<IpNetwork>,
+    /// `MUD_TRUST_STORE`: The path to a PEM bundle of the certificates trusted to sign MUD-Files. If unset, the system
+    /// trust store is used.
+    pub mud_trust_store: Option<String>,
+    /// `MUD_REPOSITORY`: The path to a directory of MUD-Files, which are used by the `repository` MUD source.
+    /// Detached signatures are read from files with the same name and the extension `p7s`.
+    pub mud_repository: Option<String>,
+    /// `MUD_SOURCES`: Comma separated list of the sources asked for MUD-Files, in order. Possible sources are
+    /// `repository`, `https` and `neo4things`. Networks without internet access can use `repository` only
+    /// (default `repository,https`).
+    #[serde(default = "default_mud_sources")]
+    pub mud_sources: Vec<MudSourceKind>,
+    /// `MUD_FETCH_PROXY`: The url of the proxy used to fetch MUD-Files and their signatures, e.g.
+    /// `http://proxy.example.com:3128`. If unset, MUD-Files are fetched directly, ignoring the system proxy settings.
+    pub mud_fetch_proxy: Option<String>,
+    /// `MUD_FETCH_CA_CERTS`: The path to a PEM bundle of CA certificates that are trusted in addition to the system
+    /// trust store when fetching MUD-Files, e.g. of an internal CA.
+    pub mud_fetch_ca_certs: Option<String>,
+    /// `MUD_FETCH_ALLOWED_NETWORKS`: Comma separated list of loopback, link-local or private networks MUD-Files may
+    /// still be fetched from. Other addresses of these kinds are refused (default none).
+    #[serde(default)]
+    pub mud_fetch_allowed_networks: Vec<IpNetwork>,
+    /// `MUD_FETCH_TIMEOUT`: The timeout in seconds for fetching a MUD-File (default `15`).
+    #[serde(default = "default_mud_fetch_timeout")]
+    pub mud_fetch_timeout: u64,
+    /// `MUD_FETCH_MAX_SIZE`: The maximum size in bytes of a fetched MUD-File or signature (default `10000000`).
+    #[serde(default = "default_mud_fetch_max_size")]
+    pub mud_fetch_max_size: usize,
+    /// `MUD_FETCH_MAX_REDIRECTS`: The maximum number of redirects followed when fetching a MUD-File (default `5`).
+    #[serde(default = "default_mud_fetch_max_redirects")]
+    pub mud_fetch_max_redirects: usize,
 }
 
 fn default_ratelimiter_requests_per_minute() -> usize {
@@ -88,3 +125,26 @@ fn default_server_cert() -> String {
 fn default_server_key() -> String {
     "./certs/server-key.pem".to_string()
 }
+
+fn default_local_networks() -> Vec<IpNetwork> {
+    ["10.0.0.0/8", "172.16.0.0/12", "192.168.0.0/16", "fc00::/7", "fe80::/10"]
+        .iter()
+        .map(|network| network.parse().expect("Is valid network"))
+        .collect()
+}
+
+fn default_mud_sources() -> Vec<MudSourceKind> {
+    vec![MudSourceKind::Repository, MudSourceKind::Https]
+}
+
+fn default_mud_fetch_timeout() -> u64 {
+    15
+}
+
+fn default_mud_fetch_max_size() -> usize {
+    10_000_000
+}
+
+fn default_mud_fetch_max_redirects() -> usize {
+    5
+}
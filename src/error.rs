@@ -98,6 +98,11 @@ pub enum Error {
         source: acme_lib::Error,
         backtrace: Backtrace,
     },
+    #[snafu(display("OpensslError {}", source), context(false))]
+    OpensslError {
+        source: openssl::error::ErrorStack,
+        backtrace: Backtrace,
+    },
     #[snafu(display("ReqwestError {}", source), context(false))]
     ReqwestError {
         source: reqwest::Error,
@@ -23,8 +23,14 @@ use std::net::{Ipv4Addr, Ipv6Addr, SocketAddrV4, SocketAddrV6};
 use dotenv::dotenv;
 use log::{error, warn};
 use namib_mud_controller::{
-    app::ControllerAppBuilder, app_config::APP_CONFIG, auth::initialize_jwt_secret, db, error::Result, rpc_server,
-    services::job_service, VERSION,
+    app::ControllerAppBuilder,
+    app_config::APP_CONFIG,
+    auth::initialize_jwt_secret,
+    db,
+    error::Result,
+    rpc_server,
+    services::{job_service, mud_service},
+    VERSION,
 };
 use tokio::select;
 
@@ -39,6 +45,10 @@ async fn main() -> Result<()> {
 
     let conn = db::connect().await?;
     initialize_jwt_secret(&conn).await?;
+    mud_service::upgrade_stored_muds(&conn).await?;
+    mud_service::repository::rescan_repository();
+    mud_service::signature::init_trust_store();
+    mud_service::init_ca_certs();
     let rpc_server_task = tokio::task::spawn(rpc_server::listen(conn.clone()));
 
     // Starts a new job that updates the expired profiles at regular intervals.
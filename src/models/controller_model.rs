@@ -0,0 +1,14 @@
+// Copyright 2020-2021, Benjamin Ludewig, Florian Bonetti, Jeffrey Munstermann, Luca Nittscher, Hugo Damer, Michael Bach
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use paperclip::actix::Apiv2Schema;
+
+/// Maps a controller class URI (or a MUD-URL, for `my-controller` matches) to a device or host acting as the
+/// controller.
+#[derive(Debug, Clone, Serialize, Deserialize, Apiv2Schema, PartialEq)]
+pub struct ControllerMapping {
+    pub id: i64,
+    pub uri: String,
+    pub device_id: Option<i64>,
+    pub host: Option<String>,
+}
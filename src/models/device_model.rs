@@ -16,8 +16,12 @@ use paperclip::actix::Apiv2Schema;
 use crate::{
     db::DbConnection,
     error::Result,
-    models::{mud_models::MudData, Room},
-    services::{mud_service, room_service},
+    models::{mud_models::MudData, Acl, Room},
+    services::{
+        mud_service,
+        mud_service::authority::{get_authority_policy, MudAuthorityPolicy},
+        room_service,
+    },
 };
 
 #[derive(Debug, Clone)]
@@ -35,6 +39,7 @@ pub struct DeviceDbo {
     pub last_interaction: NaiveDateTime,
     pub room_id: Option<i64>,
     pub clipart: Option<String>,
+    pub acl_override: Option<String>,
 }
 
 #[derive(Debug, Clone)]
@@ -52,6 +57,9 @@ pub struct Device {
     pub last_interaction: NaiveDateTime,
     pub room_id: Option<i64>,
     pub clipart: Option<String>,
+    /// Overrides of the ACLs of the MUD-Profile that only apply to this device, applied after the profile overrides.
+    /// Like the profile overrides, they only apply to devices with a MUD-Profile.
+    pub acl_override: Vec<Acl>,
 }
 
 #[derive(Debug, Clone)]
@@ -95,7 +103,11 @@ impl Device {
             None => None,
         };
         let mud_data = match &self.mud_url {
-            Some(mud_url) => Some(mud_service::get_or_fetch_mud(&mud_url, conn).await?),
+            // devices of blocked authorities keep their MUD-URL, but their MUD-File is never fetched
+            Some(mud_url) => match get_authority_policy(&mud_url, conn).await {
+                MudAuthorityPolicy::Blocked => None,
+                _ => Some(mud_service::get_or_fetch_mud(&mud_url, conn).await?),
+            },
             None => None,
         };
         Ok(DeviceWithRefs {
@@ -108,8 +120,9 @@ impl Device {
 
 impl From<DeviceDbo> for Device {
     fn from(device: DeviceDbo) -> Self {
+        let id = device.id;
         Self {
-            id: device.id,
+            id,
             name: device.name,
             ipv4_addr: device.ipv4_addr.and_then(|ip| ip.parse().ok()),
             ipv6_addr: device.ipv6_addr.and_then(|ip| ip.parse().ok()),
@@ -124,6 +137,16 @@ impl From<DeviceDbo> for Device {
             last_interaction: device.last_interaction,
             room_id: device.room_id,
             clipart: device.clipart,
+            acl_override: device
+                .acl_override
+                .and_then(|acl_override| match serde_json::from_str(&acl_override) {
+                    Ok(acl_override) => Some(acl_override),
+                    Err(e) => {
+                        error!("Ignoring the corrupt ACL override of device {}: {:?}", id, e);
+                        None
+                    },
+                })
+                .unwrap_or_default(),
         }
     }
 }
@@ -156,6 +179,7 @@ impl Device {
             last_interaction: Utc::now().naive_utc(),
             room_id: None,
             clipart: None,
+            acl_override: Vec::new(),
         }
     }
 
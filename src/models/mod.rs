@@ -2,15 +2,19 @@
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
 mod config_model;
+mod controller_model;
 mod device_model;
 mod mud_models;
+mod policy_exception_model;
 mod room_model;
 mod user_config_model;
 mod user_model;
 
 pub use config_model::*;
+pub use controller_model::*;
 pub use device_model::*;
 pub use mud_models::*;
+pub use policy_exception_model::*;
 pub use room_model::*;
 pub use user_config_model::*;
 pub use user_model::*;
@@ -18,6 +18,8 @@ use crate::error::Result;
 pub struct MudDboRefresh {
     pub url: String,
     pub expiration: NaiveDateTime,
+    /// When the MUD-Profile is refreshed next, if it was refreshed before, see `MudFetchStatusDbo`.
+    pub next_refresh: Option<NaiveDateTime>,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -26,11 +28,79 @@ pub struct MudDbo {
     pub data: String,
     pub created_at: NaiveDateTime,
     pub expiration: NaiveDateTime,
+    pub signature_status: Option<String>,
+    /// The `ETag` of the response the MUD-File was fetched with, used for conditional refreshes.
+    pub etag: Option<String>,
+    /// The `Last-Modified` date of the response the MUD-File was fetched with, used for conditional refreshes.
+    pub last_modified: Option<String>,
+    /// The revision the MUD-Profile is pinned to by an admin, see `MudRevisionDbo`.
+    pub pinned_revision_id: Option<i64>,
+    /// The name of the `MudSource` that provided the MUD-File, `None` for custom MUD-Profiles.
+    pub source: Option<String>,
+    /// When an admin approved the MUD-Profile, which is required if its authority is untrusted, see `authority`.
+    pub approved_at: Option<NaiveDateTime>,
 }
 
 impl MudDbo {
     pub fn parse_data(&self) -> Result<MudData> {
-        Ok(serde_json::from_str::<MudData>(self.data.as_str())?)
+        let mut data = parse_stored_mud_data(self.data.as_str())?;
+        data.signature_status = self.signature_status.as_deref().and_then(|s| s.parse().ok());
+        Ok(data)
+    }
+}
+
+/// The policy set by an admin for the MUD-URLs of an authority, see `authority::MudAuthorityPolicy`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MudAuthorityDbo {
+    pub authority: String,
+    pub policy: String,
+}
+
+/// A revision of a MUD-Profile, recorded whenever a changed MUD-File is fetched or the overrides are edited.
+#[derive(Debug, Clone, Serialize)]
+pub struct MudRevisionDbo {
+    pub id: i64,
+    pub url: String,
+    pub data: String,
+    pub created_at: NaiveDateTime,
+}
+
+impl MudRevisionDbo {
+    pub fn parse_data(&self) -> Result<MudData> {
+        parse_stored_mud_data(self.data.as_str())
+    }
+}
+
+/// Deserializes `MudData` as stored in the database, upgrading data stored by older versions.
+pub fn parse_stored_mud_data(data: &str) -> Result<MudData> {
+    let mut value: serde_json::Value = serde_json::from_str(data)?;
+    for acls in &["acllist", "acl_override"] {
+        if let Some(acls) = value.get_mut(acls).and_then(serde_json::Value::as_array_mut) {
+            acls.iter_mut().for_each(upgrade_legacy_host_matches);
+        }
+    }
+    Ok(serde_json::from_value(value)?)
+}
+
+/// Older versions stored a single `address_mask` and `dnsname` per ACE, which always matched the remote host, i.e.
+/// the destination of packets from the device and the source of packets to the device.
+fn upgrade_legacy_host_matches(acl: &mut serde_json::Value) {
+    let side = match acl.get("packet_direction").and_then(serde_json::Value::as_str) {
+        Some("ToDevice") => "source",
+        _ => "destination",
+    };
+    let aces = match acl.get_mut("ace").and_then(serde_json::Value::as_array_mut) {
+        Some(aces) => aces,
+        None => return,
+    };
+    for ace in aces {
+        if let Some(matches) = ace.get_mut("matches").and_then(serde_json::Value::as_object_mut) {
+            for (legacy_key, key) in &[("address_mask", "network"), ("dnsname", "dnsname")] {
+                if let Some(legacy_value) = matches.remove(*legacy_key) {
+                    matches.insert(format!("{}_{}", side, key), legacy_value);
+                }
+            }
+        }
     }
 }
 
@@ -38,16 +108,108 @@ impl MudDbo {
 pub struct MudData {
     pub url: String,
     pub masa_url: Option<String>,
+    /// The url of the detached signature of the MUD-File, if it references one.
+    pub mud_signature: Option<String>,
+    /// The result of verifying the signature when the MUD-File was fetched, `None` for custom MUD-Profiles.
+    pub signature_status: Option<MudSignatureStatus>,
     pub last_update: String,
     pub systeminfo: Option<String>,
     pub mfg_name: Option<String>,
     pub model_name: Option<String>,
     pub documentation: Option<String>,
+    /// Whether the manufacturer still supports the device, `false` for end-of-life devices.
+    #[serde(default = "default_is_supported")]
+    pub is_supported: bool,
+    /// The `cache-validity` of the MUD-File in hours, `None` if the MUD-File doesn't set it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_validity: Option<i64>,
+    /// The result of the latest refreshes of the MUD-File, see `refresh`. Only set when MUD-Profiles are queried.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fetch_status: Option<MudFetchStatus>,
+    /// The MUD-URL of the profile this custom MUD-Profile inherits its ACLs from, see `inheritance`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent_url: Option<String>,
+    /// The changes to single ACEs of the profile, or of the parent profile if it has one, see `ace_patch`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ace_patches: Vec<AcePatch>,
     pub expiration: DateTime<Utc>,
     pub acllist: Vec<Acl>,
     pub acl_override: Vec<Acl>,
 }
 
+fn default_is_supported() -> bool {
+    true
+}
+
+/// The result of the latest refreshes of a MUD-File, used to schedule its next refresh.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MudFetchStatusDbo {
+    pub url: String,
+    pub last_success: Option<NaiveDateTime>,
+    pub last_error: Option<String>,
+    pub last_error_at: Option<NaiveDateTime>,
+    pub consecutive_failures: i64,
+    pub next_refresh: NaiveDateTime,
+}
+
+#[derive(Debug, Serialize, Deserialize, Apiv2Schema, Clone, Eq, PartialEq)]
+pub struct MudFetchStatus {
+    pub last_success: Option<NaiveDateTime>,
+    pub last_error: Option<String>,
+    pub last_error_at: Option<NaiveDateTime>,
+    /// The number of failed refreshes since the last successful one, which the delay of the next refresh depends on.
+    pub consecutive_failures: i64,
+    pub next_refresh: NaiveDateTime,
+}
+
+impl From<MudFetchStatusDbo> for MudFetchStatus {
+    fn from(status: MudFetchStatusDbo) -> Self {
+        Self {
+            last_success: status.last_success,
+            last_error: status.last_error,
+            last_error_at: status.last_error_at,
+            consecutive_failures: status.consecutive_failures,
+            next_refresh: status.next_refresh,
+        }
+    }
+}
+
+/// A change of a single ACE of an ACL, identified by the names of the ACL and the ACE.
+#[derive(Debug, Serialize, Deserialize, Apiv2Schema, Clone, Eq, PartialEq)]
+pub struct AcePatch {
+    pub acl_name: String,
+    pub ace_name: String,
+    pub operation: AcePatchOperation,
+    /// The new ACE for `Add` and `Replace` operations, its name is set to `ace_name`.
+    pub ace: Option<Ace>,
+    /// The patched ACE as it was when the patch was made, used to detect changes of the patched ACE.
+    #[serde(default)]
+    pub base: Option<Ace>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Apiv2Schema, Clone, Copy, Eq, PartialEq)]
+pub enum AcePatchOperation {
+    /// Appends a new ACE to the ACL.
+    Add,
+    /// Replaces the ACE of the same name.
+    Replace,
+    /// Removes the ACE of the same name.
+    Remove,
+}
+
+/// The result of verifying the detached signature of a MUD-File, see https://tools.ietf.org/html/rfc8520#section-13
+#[derive(
+    Debug, Serialize, Deserialize, Apiv2Schema, Clone, Copy, Eq, PartialEq, strum::AsRefStr, strum::EnumString,
+)]
+pub enum MudSignatureStatus {
+    /// The signature was verified against the trust store.
+    Valid,
+    /// The signature couldn't be fetched or doesn't match the MUD-File or the trust store.
+    Invalid,
+    /// The MUD-File doesn't reference a signature.
+    Unsigned,
+}
+
 #[derive(Debug, Serialize, Deserialize, Apiv2Schema, Clone, Eq, PartialEq)]
 pub struct Acl {
     pub name: String,
@@ -67,10 +229,49 @@ pub struct Ace {
 pub struct AceMatches {
     pub protocol: Option<AceProtocol>,
     pub direction_initiated: Option<AclDirection>,
-    pub address_mask: Option<String>,
-    pub dnsname: Option<String>,
+    pub source_network: Option<String>,
+    pub destination_network: Option<String>,
+    pub source_dnsname: Option<String>,
+    pub destination_dnsname: Option<String>,
     pub source_port: Option<AcePort>,
     pub destination_port: Option<AcePort>,
+    pub icmp: Option<AceIcmpMatches>,
+    pub eth: Option<AceEthMatches>,
+    pub mud: Option<AceMudMatches>,
+}
+
+/// The ethernet header fields an ACE matches, if set.
+#[derive(Debug, Serialize, Deserialize, Apiv2Schema, Clone, Eq, PartialEq)]
+pub struct AceEthMatches {
+    pub ethertype: Option<u16>,
+    pub source_mac_address: Option<String>,
+    pub destination_mac_address: Option<String>,
+}
+
+/// The icmp type and code an ACE matches, if set.
+#[derive(Debug, Serialize, Deserialize, Apiv2Schema, Clone, Eq, PartialEq)]
+pub struct AceIcmpMatches {
+    pub icmp_type: Option<u8>,
+    pub code: Option<u8>,
+}
+
+/// The MUD specific abstractions of an ACE (RFC 8520 section 8), which are resolved when generating the firewall
+/// configuration.
+#[derive(Debug, Serialize, Deserialize, Apiv2Schema, Clone, Eq, PartialEq)]
+pub struct AceMudMatches {
+    /// Matches the local networks the controller is managing.
+    pub local_networks: bool,
+    /// Matches devices whose MUD-URL has the same authority as the MUD-URL of this device.
+    pub same_manufacturer: bool,
+    /// Matches devices whose MUD-URL has the given authority.
+    pub manufacturer: Option<String>,
+    /// Matches devices that use the given MUD-URL.
+    pub model: Option<String>,
+    /// Matches the controllers registered for the given controller class URI.
+    pub controller: Option<String>,
+    /// Matches the controllers registered for the MUD-URL of this device.
+    #[serde(default)]
+    pub my_controller: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
@@ -79,6 +280,12 @@ pub enum AcePort {
     Single(u32),
     #[serde(rename = "range")]
     Range(u32, u32),
+    #[serde(rename = "lte")]
+    Lte(u32),
+    #[serde(rename = "gte")]
+    Gte(u32),
+    #[serde(rename = "neq")]
+    Neq(u32),
 }
 
 impl Apiv2Schema for AcePort {
@@ -90,6 +297,9 @@ impl Apiv2Schema for AcePort {
         schema
             .properties
             .insert("range".into(), <[u32; 2]>::raw_schema().into());
+        schema.properties.insert("lte".into(), u32::raw_schema().into());
+        schema.properties.insert("gte".into(), u32::raw_schema().into());
+        schema.properties.insert("neq".into(), u32::raw_schema().into());
         schema.name = Some("AcePort".into());
         schema
     }
@@ -132,6 +342,7 @@ pub enum AceAction {
 pub enum AclType {
     IPV6,
     IPV4,
+    Ethernet,
 }
 
 #[derive(Clone, Copy, Debug, Serialize, Deserialize, Apiv2Schema, Eq, PartialEq)]
@@ -149,7 +360,68 @@ mod test {
     #[test]
     fn expect_port_json() {
         assert_eq!(json!(AcePort::Single(17)), json!({"single": 17}));
-        assert_eq!(json!(AcePort::Range(9000, 9050)), json!({"range": [9000, 9050]}))
+        assert_eq!(json!(AcePort::Range(9000, 9050)), json!({"range": [9000, 9050]}));
+        assert_eq!(json!(AcePort::Lte(1023)), json!({"lte": 1023}));
+        assert_eq!(json!(AcePort::Gte(49152)), json!({"gte": 49152}));
+        assert_eq!(json!(AcePort::Neq(80)), json!({"neq": 80}))
+    }
+
+    #[test]
+    fn expect_legacy_host_matches_upgrade() -> Result<()> {
+        let legacy_ace = |dnsname: &str, address_mask: Option<&str>| {
+            json!({
+                "name": "ace",
+                "action": "Accept",
+                "matches": {
+                    "protocol": null,
+                    "direction_initiated": null,
+                    "address_mask": address_mask,
+                    "dnsname": dnsname,
+                    "source_port": null,
+                    "destination_port": null
+                }
+            })
+        };
+        let data = json!({
+            "url": "https://example.com/device",
+            "masa_url": null,
+            "last_update": "2021-05-31T12:00:00",
+            "systeminfo": null,
+            "mfg_name": null,
+            "model_name": null,
+            "documentation": null,
+            "expiration": "2021-06-02T12:00:00Z",
+            "acllist": [
+                {
+                    "name": "from-device",
+                    "packet_direction": "FromDevice",
+                    "acl_type": "IPV4",
+                    "ace": [legacy_ace("www.example.com", Some("192.0.2.0/24"))]
+                },
+                {
+                    "name": "to-device",
+                    "packet_direction": "ToDevice",
+                    "acl_type": "IPV4",
+                    "ace": [legacy_ace("www.example.com", None)]
+                }
+            ],
+            "acl_override": []
+        });
+
+        let mud_data = parse_stored_mud_data(&data.to_string())?;
+
+        let from_device = &mud_data.acllist[0].ace[0].matches;
+        assert_eq!(from_device.source_network, None);
+        assert_eq!(from_device.destination_network, Some(String::from("192.0.2.0/24")));
+        assert_eq!(from_device.source_dnsname, None);
+        assert_eq!(from_device.destination_dnsname, Some(String::from("www.example.com")));
+        let to_device = &mud_data.acllist[1].ace[0].matches;
+        assert_eq!(to_device.source_network, None);
+        assert_eq!(to_device.destination_network, None);
+        assert_eq!(to_device.source_dnsname, Some(String::from("www.example.com")));
+        assert_eq!(to_device.destination_dnsname, None);
+
+        Ok(())
     }
 
     #[test]
@@ -0,0 +1,79 @@
+// Copyright 2020-2021, Benjamin Ludewig, Florian Bonetti, Jeffrey Munstermann, Luca Nittscher, Hugo Damer, Michael Bach
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use chrono::{NaiveDateTime, Utc};
+use paperclip::actix::Apiv2Schema;
+
+use crate::models::{Acl, DeviceWithRefs};
+
+#[derive(Debug, Clone)]
+pub struct PolicyExceptionDbo {
+    pub id: i64,
+    pub device_id: Option<i64>,
+    pub room_id: Option<i64>,
+    pub mud_url: Option<String>,
+    pub acls: String,
+    pub reason: String,
+    pub created_by: String,
+    pub created_at: NaiveDateTime,
+    pub expires_at: NaiveDateTime,
+    pub retired_at: Option<NaiveDateTime>,
+}
+
+/// Additional ACLs for a device, the devices of a room or the devices using a MUD-Profile, which only apply until
+/// they expire, e.g. to let a vendor reach a device for a remote-support session.
+#[derive(Debug, Clone, Serialize, Deserialize, Apiv2Schema, PartialEq)]
+pub struct PolicyException {
+    pub id: i64,
+    /// The device the exception is attached to.
+    pub device_id: Option<i64>,
+    /// The room whose devices the exception is attached to.
+    pub room_id: Option<i64>,
+    /// The MUD-URL of the devices the exception is attached to.
+    pub mud_url: Option<String>,
+    /// The ACLs added to the devices while the exception is active.
+    pub acls: Vec<Acl>,
+    /// Why the exception was created.
+    pub reason: String,
+    /// The name of the user who created the exception.
+    pub created_by: String,
+    pub created_at: NaiveDateTime,
+    /// When the exception is retired.
+    pub expires_at: NaiveDateTime,
+    /// When the exception was retired after it expired. Retired exceptions are kept as audit trail.
+    pub retired_at: Option<NaiveDateTime>,
+}
+
+impl PolicyException {
+    pub fn is_active(&self) -> bool {
+        self.expires_at > Utc::now().naive_utc()
+    }
+
+    /// Whether the exception is attached to the device, its room or its MUD-Profile.
+    pub fn applies_to(&self, device: &DeviceWithRefs) -> bool {
+        self.device_id == Some(device.id)
+            || (self.room_id.is_some() && self.room_id == device.room_id)
+            || (self.mud_url.is_some() && self.mud_url == device.mud_url)
+    }
+}
+
+impl From<PolicyExceptionDbo> for PolicyException {
+    fn from(exception: PolicyExceptionDbo) -> Self {
+        let acls = serde_json::from_str(&exception.acls).unwrap_or_else(|e| {
+            warn!("Ignoring invalid ACLs of policy exception {}: {:?}", exception.id, e);
+            Vec::new()
+        });
+        Self {
+            id: exception.id,
+            device_id: exception.device_id,
+            room_id: exception.room_id,
+            mud_url: exception.mud_url,
+            acls,
+            reason: exception.reason,
+            created_by: exception.created_by,
+            created_at: exception.created_at,
+            expires_at: exception.expires_at,
+            retired_at: exception.retired_at,
+        }
+    }
+}
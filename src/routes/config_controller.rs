@@ -12,7 +12,7 @@ use crate::{
     db::DbConnection,
     error::Result,
     routes::dtos::ConfigQueryDto,
-    services::{config_service, role_service::Permission},
+    services::{config_service, firewall_configuration_service, role_service::Permission},
 };
 
 pub fn init(cfg: &mut web::ServiceConfig) {
@@ -59,6 +59,9 @@ async fn set_configs(
     for (key, value) in config_set_dto.iter() {
         config_service::set_config_value(&key, value, &pool).await?;
     }
+    if config_set_dto.keys().any(|key| config_service::is_firewall_policy(key)) {
+        firewall_configuration_service::update_config_version(&pool).await?;
+    }
 
     let mut config_map: HashMap<String, Option<String>> = HashMap::new();
     for (key, _) in config_set_dto.into_inner() {
@@ -82,6 +85,12 @@ async fn delete_config(
         let value = config_service::delete_config_key(&key, &pool).await?;
         deletion_map.insert(key, value);
     }
+    if deletion_map
+        .iter()
+        .any(|(key, deleted)| *deleted && config_service::is_firewall_policy(key))
+    {
+        firewall_configuration_service::update_config_version(&pool).await?;
+    }
 
     Ok(Json(deletion_map))
 }
@@ -0,0 +1,170 @@
+// Copyright 2020-2021, Benjamin Ludewig, Florian Bonetti, Jeffrey Munstermann, Luca Nittscher, Hugo Damer, Michael Bach
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+#![allow(clippy::needless_pass_by_value)]
+
+use actix_web::http::StatusCode;
+use paperclip::actix::{
+    api_v2_operation, web,
+    web::{HttpResponse, Json},
+};
+use snafu::ensure;
+use validator::Validate;
+
+use crate::{
+    auth::AuthToken,
+    db::DbConnection,
+    error,
+    error::Result,
+    routes::dtos::{ControllerMappingCreationUpdateDto, ControllerMappingDto},
+    services::{controller_mapping_service, device_service, role_service::Permission},
+};
+
+pub fn init(cfg: &mut web::ServiceConfig) {
+    cfg.route("", web::get().to(get_all_controller_mappings));
+    cfg.route("/{id}", web::get().to(get_controller_mapping));
+    cfg.route("", web::post().to(create_controller_mapping));
+    cfg.route("/{id}", web::put().to(update_controller_mapping));
+    cfg.route("/{id}", web::delete().to(delete_controller_mapping));
+}
+
+#[api_v2_operation(summary = "Return all controller mappings.", tags(Controllers))]
+async fn get_all_controller_mappings(
+    pool: web::Data<DbConnection>,
+    auth: AuthToken,
+) -> Result<Json<Vec<ControllerMappingDto>>> {
+    auth.require_permission(Permission::controller__list)?;
+    auth.require_permission(Permission::controller__read)?;
+    let res = controller_mapping_service::get_all_controller_mappings(&pool).await?;
+    debug!("{:?}", res);
+    Ok(Json(res.into_iter().map(ControllerMappingDto::from).collect()))
+}
+
+#[api_v2_operation(summary = "Get a controller mapping through its id.", tags(Controllers))]
+async fn get_controller_mapping(
+    pool: web::Data<DbConnection>,
+    auth: AuthToken,
+    id: web::Path<i64>,
+) -> Result<Json<ControllerMappingDto>> {
+    auth.require_permission(Permission::controller__read)?;
+    let res = controller_mapping_service::find_by_id(id.into_inner(), &pool)
+        .await
+        .or_else(|_| {
+            error::ResponseError {
+                status: StatusCode::NOT_FOUND,
+                message: Some("Controller mapping can not be found.".to_string()),
+            }
+            .fail()
+        })?;
+    debug!("{:?}", res);
+    Ok(Json(ControllerMappingDto::from(res)))
+}
+
+#[api_v2_operation(
+    summary = "Maps a controller class URI (or a MUD-URL for my-controller) to either a device or a host.",
+    tags(Controllers)
+)]
+async fn create_controller_mapping(
+    pool: web::Data<DbConnection>,
+    auth: AuthToken,
+    controller_mapping_dto: Json<ControllerMappingCreationUpdateDto>,
+) -> Result<Json<ControllerMappingDto>> {
+    auth.require_permission(Permission::controller__write)?;
+
+    validate_controller_mapping(&controller_mapping_dto, &pool).await?;
+
+    let mapping = controller_mapping_dto.into_inner().into_controller_mapping(0);
+    let id = controller_mapping_service::insert_controller_mapping(&mapping, &pool).await?;
+    let res = controller_mapping_service::find_by_id(id, &pool).await.or_else(|_| {
+        error::ResponseError {
+            status: StatusCode::NOT_FOUND,
+            message: Some("Could not insert controller mapping.".to_string()),
+        }
+        .fail()
+    })?;
+    debug!("{:?}", res);
+    Ok(Json(ControllerMappingDto::from(res)))
+}
+
+#[api_v2_operation(summary = "Updates a controller mapping.", tags(Controllers))]
+async fn update_controller_mapping(
+    pool: web::Data<DbConnection>,
+    auth: AuthToken,
+    id: web::Path<i64>,
+    controller_mapping_dto: Json<ControllerMappingCreationUpdateDto>,
+) -> Result<Json<ControllerMappingDto>> {
+    auth.require_permission(Permission::controller__write)?;
+
+    validate_controller_mapping(&controller_mapping_dto, &pool).await?;
+
+    let mapping = controller_mapping_dto.into_inner().into_controller_mapping(id.0);
+
+    debug!("{:?}", mapping);
+
+    let updated = controller_mapping_service::update_controller_mapping(&mapping, &pool).await?;
+
+    ensure!(
+        updated,
+        error::ResponseError {
+            status: StatusCode::NOT_FOUND,
+            message: Some("Controller mapping can not be found.".to_string()),
+        }
+    );
+
+    Ok(Json(ControllerMappingDto::from(mapping)))
+}
+
+#[api_v2_operation(summary = "Deletes a controller mapping.", tags(Controllers))]
+async fn delete_controller_mapping(
+    pool: web::Data<DbConnection>,
+    auth: AuthToken,
+    id: web::Path<i64>,
+) -> Result<HttpResponse> {
+    auth.require_permission(Permission::controller__delete)?;
+
+    let deleted = controller_mapping_service::delete_controller_mapping(id.0, &pool).await?;
+
+    ensure!(
+        deleted,
+        error::ResponseError {
+            status: StatusCode::NOT_FOUND,
+            message: None,
+        }
+    );
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Checks that a controller mapping is well formed and refers to exactly one existing device or a host.
+async fn validate_controller_mapping(
+    controller_mapping_dto: &ControllerMappingCreationUpdateDto,
+    pool: &DbConnection,
+) -> Result<()> {
+    controller_mapping_dto.validate().or_else(|_| {
+        error::ResponseError {
+            status: StatusCode::BAD_REQUEST,
+            message: None,
+        }
+        .fail()
+    })?;
+
+    ensure!(
+        controller_mapping_dto.device_id.is_some() != controller_mapping_dto.host.is_some(),
+        error::ResponseError {
+            status: StatusCode::BAD_REQUEST,
+            message: Some("Either a device or a host has to be specified.".to_string()),
+        }
+    );
+
+    if let Some(device_id) = controller_mapping_dto.device_id {
+        device_service::find_by_id(device_id, pool).await.or_else(|_| {
+            error::ResponseError {
+                status: StatusCode::BAD_REQUEST,
+                message: Some("Device can not be found.".to_string()),
+            }
+            .fail()
+        })?;
+    }
+
+    Ok(())
+}
@@ -16,9 +16,14 @@ use crate::{
     db::DbConnection,
     error,
     error::Result,
-    models::Device,
+    models::{Acl, Device},
     routes::dtos::{DeviceCreationUpdateDto, DeviceDto, GuessDto},
-    services::{device_service, neo4things_service, role_service::Permission},
+    services::{
+        device_service,
+        firewall_configuration_service::{self, RuleProvenance},
+        neo4things_service,
+        role_service::Permission,
+    },
 };
 
 pub fn init(cfg: &mut web::ServiceConfig) {
@@ -28,6 +33,10 @@ pub fn init(cfg: &mut web::ServiceConfig) {
     cfg.route("/{id}", web::put().to(update_device));
     cfg.route("/{id}", web::delete().to(delete_device));
     cfg.route("/{id}/guesses", web::get().to(guess_thing));
+    cfg.route("/{id}/overrides", web::get().to(get_device_overrides));
+    cfg.route("/{id}/overrides", web::put().to(update_device_overrides));
+    cfg.route("/{id}/overrides", web::delete().to(delete_device_overrides));
+    cfg.route("/{id}/rules", web::get().to(get_device_rules));
 }
 
 #[api_v2_operation(summary = "List all devices", tags(Devices))]
@@ -148,6 +157,69 @@ async fn guess_thing(
     Ok(Json(guesses))
 }
 
+#[api_v2_operation(summary = "Get the ACL overrides of a device", tags(Devices))]
+async fn get_device_overrides(
+    pool: web::Data<DbConnection>,
+    auth: AuthToken,
+    id: web::Path<i64>,
+) -> Result<Json<Vec<Acl>>> {
+    auth.require_permission(Permission::device__read)?;
+
+    let device = find_device(id.into_inner(), &pool).await?;
+
+    Ok(Json(device.acl_override))
+}
+
+#[api_v2_operation(summary = "Replace the ACL overrides of a device", tags(Devices))]
+async fn update_device_overrides(
+    pool: web::Data<DbConnection>,
+    auth: AuthToken,
+    id: web::Path<i64>,
+    acl_override: Json<Vec<Acl>>,
+) -> Result<Json<Vec<Acl>>> {
+    auth.require_permission(Permission::device__write)?;
+
+    let device = find_device(id.into_inner(), &pool).await?;
+    let acl_override = acl_override.into_inner();
+
+    device_service::update_device_overrides(device.id, &acl_override, &pool).await?;
+
+    Ok(Json(acl_override))
+}
+
+#[api_v2_operation(summary = "Remove the ACL overrides of a device", tags(Devices))]
+async fn delete_device_overrides(
+    pool: web::Data<DbConnection>,
+    auth: AuthToken,
+    id: web::Path<i64>,
+) -> Result<HttpResponse> {
+    auth.require_permission(Permission::device__write)?;
+
+    let device = find_device(id.into_inner(), &pool).await?;
+
+    device_service::update_device_overrides(device.id, &[], &pool).await?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[api_v2_operation(
+    summary = "List the firewall rules of a device with the MUD-URL, ACL and ACE they are generated from",
+    tags(Devices)
+)]
+async fn get_device_rules(
+    pool: web::Data<DbConnection>,
+    auth: AuthToken,
+    id: web::Path<i64>,
+) -> Result<Json<Vec<RuleProvenance>>> {
+    auth.require_permission(Permission::device__read)?;
+
+    let device = find_device(id.into_inner(), &pool).await?;
+
+    Ok(Json(
+        firewall_configuration_service::get_device_rule_provenance(device, &pool).await?,
+    ))
+}
+
 /// Helper method for finding a device with a given ip, or returning a 404 error if not found.
 async fn find_device(id: i64, pool: &DbConnection) -> Result<Device> {
     device_service::find_by_id(id, pool).await.or_else(|_| {
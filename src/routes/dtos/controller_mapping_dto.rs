@@ -0,0 +1,50 @@
+// Copyright 2020-2021, Benjamin Ludewig, Florian Bonetti, Jeffrey Munstermann, Luca Nittscher, Hugo Damer, Michael Bach
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+#![allow(clippy::field_reassign_with_default)]
+
+use paperclip::actix::Apiv2Schema;
+
+use crate::models::ControllerMapping;
+
+#[derive(Debug, Serialize, Deserialize, Apiv2Schema, PartialEq)]
+pub struct ControllerMappingDto {
+    pub id: i64,
+    /// The controller class URI, or the MUD-URL of the devices for `my-controller` matches.
+    pub uri: String,
+    /// The device acting as the controller.
+    pub device_id: Option<i64>,
+    /// The ip address or hostname of the controller, if it is not a known device.
+    pub host: Option<String>,
+}
+
+impl From<ControllerMapping> for ControllerMappingDto {
+    fn from(mapping: ControllerMapping) -> Self {
+        ControllerMappingDto {
+            id: mapping.id,
+            uri: mapping.uri,
+            device_id: mapping.device_id,
+            host: mapping.host,
+        }
+    }
+}
+
+#[derive(Validate, Debug, Serialize, Deserialize, Apiv2Schema)]
+pub struct ControllerMappingCreationUpdateDto {
+    #[validate(length(min = 1))]
+    pub uri: String,
+    pub device_id: Option<i64>,
+    #[validate(length(min = 1, max = 253))]
+    pub host: Option<String>,
+}
+
+impl ControllerMappingCreationUpdateDto {
+    pub fn into_controller_mapping(self, id: i64) -> ControllerMapping {
+        ControllerMapping {
+            id,
+            uri: self.uri,
+            device_id: self.device_id,
+            host: self.host,
+        }
+    }
+}
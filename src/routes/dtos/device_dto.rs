@@ -9,7 +9,7 @@ use paperclip::actix::Apiv2Schema;
 
 use crate::{
     error::Result,
-    models::{Device, DeviceType, DeviceWithRefs, MudData, Room},
+    models::{Acl, Device, DeviceType, DeviceWithRefs, MudData, Room},
 };
 
 #[derive(Debug, Serialize, Deserialize, Apiv2Schema)]
@@ -25,8 +25,12 @@ pub struct DeviceDto {
     pub mud_url: Option<String>,
     pub last_interaction: NaiveDateTime,
     pub mud_data: Option<MudData>,
+    /// Whether the manufacturer still supports the device according to its MUD-File.
+    pub is_supported: bool,
     pub clipart: Option<String>,
     pub room: Option<Room>,
+    /// Overrides of the ACLs of the MUD-Profile that only apply to this device.
+    pub acl_override: Vec<Acl>,
     #[serde(rename = "type")]
     pub type_: DeviceType,
 }
@@ -34,6 +38,7 @@ pub struct DeviceDto {
 impl From<DeviceWithRefs> for DeviceDto {
     fn from(d: DeviceWithRefs) -> Self {
         let type_ = d.get_type();
+        let is_supported = d.mud_data.as_ref().map_or(true, |m| m.is_supported);
         DeviceDto {
             id: d.id,
             ipv4_addr: d.ipv4_addr.map(|ip| ip.to_string()),
@@ -46,8 +51,10 @@ impl From<DeviceWithRefs> for DeviceDto {
             mud_url: d.inner.mud_url,
             last_interaction: d.inner.last_interaction,
             mud_data: d.mud_data,
+            is_supported,
             clipart: d.inner.clipart,
             room: d.room,
+            acl_override: d.inner.acl_override,
             type_,
         }
     }
@@ -91,6 +98,7 @@ impl DeviceCreationUpdateDto {
             last_interaction: Utc::now().naive_utc(),
             clipart: self.clipart.clone(),
             room_id: self.room_id,
+            acl_override: Vec::new(),
         })
     }
 
@@ -2,9 +2,11 @@
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
 mod config_dto;
+mod controller_mapping_dto;
 mod device_dto;
 mod enforcer_dto;
 mod mud_dto;
+mod policy_exception_dto;
 mod role_assign_dto;
 mod room_dto;
 mod status_dto;
@@ -14,9 +16,11 @@ mod users_dto;
 mod users_management_dto;
 
 pub use config_dto::*;
+pub use controller_mapping_dto::*;
 pub use device_dto::*;
 pub use enforcer_dto::*;
 pub use mud_dto::*;
+pub use policy_exception_dto::*;
 pub use role_assign_dto::*;
 pub use room_dto::*;
 pub use status_dto::*;
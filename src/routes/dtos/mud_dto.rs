@@ -1,9 +1,16 @@
 // Copyright 2020-2021, Benjamin Ludewig, Florian Bonetti, Jeffrey Munstermann, Luca Nittscher, Hugo Damer, Michael Bach
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
+use chrono::NaiveDateTime;
 use paperclip::actix::Apiv2Schema;
 
-use crate::models::Acl;
+use crate::{
+    models::{Ace, AcePatch, AcePatchOperation, Acl, MudData},
+    services::mud_service::{
+        ace_patch::AcePatchConflict, authority::MudAuthorityPolicy, repository::MudRepositoryEntry,
+        source::MudSourceKind,
+    },
+};
 
 #[derive(Deserialize, Apiv2Schema)]
 pub struct MudQueryDto {
@@ -15,13 +22,102 @@ pub struct MudUpdateQueryDto {
     pub mud_url: String,
 }
 
+#[derive(Deserialize, Apiv2Schema)]
+pub struct MudValidationQueryDto {
+    /// The MUD-URL the MUD-File is expected to be served from.
+    pub mud_url: Option<String>,
+}
+
+#[derive(Deserialize, Apiv2Schema)]
+pub struct MudExportQueryDto {
+    pub mud_url: String,
+    /// Whether to replace the ACLs of the MUD-File with the overrides of the same name.
+    #[serde(default)]
+    pub merge_overrides: bool,
+}
+
 #[derive(Debug, Serialize, Deserialize, Apiv2Schema)]
 pub struct MudCreationDto {
     pub mud_url: String,
     pub acl_override: Option<Vec<Acl>>,
+    /// The MUD-URL of the profile a custom MUD-Profile inherits its ACLs from.
+    pub parent_url: Option<String>,
+    /// The changes to the inherited ACEs, requires a `parent_url`. Fetched MUD-Profiles can be patched once created.
+    pub ace_patches: Option<Vec<AcePatch>>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Apiv2Schema)]
 pub struct MudUpdateDto {
     pub acl_override: Option<Vec<Acl>>,
+    /// Replaces the changes to the ACEs of the MUD-Profile or its parent if set.
+    pub ace_patches: Option<Vec<AcePatch>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Apiv2Schema)]
+pub struct MudAcePatchDto {
+    pub acl_name: String,
+    pub ace_name: String,
+    pub operation: AcePatchOperation,
+    /// The new ACE for `Add` and `Replace` operations, its name is set to `ace_name`.
+    pub ace: Option<Ace>,
+}
+
+#[derive(Deserialize, Apiv2Schema)]
+pub struct MudAceQueryDto {
+    pub mud_url: String,
+    pub acl_name: String,
+    pub ace_name: String,
+}
+
+#[derive(Debug, Serialize, Apiv2Schema)]
+pub struct MudEffectiveDto {
+    pub mud_data: MudData,
+    /// The ACLs that apply to the devices using the MUD-Profile, i.e. its ACLs merged with its overrides.
+    pub acls: Vec<Acl>,
+    /// The ACE patches that don't apply cleanly, because the upstream ACEs changed since they were made.
+    pub conflicts: Vec<AcePatchConflict>,
+}
+
+#[derive(Debug, Serialize, Apiv2Schema)]
+pub struct MudRevisionDto {
+    pub id: i64,
+    pub created_at: NaiveDateTime,
+    /// Whether the MUD-Profile is pinned to this revision.
+    pub pinned: bool,
+    pub mud_data: MudData,
+}
+
+#[derive(Deserialize, Apiv2Schema)]
+pub struct MudRevisionDiffQueryDto {
+    pub mud_url: String,
+    pub from: i64,
+    pub to: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Apiv2Schema)]
+pub struct MudPinDto {
+    pub revision_id: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Apiv2Schema)]
+pub struct MudAuthorityDto {
+    /// The host of the MUD-URLs, the policy also applies to its subdomains.
+    pub authority: String,
+    pub policy: MudAuthorityPolicy,
+}
+
+#[derive(Deserialize, Apiv2Schema)]
+pub struct MudAuthorityQueryDto {
+    pub authority: String,
+}
+
+#[derive(Debug, Serialize, Apiv2Schema)]
+pub struct MudRepositoryStatusDto {
+    /// The directory of the MUD repository, `None` if no repository is configured.
+    pub directory: Option<String>,
+    /// The configured MUD sources, in the order they are asked for MUD-Files.
+    pub sources: Vec<MudSourceKind>,
+    pub scanned_at: Option<NaiveDateTime>,
+    /// The MUD-Files in the repository, i.e. the MUD-URLs that are resolved locally.
+    pub entries: Vec<MudRepositoryEntry>,
 }
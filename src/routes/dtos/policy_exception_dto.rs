@@ -0,0 +1,36 @@
+// Copyright 2020-2021, Benjamin Ludewig, Florian Bonetti, Jeffrey Munstermann, Luca Nittscher, Hugo Damer, Michael Bach
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use chrono::{NaiveDateTime, Utc};
+use paperclip::actix::Apiv2Schema;
+
+use crate::models::{Acl, PolicyException};
+
+#[derive(Validate, Debug, Serialize, Deserialize, Apiv2Schema)]
+pub struct PolicyExceptionCreationDto {
+    pub device_id: Option<i64>,
+    pub room_id: Option<i64>,
+    pub mud_url: Option<String>,
+    #[validate(length(min = 1))]
+    pub acls: Vec<Acl>,
+    #[validate(length(min = 1))]
+    pub reason: String,
+    pub expires_at: NaiveDateTime,
+}
+
+impl PolicyExceptionCreationDto {
+    pub fn into_policy_exception(self, created_by: String) -> PolicyException {
+        PolicyException {
+            id: 0,
+            device_id: self.device_id,
+            room_id: self.room_id,
+            mud_url: self.mud_url,
+            acls: self.acls,
+            reason: self.reason,
+            created_by,
+            created_at: Utc::now().naive_utc(),
+            expires_at: self.expires_at,
+            retired_at: None,
+        }
+    }
+}
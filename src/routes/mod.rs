@@ -2,10 +2,12 @@
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
 pub mod config_controller;
+pub mod controller_mapping_controller;
 pub mod device_controller;
 pub mod dtos;
 pub mod enforcer_controller;
 pub mod mud_controller;
+pub mod policy_exception_controller;
 pub mod role_manager_controller;
 pub mod room_controller;
 pub mod status_controller;
@@ -11,13 +11,25 @@ use paperclip::actix::{
 };
 
 use crate::{
+    app_config::APP_CONFIG,
     auth::AuthToken,
     db::DbConnection,
     error,
     error::Result,
-    models::{MudData, MudDbo},
-    routes::dtos::{MudCreationDto, MudQueryDto, MudUpdateDto, MudUpdateQueryDto},
-    services::{firewall_configuration_service, mud_service, mud_service::is_url, role_service::Permission},
+    models::{AcePatch, AcePatchOperation, Acl, MudData, MudDbo},
+    routes::dtos::{
+        MudAcePatchDto, MudAceQueryDto, MudAuthorityDto, MudAuthorityQueryDto, MudCreationDto, MudEffectiveDto,
+        MudExportQueryDto, MudPinDto, MudQueryDto, MudRepositoryStatusDto, MudRevisionDiffQueryDto, MudRevisionDto,
+        MudUpdateDto, MudUpdateQueryDto, MudValidationQueryDto,
+    },
+    services::{
+        firewall_configuration_service, mud_service,
+        mud_service::{
+            ace_patch, ace_patch::AcePatchConflictReason, authority, inheritance, is_url, refresh, repository,
+            revision, revision::MudRevisionDiff, validation::MudDiagnostic,
+        },
+        role_service::Permission,
+    },
 };
 
 pub fn init(cfg: &mut web::ServiceConfig) {
@@ -25,6 +37,21 @@ pub fn init(cfg: &mut web::ServiceConfig) {
     cfg.route("/", web::put().to(update_mud));
     cfg.route("/", web::delete().to(delete_mud));
     cfg.route("/", web::post().to(create_mud));
+    cfg.route("/effective", web::get().to(get_effective_mud));
+    cfg.route("/aces", web::post().to(patch_ace));
+    cfg.route("/aces", web::delete().to(revert_ace));
+    cfg.route("/export", web::get().to(export_mud));
+    cfg.route("/validate", web::post().to(validate_mud));
+    cfg.route("/revisions", web::get().to(get_mud_revisions));
+    cfg.route("/revisions/diff", web::get().to(diff_mud_revisions));
+    cfg.route("/pin", web::put().to(pin_mud_revision));
+    cfg.route("/pin", web::delete().to(unpin_mud_revision));
+    cfg.route("/repository", web::get().to(get_mud_repository_status));
+    cfg.route("/authorities", web::get().to(get_mud_authorities));
+    cfg.route("/authorities", web::put().to(set_mud_authority));
+    cfg.route("/authorities", web::delete().to(delete_mud_authority));
+    cfg.route("/pending", web::get().to(get_pending_muds));
+    cfg.route("/approve", web::post().to(approve_mud));
 }
 
 #[api_v2_operation(summary = "Get all known MUDs or query for a single MUD-Url", tags(MUD))]
@@ -36,26 +63,66 @@ pub async fn get_muds(
     auth.require_permission(Permission::mud__read)?;
 
     if let Some(url) = &query.mud_url {
-        let mud = mud_service::get_or_fetch_mud(&url, &pool).await.or_else(|_| {
+        let mut mud = mud_service::get_or_fetch_mud(&url, &pool).await.or_else(|_| {
             error::ResponseError {
                 status: StatusCode::NOT_FOUND,
                 message: Some("Couldn't find MUD-Profile".to_string()),
             }
             .fail()
         })?;
+        mud.fetch_status = refresh::get_fetch_status(&url, &pool).await?;
         Ok(Json(vec![mud]))
     } else {
         auth.require_permission(Permission::mud__list)?;
+        let mut fetch_status = refresh::get_all_fetch_status(&pool).await?;
         Ok(Json(
             mud_service::get_all_muds(&pool)
                 .await?
                 .iter()
-                .map(MudDbo::parse_data)
+                .map(|mud| {
+                    let mut mud_data = mud.parse_data()?;
+                    mud_data.fetch_status = fetch_status.remove(&mud.url);
+                    Ok(mud_data)
+                })
                 .collect::<Result<_>>()?,
         ))
     }
 }
 
+#[api_v2_operation(summary = "Export a MUD as RFC8520 MUD-File", tags(MUD))]
+pub async fn export_mud(
+    pool: web::Data<DbConnection>,
+    auth: AuthToken,
+    query: web::Query<MudExportQueryDto>,
+) -> Result<HttpResponse> {
+    auth.require_permission(Permission::mud__read)?;
+
+    let mud_dbo = mud_service::get_mud(&query.mud_url, &pool).await.ok_or_else(|| {
+        error::ResponseError {
+            status: StatusCode::NOT_FOUND,
+            message: Some("Couldn't find MUD-Profile".to_string()),
+        }
+        .build()
+    })?;
+    let mud_json = mud_service::export::export_mud(&mud_dbo.parse_data()?, query.merge_overrides);
+
+    Ok(HttpResponse::Ok().content_type("application/mud+json").json(mud_json))
+}
+
+#[api_v2_operation(summary = "Check a MUD-File for problems without storing it", tags(MUD))]
+pub async fn validate_mud(
+    auth: AuthToken,
+    query: web::Query<MudValidationQueryDto>,
+    mud_json: Json<serde_json::Value>,
+) -> Result<Json<Vec<MudDiagnostic>>> {
+    auth.require_permission(Permission::mud__read)?;
+
+    Ok(Json(mud_service::validation::validate_mud(
+        &mud_json,
+        query.mud_url.as_deref(),
+    )))
+}
+
 #[api_v2_operation(summary = "Update the overrides on a MUD", tags(MUD))]
 pub async fn update_mud(
     pool: web::Data<DbConnection>,
@@ -74,8 +141,13 @@ pub async fn update_mud(
     })?;
 
     // update the acl_override in mud_data
+    let mud_update_dto = mud_update_dto.into_inner();
     let mut mud_data = mud_dbo.parse_data()?;
-    mud_data.acl_override = mud_update_dto.into_inner().acl_override.unwrap_or_default();
+    mud_data.acl_override = mud_update_dto.acl_override.unwrap_or_default();
+    if let Some(ace_patches) = mud_update_dto.ace_patches {
+        let upstream = ace_patch::get_upstream_acls(&query.mud_url, &pool).await?;
+        mud_data.ace_patches = merge_ace_patches(ace_patches, &upstream)?;
+    }
 
     // use the new mud_data in the existing mud_dbo
     mud_dbo.data = serde_json::to_string(&mud_data)?;
@@ -84,7 +156,82 @@ pub async fn update_mud(
 
     firewall_configuration_service::update_config_version(&pool).await?;
 
-    Ok(Json(mud_data))
+    Ok(Json(ace_patch::apply_patches(mud_data, &pool).await?))
+}
+
+#[api_v2_operation(summary = "Add, replace or remove a single ACE of a MUD", tags(MUD))]
+pub async fn patch_ace(
+    pool: web::Data<DbConnection>,
+    auth: AuthToken,
+    query: web::Query<MudUpdateQueryDto>,
+    ace_patch_dto: Json<MudAcePatchDto>,
+) -> Result<Json<MudData>> {
+    auth.require_permission(Permission::mud__write)?;
+
+    let mut mud_dbo = mud_service::get_mud(&query.mud_url, &pool).await.ok_or_else(|| {
+        error::ResponseError {
+            status: StatusCode::NOT_FOUND,
+            message: Some("Couldn't find MUD-Profile".to_string()),
+        }
+        .build()
+    })?;
+
+    let ace_patch_dto = ace_patch_dto.into_inner();
+    let mut mud_data = mud_dbo.parse_data()?;
+    let upstream = ace_patch::get_upstream_acls(&query.mud_url, &pool).await?;
+    merge_ace_patch(
+        &mut mud_data.ace_patches,
+        &upstream,
+        AcePatch {
+            acl_name: ace_patch_dto.acl_name,
+            ace_name: ace_patch_dto.ace_name,
+            operation: ace_patch_dto.operation,
+            ace: ace_patch_dto.ace,
+            base: None,
+        },
+    )?;
+
+    mud_dbo.data = serde_json::to_string(&mud_data)?;
+
+    mud_service::upsert_mud(&mud_dbo, &pool).await?;
+
+    firewall_configuration_service::update_config_version(&pool).await?;
+
+    Ok(Json(ace_patch::apply_patches(mud_data, &pool).await?))
+}
+
+#[api_v2_operation(summary = "Revert a single ACE of a MUD to the upstream one", tags(MUD))]
+pub async fn revert_ace(
+    pool: web::Data<DbConnection>,
+    auth: AuthToken,
+    query: web::Query<MudAceQueryDto>,
+) -> Result<Json<MudData>> {
+    auth.require_permission(Permission::mud__write)?;
+
+    let mut mud_dbo = mud_service::get_mud(&query.mud_url, &pool).await.ok_or_else(|| {
+        error::ResponseError {
+            status: StatusCode::NOT_FOUND,
+            message: Some("Couldn't find MUD-Profile".to_string()),
+        }
+        .build()
+    })?;
+
+    let mut mud_data = mud_dbo.parse_data()?;
+    if !ace_patch::revert_ace_patch(&mut mud_data.ace_patches, &query.acl_name, &query.ace_name) {
+        error::ResponseError {
+            status: StatusCode::NOT_FOUND,
+            message: Some("The ACE isn't patched".to_string()),
+        }
+        .fail()?;
+    }
+
+    mud_dbo.data = serde_json::to_string(&mud_data)?;
+
+    mud_service::upsert_mud(&mud_dbo, &pool).await?;
+
+    firewall_configuration_service::update_config_version(&pool).await?;
+
+    Ok(Json(ace_patch::apply_patches(mud_data, &pool).await?))
 }
 
 #[api_v2_operation(summary = "Delete a MUD", tags(MUD))]
@@ -105,7 +252,7 @@ pub async fn delete_mud(
         .fail()?;
     }
 
-    if mud_service::is_mud_used(&url, &pool).await? {
+    if mud_service::is_mud_used(&url, &pool).await? || !inheritance::get_children(&url, &pool).await?.is_empty() {
         error::ResponseError {
             status: StatusCode::CONFLICT,
             message: Some("MUD is being used elsewhere, can't delete it.".to_string()),
@@ -137,23 +284,372 @@ pub async fn create_mud(
 
     // Check if the mud_url is actually an url. It might be a custom user mud-profile
     if is_url(&mud_creation_dto.mud_url) {
+        if mud_creation_dto.parent_url.is_some() {
+            error::ResponseError {
+                status: StatusCode::BAD_REQUEST,
+                message: Some("Only custom MUD-Profiles can inherit from a parent".to_string()),
+            }
+            .fail()?;
+        }
+        if mud_creation_dto.ace_patches.is_some() {
+            error::ResponseError {
+                status: StatusCode::BAD_REQUEST,
+                message: Some("The ACEs of a fetched MUD-Profile can only be patched once it is created".to_string()),
+            }
+            .fail()?;
+        }
         let created_mud = mud_service::get_or_fetch_mud(&mud_creation_dto.mud_url, &pool).await?;
 
         Ok(Json(created_mud))
     } else {
-        let empty_mud = mud_service::generate_empty_custom_mud_profile(
+        let mut empty_mud = mud_service::generate_empty_custom_mud_profile(
             &mud_creation_dto.mud_url,
             mud_creation_dto.acl_override.unwrap_or_default(),
         );
+        match (mud_creation_dto.parent_url, mud_creation_dto.ace_patches) {
+            (Some(parent_url), ace_patches) => {
+                empty_mud.parent_url = Some(parent_url);
+                let upstream = get_inheritable_parent_acls(&empty_mud, &pool).await?;
+                empty_mud.ace_patches = merge_ace_patches(ace_patches.unwrap_or_default(), &upstream)?;
+            },
+            (None, Some(_)) => error::ResponseError {
+                status: StatusCode::BAD_REQUEST,
+                message: Some("Patching ACEs requires a parent".to_string()),
+            }
+            .fail()?,
+            (None, None) => {},
+        }
         let mud_dbo = MudDbo {
             url: mud_creation_dto.mud_url,
             data: serde_json::to_string(&empty_mud)?,
             created_at: Utc::now().naive_utc(),
             expiration: empty_mud.expiration.naive_utc(),
+            signature_status: None,
+            etag: None,
+            last_modified: None,
+            pinned_revision_id: None,
+            source: None,
+            approved_at: Some(Utc::now().naive_utc()),
         };
 
         mud_service::create_mud(&mud_dbo, &pool).await?;
 
-        Ok(Json(empty_mud))
+        Ok(Json(ace_patch::apply_patches(empty_mud, &pool).await?))
+    }
+}
+
+/// Returns the ACLs a custom MUD-Profile inherits from its parent, checking that the parent can be inherited from.
+async fn get_inheritable_parent_acls(mud_data: &MudData, pool: &DbConnection) -> Result<Vec<Acl>> {
+    let parent = inheritance::get_parent(mud_data, pool).await.or_else(|_| {
+        error::ResponseError {
+            status: StatusCode::BAD_REQUEST,
+            message: Some("Couldn't find parent MUD-Profile".to_string()),
+        }
+        .fail()
+    })?;
+    match parent {
+        Some(parent) if parent.parent_url.is_some() => error::ResponseError {
+            status: StatusCode::BAD_REQUEST,
+            message: Some("The parent MUD-Profile inherits from another MUD-Profile itself".to_string()),
+        }
+        .fail(),
+        Some(parent) => Ok(inheritance::inheritable_acls(&parent)),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// Merges the ACE patches in order, so there is at most one patch per ACE.
+fn merge_ace_patches(ace_patches: Vec<AcePatch>, upstream: &[Acl]) -> Result<Vec<AcePatch>> {
+    let mut merged = Vec::new();
+    for patch in ace_patches {
+        merge_ace_patch(&mut merged, upstream, patch)?;
+    }
+    Ok(merged)
+}
+
+/// Merges the ACE patch into the patches, refusing it if it doesn't apply cleanly to the upstream ACLs.
+fn merge_ace_patch(ace_patches: &mut Vec<AcePatch>, upstream: &[Acl], patch: AcePatch) -> Result<()> {
+    if patch.operation != AcePatchOperation::Remove && patch.ace.is_none() {
+        error::ResponseError {
+            status: StatusCode::BAD_REQUEST,
+            message: Some("Adding or replacing an ACE requires the new ACE".to_string()),
+        }
+        .fail()?;
     }
+    let (acl_name, ace_name) = (patch.acl_name.clone(), patch.ace_name.clone());
+    if let Err(reason) = ace_patch::merge_ace_patch(ace_patches, upstream, patch) {
+        let message = match reason {
+            AcePatchConflictReason::AclMissing => format!("ACL {} doesn't exist upstream", acl_name),
+            AcePatchConflictReason::AceMissing => {
+                format!("ACE {} of ACL {} doesn't exist upstream", ace_name, acl_name)
+            },
+            AcePatchConflictReason::AceExists => {
+                format!(
+                    "ACE {} of ACL {} exists upstream, replace it instead",
+                    ace_name, acl_name
+                )
+            },
+            AcePatchConflictReason::AceChanged => format!("ACE {} of ACL {} changed upstream", ace_name, acl_name),
+        };
+        error::ResponseError {
+            status: StatusCode::CONFLICT,
+            message: Some(message),
+        }
+        .fail()?;
+    }
+    Ok(())
+}
+
+#[api_v2_operation(summary = "Get the ACLs of a MUD as they apply to devices", tags(MUD))]
+pub async fn get_effective_mud(
+    pool: web::Data<DbConnection>,
+    auth: AuthToken,
+    query: web::Query<MudUpdateQueryDto>,
+) -> Result<Json<MudEffectiveDto>> {
+    auth.require_permission(Permission::mud__read)?;
+
+    let mud_data = mud_service::get_or_fetch_mud(&query.mud_url, &pool)
+        .await
+        .or_else(|_| {
+            error::ResponseError {
+                status: StatusCode::NOT_FOUND,
+                message: Some("Couldn't find MUD-Profile".to_string()),
+            }
+            .fail()
+        })?;
+    let conflicts = ace_patch::get_conflicts(&query.mud_url, &pool).await?;
+
+    Ok(Json(MudEffectiveDto {
+        acls: firewall_configuration_service::merge_acls(&mud_data.acllist, &mud_data.acl_override)
+            .into_iter()
+            .cloned()
+            .collect(),
+        conflicts,
+        mud_data,
+    }))
+}
+
+#[api_v2_operation(summary = "Get all revisions of a MUD", tags(MUD))]
+pub async fn get_mud_revisions(
+    pool: web::Data<DbConnection>,
+    auth: AuthToken,
+    query: web::Query<MudUpdateQueryDto>,
+) -> Result<Json<Vec<MudRevisionDto>>> {
+    auth.require_permission(Permission::mud__read)?;
+
+    let mud_dbo = mud_service::get_mud(&query.mud_url, &pool).await.ok_or_else(|| {
+        error::ResponseError {
+            status: StatusCode::NOT_FOUND,
+            message: Some("Couldn't find MUD-Profile".to_string()),
+        }
+        .build()
+    })?;
+
+    Ok(Json(
+        revision::get_revisions(&mud_dbo.url, &pool)
+            .await?
+            .into_iter()
+            .map(|r| {
+                Ok(MudRevisionDto {
+                    id: r.id,
+                    created_at: r.created_at,
+                    pinned: mud_dbo.pinned_revision_id == Some(r.id),
+                    mud_data: r.parse_data()?,
+                })
+            })
+            .collect::<Result<_>>()?,
+    ))
+}
+
+#[api_v2_operation(summary = "Compare the ACLs of two revisions of a MUD", tags(MUD))]
+pub async fn diff_mud_revisions(
+    pool: web::Data<DbConnection>,
+    auth: AuthToken,
+    query: web::Query<MudRevisionDiffQueryDto>,
+) -> Result<Json<MudRevisionDiff>> {
+    auth.require_permission(Permission::mud__read)?;
+
+    let not_found = || {
+        error::ResponseError {
+            status: StatusCode::NOT_FOUND,
+            message: Some("Couldn't find MUD revision".to_string()),
+        }
+        .build()
+    };
+    // both revisions have to belong to the MUD, so revisions of other MUDs can't be read through it
+    let from = revision::get_revision(query.from, &pool)
+        .await?
+        .filter(|r| r.url == query.mud_url)
+        .ok_or_else(not_found)?;
+    let to = revision::get_revision(query.to, &pool)
+        .await?
+        .filter(|r| r.url == query.mud_url)
+        .ok_or_else(not_found)?;
+
+    Ok(Json(revision::diff_revisions(&from, &to)?))
+}
+
+#[api_v2_operation(summary = "Pin a MUD to one of its revisions", tags(MUD))]
+pub async fn pin_mud_revision(
+    pool: web::Data<DbConnection>,
+    auth: AuthToken,
+    query: web::Query<MudUpdateQueryDto>,
+    mud_pin_dto: Json<MudPinDto>,
+) -> Result<Json<MudData>> {
+    auth.require_permission(Permission::mud__write)?;
+
+    let revision = revision::get_revision(mud_pin_dto.revision_id, &pool).await?;
+    if revision.map_or(true, |r| r.url != query.mud_url) {
+        error::ResponseError {
+            status: StatusCode::NOT_FOUND,
+            message: Some("Couldn't find MUD revision".to_string()),
+        }
+        .fail()?;
+    }
+
+    revision::pin_revision(&query.mud_url, Some(mud_pin_dto.revision_id), &pool).await?;
+
+    firewall_configuration_service::update_config_version(&pool).await?;
+
+    Ok(Json(mud_service::get_or_fetch_mud(&query.mud_url, &pool).await?))
+}
+
+#[api_v2_operation(summary = "Unpin a MUD, using its latest revision again", tags(MUD))]
+pub async fn unpin_mud_revision(
+    pool: web::Data<DbConnection>,
+    auth: AuthToken,
+    query: web::Query<MudUpdateQueryDto>,
+) -> Result<HttpResponse> {
+    auth.require_permission(Permission::mud__write)?;
+
+    if !revision::pin_revision(&query.mud_url, None, &pool).await? {
+        error::ResponseError {
+            status: StatusCode::NOT_FOUND,
+            message: Some("No MUD-Profile with this URL".to_string()),
+        }
+        .fail()?;
+    }
+
+    firewall_configuration_service::update_config_version(&pool).await?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[api_v2_operation(
+    summary = "Get the MUD-URLs that are resolved by the local MUD repository",
+    tags(MUD)
+)]
+pub async fn get_mud_repository_status(auth: AuthToken) -> Result<Json<MudRepositoryStatusDto>> {
+    auth.require_permission(Permission::mud__list)?;
+
+    let index = repository::get_repository_index();
+
+    Ok(Json(MudRepositoryStatusDto {
+        directory: APP_CONFIG.mud_repository.clone(),
+        sources: APP_CONFIG.mud_sources.clone(),
+        scanned_at: index.scanned_at,
+        entries: index.entries.into_iter().map(|(_, entry)| entry).collect(),
+    }))
+}
+
+#[api_v2_operation(summary = "Get the policies set for MUD-URL authorities", tags(MUD))]
+pub async fn get_mud_authorities(pool: web::Data<DbConnection>, auth: AuthToken) -> Result<Json<Vec<MudAuthorityDto>>> {
+    auth.require_permission(Permission::mud__list)?;
+
+    Ok(Json(
+        authority::get_authorities(&pool)
+            .await?
+            .into_iter()
+            .filter_map(|a| {
+                Some(MudAuthorityDto {
+                    policy: a.policy.parse().ok()?,
+                    authority: a.authority,
+                })
+            })
+            .collect(),
+    ))
+}
+
+#[api_v2_operation(summary = "Set the policy for the MUD-URLs of an authority", tags(MUD))]
+pub async fn set_mud_authority(
+    pool: web::Data<DbConnection>,
+    auth: AuthToken,
+    mud_authority_dto: Json<MudAuthorityDto>,
+) -> Result<Json<MudAuthorityDto>> {
+    auth.require_permission(Permission::mud__write)?;
+
+    if mud_authority_dto.authority.is_empty() {
+        error::ResponseError {
+            status: StatusCode::BAD_REQUEST,
+            message: Some("Authority must not be empty".to_string()),
+        }
+        .fail()?;
+    }
+
+    authority::set_authority_policy(&mud_authority_dto.authority, mud_authority_dto.policy, &pool).await?;
+
+    firewall_configuration_service::update_config_version(&pool).await?;
+
+    Ok(Json(MudAuthorityDto {
+        authority: mud_authority_dto.authority.to_lowercase(),
+        policy: mud_authority_dto.policy,
+    }))
+}
+
+#[api_v2_operation(summary = "Remove the policy for the MUD-URLs of an authority", tags(MUD))]
+pub async fn delete_mud_authority(
+    pool: web::Data<DbConnection>,
+    auth: AuthToken,
+    query: web::Query<MudAuthorityQueryDto>,
+) -> Result<HttpResponse> {
+    auth.require_permission(Permission::mud__write)?;
+
+    if !authority::delete_authority_policy(&query.authority, &pool).await? {
+        error::ResponseError {
+            status: StatusCode::NOT_FOUND,
+            message: Some("No policy for this authority".to_string()),
+        }
+        .fail()?;
+    }
+
+    firewall_configuration_service::update_config_version(&pool).await?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+#[api_v2_operation(
+    summary = "Get the MUDs of untrusted authorities that are pending approval",
+    tags(MUD)
+)]
+pub async fn get_pending_muds(pool: web::Data<DbConnection>, auth: AuthToken) -> Result<Json<Vec<MudData>>> {
+    auth.require_permission(Permission::mud__list)?;
+
+    Ok(Json(
+        authority::get_pending_muds(&pool)
+            .await?
+            .iter()
+            .map(MudDbo::parse_data)
+            .collect::<Result<_>>()?,
+    ))
+}
+
+#[api_v2_operation(summary = "Approve a MUD of an untrusted authority, so its ACLs apply", tags(MUD))]
+pub async fn approve_mud(
+    pool: web::Data<DbConnection>,
+    auth: AuthToken,
+    query: web::Query<MudUpdateQueryDto>,
+) -> Result<Json<MudData>> {
+    auth.require_permission(Permission::mud__write)?;
+
+    if !authority::approve_mud(&query.mud_url, &pool).await? {
+        error::ResponseError {
+            status: StatusCode::NOT_FOUND,
+            message: Some("No MUD-Profile with this URL".to_string()),
+        }
+        .fail()?;
+    }
+
+    firewall_configuration_service::update_config_version(&pool).await?;
+
+    Ok(Json(mud_service::get_or_fetch_mud(&query.mud_url, &pool).await?))
 }
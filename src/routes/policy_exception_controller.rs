@@ -0,0 +1,162 @@
+// Copyright 2020-2021, Benjamin Ludewig, Florian Bonetti, Jeffrey Munstermann, Luca Nittscher, Hugo Damer, Michael Bach
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+#![allow(clippy::needless_pass_by_value)]
+
+use actix_web::http::StatusCode;
+use chrono::Utc;
+use paperclip::actix::{
+    api_v2_operation, web,
+    web::{HttpResponse, Json},
+};
+use snafu::ensure;
+use validator::Validate;
+
+use crate::{
+    auth::AuthToken,
+    db::DbConnection,
+    error,
+    error::Result,
+    models::PolicyException,
+    routes::dtos::PolicyExceptionCreationDto,
+    services::{device_service, mud_service, policy_exception_service, role_service::Permission, room_service},
+};
+
+pub fn init(cfg: &mut web::ServiceConfig) {
+    cfg.route("", web::get().to(get_all_exceptions));
+    cfg.route("/{id}", web::get().to(get_exception));
+    cfg.route("", web::post().to(create_exception));
+    cfg.route("/{id}", web::delete().to(delete_exception));
+}
+
+#[api_v2_operation(summary = "List all policy exceptions, including the retired ones", tags(Exceptions))]
+async fn get_all_exceptions(pool: web::Data<DbConnection>, auth: AuthToken) -> Result<Json<Vec<PolicyException>>> {
+    auth.require_permission(Permission::exception__list)?;
+    auth.require_permission(Permission::exception__read)?;
+
+    let exceptions = policy_exception_service::get_all_exceptions(&pool).await?;
+    Ok(Json(exceptions))
+}
+
+#[api_v2_operation(summary = "Get a policy exception by id", tags(Exceptions))]
+async fn get_exception(
+    pool: web::Data<DbConnection>,
+    auth: AuthToken,
+    id: web::Path<i64>,
+) -> Result<Json<PolicyException>> {
+    auth.require_permission(Permission::exception__read)?;
+
+    let exception = policy_exception_service::find_by_id(id.into_inner(), &pool)
+        .await
+        .or_else(|_| {
+            error::ResponseError {
+                status: StatusCode::NOT_FOUND,
+                message: Some("No policy exception with this Id found".to_string()),
+            }
+            .fail()
+        })?;
+
+    Ok(Json(exception))
+}
+
+#[api_v2_operation(
+    summary = "Create a policy exception for a device, room or MUD-Profile, which is retired when it expires",
+    tags(Exceptions)
+)]
+async fn create_exception(
+    pool: web::Data<DbConnection>,
+    auth: AuthToken,
+    exception_creation_dto: Json<PolicyExceptionCreationDto>,
+) -> Result<Json<PolicyException>> {
+    auth.require_permission(Permission::exception__write)?;
+
+    validate_exception(&exception_creation_dto, &pool).await?;
+
+    let exception = exception_creation_dto
+        .into_inner()
+        .into_policy_exception(auth.username.clone());
+    let id = policy_exception_service::insert_exception(&exception, &pool).await?;
+
+    let created_exception = policy_exception_service::find_by_id(id, &pool).await?;
+    Ok(Json(created_exception))
+}
+
+#[api_v2_operation(summary = "Retire a policy exception before it expires", tags(Exceptions))]
+async fn delete_exception(pool: web::Data<DbConnection>, auth: AuthToken, id: web::Path<i64>) -> Result<HttpResponse> {
+    auth.require_permission(Permission::exception__delete)?;
+
+    let deleted = policy_exception_service::delete_exception(id.into_inner(), &pool).await?;
+
+    ensure!(
+        deleted,
+        error::ResponseError {
+            status: StatusCode::NOT_FOUND,
+            message: None,
+        }
+    );
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Checks that a policy exception is well formed, expires in the future and is attached to exactly one existing
+/// device, room or MUD-Profile.
+async fn validate_exception(exception_creation_dto: &PolicyExceptionCreationDto, pool: &DbConnection) -> Result<()> {
+    exception_creation_dto.validate().or_else(|_| {
+        error::ResponseError {
+            status: StatusCode::BAD_REQUEST,
+            message: None,
+        }
+        .fail()
+    })?;
+
+    ensure!(
+        exception_creation_dto.expires_at > Utc::now().naive_utc(),
+        error::ResponseError {
+            status: StatusCode::BAD_REQUEST,
+            message: Some("The exception has to expire in the future.".to_string()),
+        }
+    );
+
+    let targets = [
+        exception_creation_dto.device_id.is_some(),
+        exception_creation_dto.room_id.is_some(),
+        exception_creation_dto.mud_url.is_some(),
+    ];
+    ensure!(
+        targets.iter().filter(|target| **target).count() == 1,
+        error::ResponseError {
+            status: StatusCode::BAD_REQUEST,
+            message: Some("Either a device, a room or a MUD-Profile has to be specified.".to_string()),
+        }
+    );
+
+    if let Some(device_id) = exception_creation_dto.device_id {
+        device_service::find_by_id(device_id, pool).await.or_else(|_| {
+            error::ResponseError {
+                status: StatusCode::BAD_REQUEST,
+                message: Some("Device can not be found.".to_string()),
+            }
+            .fail()
+        })?;
+    }
+    if let Some(room_id) = exception_creation_dto.room_id {
+        room_service::find_by_id(room_id, pool).await.or_else(|_| {
+            error::ResponseError {
+                status: StatusCode::BAD_REQUEST,
+                message: Some("Room can not be found.".to_string()),
+            }
+            .fail()
+        })?;
+    }
+    if let Some(mud_url) = &exception_creation_dto.mud_url {
+        ensure!(
+            mud_service::get_mud(mud_url, pool).await.is_some(),
+            error::ResponseError {
+                status: StatusCode::BAD_REQUEST,
+                message: Some("MUD-Profile can not be found.".to_string()),
+            }
+        );
+    }
+
+    Ok(())
+}
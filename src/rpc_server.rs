@@ -26,7 +26,10 @@ use crate::{
     app_config::APP_CONFIG,
     db::DbConnection,
     error::Result,
-    services::{acme_service::CertId, device_service, enforcer_service, firewall_configuration_service, log_service},
+    services::{
+        acme_service::CertId, controller_mapping_service, device_service, enforcer_service,
+        firewall_configuration_service, log_service, mud_service, policy_exception_service,
+    },
     util::open_file_with,
 };
 
@@ -56,8 +59,23 @@ impl NamibRpc for NamibRpcServer {
                 .try_collect()
                 .await
                 .unwrap_or_default();
-            let new_config =
-                firewall_configuration_service::create_configuration(current_config_version, &init_devices);
+            let controllers = controller_mapping_service::get_all_controller_mappings(&self.db_connection)
+                .await
+                .unwrap_or_default();
+            let signature_policy = mud_service::signature::get_signature_policy(&self.db_connection).await;
+            let unsupported_device_policy =
+                firewall_configuration_service::get_unsupported_device_policy(&self.db_connection).await;
+            let exceptions = policy_exception_service::get_active_exceptions(&self.db_connection)
+                .await
+                .unwrap_or_default();
+            let new_config = firewall_configuration_service::create_configuration(
+                current_config_version,
+                &init_devices,
+                &controllers,
+                signature_policy,
+                unsupported_device_policy,
+                &exceptions,
+            );
             debug!("Returning Heartbeat to client with config: {:?}", new_config.version());
             return Some(new_config);
         }
@@ -10,6 +10,17 @@ pub enum ConfigKeys {
     CollectDeviceData,
     AllowUserSignup,
     FirewallConfigVersion,
+    MudSignaturePolicy,
+    UnsupportedDevicePolicy,
+    DefaultMudAuthorityPolicy,
+}
+
+/// Checks whether the key configures a policy that decides which firewall rules the devices get, so the enforcers have to
+/// fetch a new firewall configuration when it changes.
+pub fn is_firewall_policy(key: &str) -> bool {
+    key == ConfigKeys::MudSignaturePolicy.as_ref()
+        || key == ConfigKeys::UnsupportedDevicePolicy.as_ref()
+        || key == ConfigKeys::DefaultMudAuthorityPolicy.as_ref()
 }
 
 /// Gets the config value by key from the database.
@@ -0,0 +1,79 @@
+// Copyright 2020-2021, Benjamin Ludewig, Florian Bonetti, Jeffrey Munstermann, Luca Nittscher, Hugo Damer, Michael Bach
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use crate::{db::DbConnection, error::Result, models::ControllerMapping, services::firewall_configuration_service};
+
+///returns all controller mappings from the database
+pub async fn get_all_controller_mappings(pool: &DbConnection) -> Result<Vec<ControllerMapping>> {
+    let mappings = sqlx::query_as!(ControllerMapping, "SELECT * FROM controller_mappings")
+        .fetch_all(pool)
+        .await?;
+
+    Ok(mappings)
+}
+
+///returns controller mapping by id from the database
+pub async fn find_by_id(id: i64, pool: &DbConnection) -> Result<ControllerMapping> {
+    let mapping = sqlx::query_as!(ControllerMapping, "SELECT * FROM controller_mappings WHERE id = $1", id)
+        .fetch_one(pool)
+        .await?;
+
+    Ok(mapping)
+}
+
+///Creates a new controller mapping in the database
+pub async fn insert_controller_mapping(mapping: &ControllerMapping, pool: &DbConnection) -> Result<i64> {
+    #[cfg(not(feature = "postgres"))]
+    let result = sqlx::query!(
+        "INSERT INTO controller_mappings (uri, device_id, host) VALUES ($1, $2, $3)",
+        mapping.uri,
+        mapping.device_id,
+        mapping.host,
+    )
+    .execute(pool)
+    .await?
+    .last_insert_rowid();
+
+    #[cfg(feature = "postgres")]
+    let result = sqlx::query!(
+        "INSERT INTO controller_mappings (uri, device_id, host) VALUES ($1, $2, $3) RETURNING id",
+        mapping.uri,
+        mapping.device_id,
+        mapping.host,
+    )
+    .fetch_one(pool)
+    .await?
+    .id;
+
+    firewall_configuration_service::update_config_version(pool).await?;
+
+    Ok(result)
+}
+
+///updates a controller mapping in the database
+pub async fn update_controller_mapping(mapping: &ControllerMapping, pool: &DbConnection) -> Result<bool> {
+    let upd_count = sqlx::query!(
+        "UPDATE controller_mappings SET uri = $1, device_id = $2, host = $3 WHERE id = $4",
+        mapping.uri,
+        mapping.device_id,
+        mapping.host,
+        mapping.id
+    )
+    .execute(pool)
+    .await?;
+
+    firewall_configuration_service::update_config_version(pool).await?;
+
+    Ok(upd_count.rows_affected() == 1)
+}
+
+///Deletes a controller mapping with a given id from the database
+pub async fn delete_controller_mapping(id: i64, pool: &DbConnection) -> Result<bool> {
+    let del_count = sqlx::query!("DELETE FROM controller_mappings WHERE id = $1", id)
+        .execute(pool)
+        .await?;
+
+    firewall_configuration_service::update_config_version(pool).await?;
+
+    Ok(del_count.rows_affected() == 1)
+}
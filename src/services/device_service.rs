@@ -8,13 +8,26 @@ use namib_shared::{macaddr::SerdeMacAddr, models::DhcpLeaseInformation};
 use crate::{
     db::DbConnection,
     error::Result,
-    models::{Device, DeviceDbo, DeviceWithRefs},
-    services::{config_service, config_service::ConfigKeys, firewall_configuration_service, neo4things_service},
+    models::{Acl, Device, DeviceDbo, DeviceWithRefs},
+    services::{
+        config_service,
+        config_service::ConfigKeys,
+        firewall_configuration_service,
+        mud_service::authority::{get_authority_policy, MudAuthorityPolicy},
+        neo4things_service,
+    },
 };
 
 pub async fn upsert_device_from_dhcp_lease(lease_info: DhcpLeaseInformation, pool: &DbConnection) -> Result<()> {
     debug!("dhcp request device mud file: {:?}", lease_info.mud_url);
 
+    // the MUD-URL is kept, so the device stays on the default rules instead of becoming unrestricted
+    if let Some(mud_url) = &lease_info.mud_url {
+        if get_authority_policy(mud_url, pool).await == MudAuthorityPolicy::Blocked {
+            warn!("Not fetching MUD-URL {} of blocked authority", mud_url);
+        }
+    }
+
     if let Ok(mut device) =
         find_by_mac_or_duid(lease_info.mac_address, lease_info.duid().map(|d| d.to_string()), pool).await
     {
@@ -112,10 +125,11 @@ pub async fn insert_device(device_data: &DeviceWithRefs, pool: &DbConnection) ->
     let ipv4_addr = device_data.ipv4_addr.map(|ip| ip.to_string());
     let ipv6_addr = device_data.ipv6_addr.map(|ip| ip.to_string());
     let mac_addr = device_data.mac_addr.map(|m| m.to_string());
+    let acl_override = serde_json::to_string(&device_data.acl_override)?;
 
     #[cfg(not(feature = "postgres"))]
     let result = sqlx::query!(
-        "INSERT INTO devices (name, ipv4_addr, ipv6_addr, mac_addr, duid, hostname, vendor_class, mud_url, collect_info, last_interaction, room_id, clipart) values ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)",
+        "INSERT INTO devices (name, ipv4_addr, ipv6_addr, mac_addr, duid, hostname, vendor_class, mud_url, collect_info, last_interaction, room_id, clipart, acl_override) values ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)",
         device_data.name,
         ipv4_addr,
         ipv6_addr,
@@ -128,6 +142,7 @@ pub async fn insert_device(device_data: &DeviceWithRefs, pool: &DbConnection) ->
         device_data.last_interaction,
         device_data.room_id,
         device_data.clipart,
+        acl_override,
     )
     .execute(pool)
     .await?
@@ -135,7 +150,7 @@ pub async fn insert_device(device_data: &DeviceWithRefs, pool: &DbConnection) ->
 
     #[cfg(feature = "postgres")]
     let result = sqlx::query!(
-        "INSERT INTO devices (name, ipv4_addr, ipv6_addr, mac_addr, duid, hostname, vendor_class, mud_url, collect_info, last_interaction, room_id, clipart) values ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12) RETURNING id",
+        "INSERT INTO devices (name, ipv4_addr, ipv6_addr, mac_addr, duid, hostname, vendor_class, mud_url, collect_info, last_interaction, room_id, clipart, acl_override) values ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13) RETURNING id",
         device_data.name,
         ipv4_addr,
         ipv6_addr,
@@ -148,6 +163,7 @@ pub async fn insert_device(device_data: &DeviceWithRefs, pool: &DbConnection) ->
         device_data.last_interaction,
         device_data.room_id,
         device_data.clipart,
+        acl_override,
     )
     .fetch_one(pool)
     .await?
@@ -163,6 +179,8 @@ pub async fn insert_device(device_data: &DeviceWithRefs, pool: &DbConnection) ->
     Ok(result)
 }
 
+/// Updates the device, except for its ACL overrides, see `update_device_overrides`.
+/// This keeps overrides that couldn't be parsed, instead of replacing them with the empty list they were loaded as.
 pub async fn update_device(device_data: &DeviceWithRefs, pool: &DbConnection) -> Result<bool> {
     let ipv4_addr = device_data.ipv4_addr.map(|ip| ip.to_string());
     let ipv6_addr = device_data.ipv6_addr.map(|ip| ip.to_string());
@@ -192,6 +210,19 @@ pub async fn update_device(device_data: &DeviceWithRefs, pool: &DbConnection) ->
     Ok(upd_count.rows_affected() == 1)
 }
 
+/// Replaces the ACL overrides of the device.
+pub async fn update_device_overrides(id: i64, acl_override: &[Acl], pool: &DbConnection) -> Result<bool> {
+    let acl_override = serde_json::to_string(acl_override)?;
+
+    let upd_count = sqlx::query!("UPDATE devices SET acl_override = $1 WHERE id = $2", acl_override, id)
+        .execute(pool)
+        .await?;
+
+    firewall_configuration_service::update_config_version(pool).await?;
+
+    Ok(upd_count.rows_affected() == 1)
+}
+
 pub async fn delete_device(id: i64, pool: &DbConnection) -> Result<bool> {
     let del_count = sqlx::query!("DELETE FROM devices WHERE id = $1", id)
         .execute(pool)
@@ -1,23 +1,75 @@
 // Copyright 2020-2021, Benjamin Ludewig, Florian Bonetti, Jeffrey Munstermann, Luca Nittscher, Hugo Damer, Michael Bach
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
-use std::net::IpAddr;
+use std::{collections::HashSet, convert::TryFrom, net::IpAddr};
 
+use ipnetwork::IpNetwork;
 use namib_shared::{
-    firewall_config::{FirewallDevice, FirewallRule, Protocol, RuleName, RuleTarget, RuleTargetHost, Verdict},
+    firewall_config::{
+        ConnectionState, FirewallDevice, FirewallRule, Protocol, RuleName, RuleTarget, RuleTargetHost, Verdict,
+    },
+    macaddr::MacAddr,
     EnforcerConfig,
 };
+use openssl::sha::sha256;
+use paperclip::actix::Apiv2Schema;
+use url::Url;
 
 use crate::{
+    app_config::APP_CONFIG,
     db::DbConnection,
     error::Result,
-    models::{AceAction, AceProtocol, Acl, AclDirection, DeviceWithRefs},
+    models::{
+        Ace, AceAction, AceIcmpMatches, AceMudMatches, AcePort, AceProtocol, Acl, AclDirection, AclType,
+        ControllerMapping, Device, DeviceWithRefs, MudSignatureStatus, PolicyException,
+    },
     services::{
         acme_service,
         config_service::{get_config_value, set_config_value, ConfigKeys},
+        controller_mapping_service, device_service,
+        mud_service::signature::{get_signature_policy, MudSignaturePolicy},
+        policy_exception_service,
     },
 };
 
+/// IANA protocol number of TCP, used by MUD-Files that set the protocol in the ipv4/ipv6 matches.
+const PROTOCOL_TCP: u32 = 6;
+/// IANA protocol number of UDP, used by MUD-Files that set the protocol in the ipv4/ipv6 matches.
+const PROTOCOL_UDP: u32 = 17;
+/// IANA protocol number of ICMP, whose ACEs may match icmp types and codes.
+const PROTOCOL_ICMP: u32 = 1;
+/// IANA protocol number of ICMPv6, whose ACEs may match icmp types and codes.
+const PROTOCOL_ICMPV6: u32 = 58;
+/// The lowest tcp/udp port, used for open ended port ranges.
+const MIN_PORT: u32 = 0;
+/// The highest tcp/udp port, used for open ended port ranges.
+const MAX_PORT: u32 = 65535;
+
+/// Decides which rules devices get whose manufacturer no longer supports them, i.e. whose MUD-File sets
+/// `is-supported` to false.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, strum::AsRefStr, strum::EnumString)]
+pub enum UnsupportedDevicePolicy {
+    /// The ACLs of the MUD-File apply as for supported devices.
+    Allow,
+    /// The ACLs of the MUD-File only allow traffic within the local networks.
+    LocalOnly,
+    /// The devices only get the default rules and the overrides.
+    Block,
+}
+
+impl Default for UnsupportedDevicePolicy {
+    fn default() -> Self {
+        UnsupportedDevicePolicy::Allow
+    }
+}
+
+/// Returns the policy for unsupported devices configured by the admin, or the default policy if none is configured.
+pub async fn get_unsupported_device_policy(pool: &DbConnection) -> UnsupportedDevicePolicy {
+    get_config_value(ConfigKeys::UnsupportedDevicePolicy.as_ref(), pool)
+        .await
+        .unwrap_or_default()
+}
+
 pub fn merge_acls<'a>(original: &'a [Acl], override_with: &'a [Acl]) -> Vec<&'a Acl> {
     let override_keys: Vec<&str> = override_with.iter().map(|x| x.name.as_ref()).collect();
     original
@@ -27,108 +79,782 @@ pub fn merge_acls<'a>(original: &'a [Acl], override_with: &'a [Acl]) -> Vec<&'a
         .collect()
 }
 
-pub fn create_configuration(version: String, devices: &[DeviceWithRefs]) -> EnforcerConfig {
+/// The state of the managed network, used to resolve the MUD abstractions of an ACE into concrete rule targets.
+pub struct NetworkContext<'a> {
+    /// All known devices.
+    pub devices: &'a [DeviceWithRefs],
+    /// The local networks managed by the enforcers.
+    pub local_networks: &'a [IpNetwork],
+    /// The registered controllers.
+    pub controllers: &'a [ControllerMapping],
+    /// The policy for MUD-Files without a valid signature.
+    pub signature_policy: MudSignaturePolicy,
+    /// The policy for devices that are no longer supported by their manufacturer.
+    pub unsupported_device_policy: UnsupportedDevicePolicy,
+    /// The policy exceptions, only the active ones apply.
+    pub exceptions: &'a [PolicyException],
+}
+
+pub fn create_configuration(
+    version: String,
+    devices: &[DeviceWithRefs],
+    controllers: &[ControllerMapping],
+    signature_policy: MudSignaturePolicy,
+    unsupported_device_policy: UnsupportedDevicePolicy,
+    exceptions: &[PolicyException],
+) -> EnforcerConfig {
+    let context = NetworkContext {
+        devices,
+        local_networks: &APP_CONFIG.local_networks,
+        controllers,
+        signature_policy,
+        unsupported_device_policy,
+        exceptions,
+    };
     let rules = devices
         .iter()
         .filter(|d| d.ipv4_addr.is_some() || d.ipv6_addr.is_some())
-        .map(|d| convert_device_to_fw_rules(d))
+        .map(|d| convert_device_to_fw_rules(d, &context))
         .collect();
     EnforcerConfig::new(version, rules, acme_service::DOMAIN.clone())
 }
 
-pub fn convert_device_to_fw_rules(device: &DeviceWithRefs) -> FirewallDevice {
-    let mut index = 0;
+/// Where the ACL a firewall rule is generated from comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Apiv2Schema)]
+pub enum RuleSource {
+    /// The ACLs of the MUD-Profile.
+    Mud,
+    /// The overrides of the MUD-Profile.
+    ProfileOverride,
+    /// The overrides of the device.
+    DeviceOverride,
+    /// An active policy exception.
+    Exception,
+    /// The default rules rejecting all other traffic of the device.
+    Default,
+}
+
+/// Where a firewall rule comes from, so enforcer logs and hit counters can be traced back to the ACE.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Apiv2Schema)]
+pub struct RuleProvenance {
+    pub rule_name: String,
+    pub device_id: i64,
+    /// The MUD-URL of the MUD-Profile of the device.
+    pub mud_url: Option<String>,
+    /// The ACL the rule is generated from, `None` for the default rules.
+    pub acl_name: Option<String>,
+    /// The ACE the rule is generated from, `None` for the default rules.
+    pub ace_name: Option<String>,
+    pub source: RuleSource,
+    /// Whether the ACL is an override of the MUD-Profile or the device instead of coming from the MUD-File.
+    pub is_override: bool,
+    /// The policy exception the ACL belongs to.
+    pub exception_id: Option<i64>,
+}
+
+/// Returns the provenance of the rules the enforcers currently get for the device.
+/// Only the devices the MUD abstractions of the device refer to are loaded, and only their addresses and MUD-URLs are
+/// needed to resolve them, so their MUD-Profiles aren't loaded.
+pub async fn get_device_rule_provenance(device: Device, pool: &DbConnection) -> Result<Vec<RuleProvenance>> {
+    let device = device.load_refs(pool).await?;
+    let controllers = controller_mapping_service::get_all_controller_mappings(pool).await?;
+    let exceptions = policy_exception_service::get_active_exceptions(pool).await?;
+
+    let mud_matches: Vec<&AceMudMatches> = device
+        .mud_data
+        .iter()
+        .flat_map(|mud_data| mud_data.acllist.iter().chain(&mud_data.acl_override))
+        .chain(&device.acl_override)
+        .chain(
+            exceptions
+                .iter()
+                .filter(|exception| exception.applies_to(&device))
+                .flat_map(|exception| &exception.acls),
+        )
+        .flat_map(|acl| &acl.ace)
+        .filter_map(|ace| ace.matches.mud.as_ref())
+        .collect();
+    let matches_manufacturers = mud_matches
+        .iter()
+        .any(|mud| mud.same_manufacturer || mud.manufacturer.is_some() || mud.model.is_some());
+    let matches_controllers = mud_matches
+        .iter()
+        .any(|mud| mud.controller.is_some() || mud.my_controller);
+
+    let mut other_devices = Vec::new();
+    if matches_manufacturers {
+        other_devices = device_service::get_all_devices(pool).await?;
+    } else if matches_controllers {
+        for device_id in controllers.iter().filter_map(|c| c.device_id) {
+            // SQLite may leave mappings of deleted devices behind
+            match device_service::find_by_id(device_id, pool).await {
+                Ok(controller_device) => other_devices.push(controller_device),
+                Err(e) => warn!("Skipping missing device {} of controller mapping: {:?}", device_id, e),
+            }
+        }
+    }
+    other_devices.retain(|d| d.id != device.id);
+
+    let devices: Vec<DeviceWithRefs> = std::iter::once(device)
+        .chain(other_devices.into_iter().map(|inner| DeviceWithRefs {
+            inner,
+            room: None,
+            mud_data: None,
+        }))
+        .collect();
+    let context = NetworkContext {
+        devices: &devices,
+        local_networks: &APP_CONFIG.local_networks,
+        controllers: &controllers,
+        signature_policy: get_signature_policy(pool).await,
+        unsupported_device_policy: get_unsupported_device_policy(pool).await,
+        exceptions: &exceptions,
+    };
+    Ok(convert_device_to_fw_rules_with_provenance(&devices[0], &context).1)
+}
+
+pub fn convert_device_to_fw_rules(device: &DeviceWithRefs, context: &NetworkContext) -> FirewallDevice {
+    convert_device_to_fw_rules_with_provenance(device, context).0
+}
+
+/// Like `convert_device_to_fw_rules`, but also returns the provenance of every rule.
+/// Rule names are derived from the device, ACL and ACE names, so they don't change when other ACEs are added.
+/// ACEs resulting in multiple rules name them after their remote host and ports, see `target_rule_name`.
+pub fn convert_device_to_fw_rules_with_provenance(
+    device: &DeviceWithRefs,
+    context: &NetworkContext,
+) -> (FirewallDevice, Vec<RuleProvenance>) {
     let mut result: Vec<FirewallRule> = Vec::new();
-    let mud_data = match &device.mud_data {
-        Some(mud_data) => mud_data,
-        None => {
-            return FirewallDevice {
+    let mut provenance: Vec<RuleProvenance> = Vec::new();
+    // devices without a MUD-URL aren't restricted. Devices whose MUD-URL belongs to a blocked authority have no
+    // MUD-Profile, see `Device::load_refs`, so they only get the overrides of the device and the default rules
+    if device.mud_url.is_none() && device.mud_data.is_none() {
+        return (
+            FirewallDevice {
                 id: device.id,
                 ipv4_addr: device.ipv4_addr,
                 ipv6_addr: device.ipv6_addr,
                 rules: result,
                 collect_data: device.collect_info,
-            }
-        },
-    };
+            },
+            provenance,
+        );
+    }
+    let mud_data = device.mud_data.as_ref();
+    let mud_url = mud_data
+        .map(|mud_data| mud_data.url.clone())
+        .or_else(|| device.mud_url.clone());
 
-    let merged_acls = if mud_data.acl_override.is_empty() {
-        mud_data.acllist.iter().collect()
-    } else {
-        merge_acls(&mud_data.acllist, &mud_data.acl_override)
+    // quarantined devices only get the overrides set by the admin, the ACLs of the MUD-File aren't trusted
+    let is_quarantined = context.signature_policy == MudSignaturePolicy::Quarantine
+        && mud_data
+            .and_then(|mud_data| mud_data.signature_status)
+            .map_or(false, |status| status != MudSignatureStatus::Valid);
+    let is_unsupported = mud_data.map_or(false, |mud_data| !mud_data.is_supported);
+    let is_blocked = is_unsupported && context.unsupported_device_policy == UnsupportedDevicePolicy::Block;
+    let is_local_only = is_unsupported && context.unsupported_device_policy == UnsupportedDevicePolicy::LocalOnly;
+    let profile_acls: Vec<(&Acl, RuleSource)> = match mud_data {
+        None => Vec::new(),
+        Some(mud_data) if is_quarantined || is_blocked => mud_data
+            .acl_override
+            .iter()
+            .map(|acl| (acl, RuleSource::ProfileOverride))
+            .collect(),
+        Some(mud_data) => merge_acls(&mud_data.acllist, &mud_data.acl_override)
+            .into_iter()
+            .map(|acl| {
+                if mud_data.acl_override.iter().any(|o| std::ptr::eq(o, acl)) {
+                    (acl, RuleSource::ProfileOverride)
+                } else {
+                    (acl, RuleSource::Mud)
+                }
+            })
+            .collect(),
     };
+    // the overrides of the device replace ACLs of the same name, including the ones overridden by the profile.
+    // active policy exceptions only add ACLs, they never take away access granted otherwise
+    let merged_acls: Vec<(&Acl, RuleSource, Option<i64>)> = profile_acls
+        .into_iter()
+        .filter(|(acl, _)| !device.acl_override.iter().any(|o| o.name == acl.name))
+        .map(|(acl, source)| (acl, source, None))
+        .chain(
+            device
+                .acl_override
+                .iter()
+                .map(|acl| (acl, RuleSource::DeviceOverride, None)),
+        )
+        .chain(
+            context
+                .exceptions
+                .iter()
+                .filter(|exception| exception.is_active() && exception.applies_to(device))
+                .flat_map(|exception| {
+                    exception
+                        .acls
+                        .iter()
+                        .map(move |acl| (acl, RuleSource::Exception, Some(exception.id)))
+                }),
+        )
+        .collect();
 
-    for acl in &merged_acls {
+    for (acl, source, exception_id) in merged_acls {
+        // ACLs of exceptions may have the same names as other ACLs, so their rules are named after the exception too
+        let acl_rule_name = match exception_id {
+            Some(exception_id) => format!(
+                "rule.{}.exception-{}.{}",
+                device.id,
+                exception_id,
+                rule_name_part(&acl.name)
+            ),
+            None => format!("rule.{}.{}", device.id, rule_name_part(&acl.name)),
+        };
         for ace in &acl.ace {
-            let rule_name = RuleName::new(format!("rule_{}", index));
-            let protocol = match &ace.matches.protocol {
-                None => Protocol::All,
-                Some(proto) => match proto {
-                    AceProtocol::Tcp => Protocol::Tcp,
-                    AceProtocol::Udp => Protocol::Udp,
-                    AceProtocol::Protocol(_proto_nr) => Protocol::All, // Default to all protocols if protocol is not supported.
-                                                                       // TODO add support for more protocols
+            let ace_rule_name = format!("{}.{}", acl_rule_name, rule_name_part(&ace.name));
+
+            let protocol = match acl.acl_type {
+                AclType::Ethernet => Protocol::Ethernet {
+                    ethertype: ace.matches.eth.as_ref().and_then(|eth| eth.ethertype),
                 },
+                AclType::IPV4 | AclType::IPV6 => {
+                    match convert_protocol(ace.matches.protocol.as_ref(), ace.matches.icmp.as_ref()) {
+                        Some(protocol) => protocol,
+                        None => {
+                            warn!(
+                                "Skipping ACE {} with invalid protocol {:?}",
+                                ace.name, ace.matches.protocol
+                            );
+                            continue;
+                        },
+                    }
+                },
+            };
+            // ports can only be matched for tcp and udp
+            let (source_ports, destination_ports) = match protocol {
+                Protocol::Tcp | Protocol::Udp => (
+                    convert_optional_port(ace.matches.source_port.as_ref()),
+                    convert_optional_port(ace.matches.destination_port.as_ref()),
+                ),
+                _ => (vec![None], vec![None]),
             };
             let target = match ace.action {
                 AceAction::Accept => Verdict::Accept,
                 AceAction::Deny => Verdict::Reject,
             };
 
-            let route_network_fw_device = RuleTarget::new(Some(RuleTargetHost::FirewallDevice), None);
-            if let Some(dns_name) = &ace.matches.dnsname {
-                let route_network_remote_host = match dns_name.parse::<IpAddr>() {
-                    Ok(addr) => RuleTarget::new(Some(RuleTargetHost::Ip(addr)), None),
-                    Err(_) => RuleTarget::new(Some(RuleTargetHost::Hostname(dns_name.clone())), None),
-                };
+            // tcp ACEs with direction-initiated only let the named side open connections, so packets in that
+            // direction may belong to new or established connections and packets in the other direction may only
+            // belong to established ones. Rules without connection states match packets of any state.
+            let connection_states = match ace.matches.direction_initiated {
+                None => Vec::new(),
+                Some(initiated) if initiated == acl.packet_direction => {
+                    vec![ConnectionState::New, ConnectionState::Established]
+                },
+                Some(_) => vec![ConnectionState::Established],
+            };
+
+            // the device is on one side of every packet, the matches of the other side select the remote hosts.
+            // A remote host of `None` matches any host.
+            let remote_hosts = match acl.acl_type {
+                AclType::Ethernet => resolve_eth_remote_hosts(device, acl.packet_direction, ace, context),
+                AclType::IPV4 | AclType::IPV6 => {
+                    resolve_ip_remote_hosts(device, acl.acl_type, acl.packet_direction, ace, context)
+                },
+            };
+            let remote_hosts: Vec<Option<RuleTargetHost>> = match remote_hosts {
+                Some(remote_hosts) => remote_hosts,
+                None => {
+                    debug!("Skipping ACE {} which doesn't apply to device {}", ace.name, device.id);
+                    continue;
+                },
+            };
+            // end-of-life devices may only be allowed to reach the local networks, unless the admin set the ACL.
+            // Ethernet frames don't leave the local network, so ethernet rules matching any host are local too,
+            // while ip rules matching any host aren't.
+            let remote_hosts = if is_local_only && ace.action == AceAction::Accept && source == RuleSource::Mud {
+                remote_hosts
+                    .into_iter()
+                    .filter(|host| {
+                        host.as_ref().map_or(acl.acl_type == AclType::Ethernet, |host| {
+                            is_local_host(host, context.local_networks)
+                        })
+                    })
+                    .collect()
+            } else {
+                remote_hosts
+            };
 
+            // an ACE results in one rule for every combination of remote host, source and destination port range
+            let targets: Vec<_> = remote_hosts
+                .iter()
+                .flat_map(|remote_host| {
+                    source_ports.iter().flat_map(move |source_port| {
+                        destination_ports
+                            .iter()
+                            .map(move |destination_port| (remote_host, source_port, destination_port))
+                    })
+                })
+                .collect();
+            let mut rule_names = HashSet::new();
+            for &(remote_host, source_port, destination_port) in &targets {
+                let rule_name = if targets.len() == 1 {
+                    ace_rule_name.clone()
+                } else {
+                    target_rule_name(
+                        &ace_rule_name,
+                        remote_host.as_ref(),
+                        source_port.as_deref(),
+                        destination_port.as_deref(),
+                    )
+                };
+                // MUD abstractions may resolve to the same host more than once, e.g. a controller that is also a device
+                if !rule_names.insert(rule_name.clone()) {
+                    continue;
+                }
+                provenance.push(RuleProvenance {
+                    rule_name: rule_name.clone(),
+                    device_id: device.id,
+                    mud_url: mud_url.clone(),
+                    acl_name: Some(acl.name.clone()),
+                    ace_name: Some(ace.name.clone()),
+                    source,
+                    is_override: source == RuleSource::ProfileOverride || source == RuleSource::DeviceOverride,
+                    exception_id,
+                });
                 let (route_network_src, route_network_dest) = match acl.packet_direction {
-                    AclDirection::FromDevice => (route_network_fw_device, route_network_remote_host),
-                    AclDirection::ToDevice => (route_network_remote_host, route_network_fw_device),
+                    AclDirection::FromDevice => (
+                        RuleTarget::new(Some(RuleTargetHost::FirewallDevice), source_port.clone()),
+                        RuleTarget::new(remote_host.clone(), destination_port.clone()),
+                    ),
+                    AclDirection::ToDevice => (
+                        RuleTarget::new(remote_host.clone(), source_port.clone()),
+                        RuleTarget::new(Some(RuleTargetHost::FirewallDevice), destination_port.clone()),
+                    ),
                 };
                 let config_firewall = FirewallRule::new(
-                    rule_name.clone(),
+                    RuleName::new(rule_name),
                     route_network_src,
                     route_network_dest,
                     protocol.clone(),
                     target.clone(),
-                );
+                )
+                .with_connection_states(connection_states.clone());
                 result.push(config_firewall);
             }
-            index += 1;
         }
     }
-    result.push(FirewallRule::new(
-        RuleName::new(format!("rule_default_{}", index)),
-        RuleTarget::new(Some(RuleTargetHost::FirewallDevice), None),
-        RuleTarget::new(None, None),
-        Protocol::All,
-        Verdict::Reject,
-    ));
-    index += 1;
-    result.push(FirewallRule::new(
-        RuleName::new(format!("rule_default_{}", index)),
-        RuleTarget::new(None, None),
-        RuleTarget::new(Some(RuleTargetHost::FirewallDevice), None),
-        Protocol::All,
-        Verdict::Reject,
-    ));
-
-    FirewallDevice {
-        id: device.id,
-        ipv4_addr: device.ipv4_addr,
-        ipv6_addr: device.ipv6_addr,
-        rules: result,
-        collect_data: device.collect_info,
+    for (direction, route_network_src, route_network_dest) in vec![
+        (
+            "out",
+            RuleTarget::new(Some(RuleTargetHost::FirewallDevice), None),
+            RuleTarget::new(None, None),
+        ),
+        (
+            "in",
+            RuleTarget::new(None, None),
+            RuleTarget::new(Some(RuleTargetHost::FirewallDevice), None),
+        ),
+    ] {
+        let rule_name = format!("rule.{}.default-{}", device.id, direction);
+        provenance.push(RuleProvenance {
+            rule_name: rule_name.clone(),
+            device_id: device.id,
+            mud_url: mud_url.clone(),
+            acl_name: None,
+            ace_name: None,
+            source: RuleSource::Default,
+            is_override: false,
+            exception_id: None,
+        });
+        result.push(FirewallRule::new(
+            RuleName::new(rule_name),
+            route_network_src,
+            route_network_dest,
+            Protocol::All,
+            Verdict::Reject,
+        ));
+    }
+
+    (
+        FirewallDevice {
+            id: device.id,
+            ipv4_addr: device.ipv4_addr,
+            ipv6_addr: device.ipv6_addr,
+            rules: result,
+            collect_data: device.collect_info,
+        },
+        provenance,
+    )
+}
+
+/// Converts the protocol of an ACE into the protocol of the enforcer rule, keeping the icmp type and code for icmp.
+/// Returns `None` if the protocol number isn't a valid IP protocol number.
+fn convert_protocol(protocol: Option<&AceProtocol>, icmp: Option<&AceIcmpMatches>) -> Option<Protocol> {
+    let icmp_type = icmp.and_then(|icmp| icmp.icmp_type);
+    let code = icmp.and_then(|icmp| icmp.code);
+    Some(match protocol {
+        None => Protocol::All,
+        Some(AceProtocol::Tcp) | Some(AceProtocol::Protocol(PROTOCOL_TCP)) => Protocol::Tcp,
+        Some(AceProtocol::Udp) | Some(AceProtocol::Protocol(PROTOCOL_UDP)) => Protocol::Udp,
+        Some(AceProtocol::Protocol(PROTOCOL_ICMP)) => Protocol::Icmp { icmp_type, code },
+        Some(AceProtocol::Protocol(PROTOCOL_ICMPV6)) => Protocol::Icmpv6 { icmp_type, code },
+        Some(AceProtocol::Protocol(number)) => Protocol::Number(u8::try_from(*number).ok()?),
+    })
+}
+
+/// Makes an ACL or ACE name usable as part of a rule name, which separates its parts with dots.
+/// Names with other characters than letters, digits, `-` and `_` get a hash of the original name appended, so
+/// names that only differ in the replaced characters don't result in the same rule name.
+fn rule_name_part(name: &str) -> String {
+    let is_allowed = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_';
+    if name.chars().all(is_allowed) {
+        return name.to_string();
+    }
+    let sanitized: String = name.chars().map(|c| if is_allowed(c) { c } else { '_' }).collect();
+    format!("{}-{}", sanitized, short_hash(name))
+}
+
+/// Names one of multiple rules of an ACE after its remote host and ports, so the name doesn't depend on the order in
+/// which the hosts are resolved.
+fn target_rule_name(
+    ace_rule_name: &str,
+    remote_host: Option<&RuleTargetHost>,
+    source_port: Option<&str>,
+    destination_port: Option<&str>,
+) -> String {
+    let host = match remote_host {
+        None => String::new(),
+        Some(RuleTargetHost::Ip(addr)) => addr.to_string(),
+        Some(RuleTargetHost::Network(network)) => network.to_string(),
+        Some(RuleTargetHost::Hostname(name)) => name.clone(),
+        Some(RuleTargetHost::Mac(mac)) => mac.to_string(),
+        Some(host) => format!("{:?}", host),
+    };
+    let target = format!(
+        "{} {} {}",
+        host,
+        source_port.unwrap_or_default(),
+        destination_port.unwrap_or_default()
+    );
+    format!("{}.{}", ace_rule_name, short_hash(&target))
+}
+
+/// A short, stable hash of the value for use in rule names, i.e. the start of its SHA-256 in hex.
+fn short_hash(value: &str) -> String {
+    sha256(value.as_bytes())[..4]
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Resolves the remote hosts of an ACE of an ipv4/ipv6 ACL, or returns `None` if the ACE doesn't apply to the device.
+/// ACEs without remote matches match any host.
+fn resolve_ip_remote_hosts(
+    device: &DeviceWithRefs,
+    acl_type: AclType,
+    packet_direction: AclDirection,
+    ace: &Ace,
+    context: &NetworkContext,
+) -> Option<Vec<Option<RuleTargetHost>>> {
+    let matches = &ace.matches;
+    let (device_network, device_dns_name, remote_network, remote_dns_name) = match packet_direction {
+        AclDirection::FromDevice => (
+            &matches.source_network,
+            &matches.source_dnsname,
+            &matches.destination_network,
+            &matches.destination_dnsname,
+        ),
+        AclDirection::ToDevice => (
+            &matches.destination_network,
+            &matches.destination_dnsname,
+            &matches.source_network,
+            &matches.source_dnsname,
+        ),
+    };
+    if !matches_device(
+        device,
+        device_network.as_deref(),
+        device_dns_name.is_some(),
+        &ace.action,
+    ) {
+        return None;
+    }
+
+    // explicit hosts and networks are more specific than the MUD abstractions, so they take precedence.
+    Some(if let Some(dns_name) = remote_dns_name {
+        vec![Some(match dns_name.parse::<IpAddr>() {
+            Ok(addr) => RuleTargetHost::Ip(addr),
+            Err(_) => RuleTargetHost::Hostname(dns_name.clone()),
+        })]
+    } else if let Some(network) = remote_network {
+        match network.parse::<IpNetwork>() {
+            Ok(network) => vec![Some(convert_network(network))],
+            Err(_) => {
+                warn!("Skipping ACE {} with invalid network {}", ace.name, network);
+                Vec::new()
+            },
+        }
+    } else if let Some(mud) = &matches.mud {
+        resolve_mud_matches(device, mud, context, acl_type)
+            .into_iter()
+            .map(Some)
+            .collect()
+    } else {
+        vec![None]
+    })
+}
+
+/// Resolves the remote hosts of an ACE of an ethernet ACL, or returns `None` if the ACE doesn't apply to the device.
+/// Ethernet frames don't leave the local network, so `local-networks` and ACEs without remote matches match any host.
+fn resolve_eth_remote_hosts(
+    device: &DeviceWithRefs,
+    packet_direction: AclDirection,
+    ace: &Ace,
+    context: &NetworkContext,
+) -> Option<Vec<Option<RuleTargetHost>>> {
+    let (device_mac, remote_mac) = match (&ace.matches.eth, packet_direction) {
+        (None, _) => (None, None),
+        (Some(eth), AclDirection::FromDevice) => (&eth.source_mac_address, &eth.destination_mac_address),
+        (Some(eth), AclDirection::ToDevice) => (&eth.destination_mac_address, &eth.source_mac_address),
+    };
+    if !matches_device_mac(device, device_mac.as_deref(), &ace.action) {
+        return None;
+    }
+
+    Some(if let Some(mac) = remote_mac {
+        match mac.parse::<MacAddr>() {
+            Ok(mac) => vec![Some(RuleTargetHost::Mac(mac.into()))],
+            Err(_) => {
+                warn!("Skipping ACE {} with invalid mac address {}", ace.name, mac);
+                Vec::new()
+            },
+        }
+    } else if let Some(mud) = ace.matches.mud.as_ref().filter(|mud| has_device_matches(mud)) {
+        resolve_mud_matches(device, mud, context, AclType::Ethernet)
+            .into_iter()
+            .map(Some)
+            .collect()
+    } else if ace.matches.mud.as_ref().map_or(true, |mud| mud.local_networks) {
+        vec![None]
+    } else {
+        Vec::new()
+    })
+}
+
+/// Checks whether the MUD abstractions of an ACE match other devices or controllers.
+fn has_device_matches(mud: &AceMudMatches) -> bool {
+    mud.controller.is_some()
+        || mud.my_controller
+        || mud.same_manufacturer
+        || mud.manufacturer.is_some()
+        || mud.model.is_some()
+}
+
+/// Resolves the MUD abstractions of an ACE into the hosts they currently stand for.
+/// Device based abstractions are combined, since all matches of an ACE have to apply. An empty result means that
+/// no device currently matches, so no rule is generated for the ACE.
+/// Devices are matched by their ip addresses of the address family of the ACL, or by their mac addresses for ethernet
+/// ACLs.
+fn resolve_mud_matches(
+    device: &DeviceWithRefs,
+    mud: &AceMudMatches,
+    context: &NetworkContext,
+    acl_type: AclType,
+) -> Vec<RuleTargetHost> {
+    if mud.controller.is_some() || mud.my_controller {
+        return resolve_controllers(device, mud, context, acl_type);
+    }
+    if !has_device_matches(mud) {
+        return if mud.local_networks {
+            context
+                .local_networks
+                .iter()
+                .filter(|n| is_address_family(n.ip(), acl_type))
+                .map(|n| convert_network(*n))
+                .collect()
+        } else {
+            Vec::new()
+        };
+    }
+
+    let own_manufacturer = device.mud_url.as_deref().and_then(get_manufacturer);
+    context
+        .devices
+        .iter()
+        .filter(|d| d.id != device.id)
+        .filter(|d| {
+            let manufacturer = d.mud_url.as_deref().and_then(get_manufacturer);
+            (!mud.same_manufacturer || (own_manufacturer.is_some() && manufacturer == own_manufacturer))
+                && mud
+                    .manufacturer
+                    .as_ref()
+                    .map_or(true, |m| manufacturer.as_ref() == Some(m))
+                && mud.model.as_ref().map_or(true, |m| d.mud_url.as_ref() == Some(m))
+        })
+        .flat_map(|d| device_hosts(d, acl_type))
+        .collect()
+}
+
+/// Returns the hosts matching a device, i.e. its ip address of the address family of the ACL or its mac address for
+/// ethernet ACLs.
+fn device_hosts(device: &DeviceWithRefs, acl_type: AclType) -> Vec<RuleTargetHost> {
+    match acl_type {
+        AclType::Ethernet => device.mac_addr.map(RuleTargetHost::Mac).into_iter().collect(),
+        AclType::IPV4 => device
+            .ipv4_addr
+            .map(|addr| RuleTargetHost::Ip(addr.into()))
+            .into_iter()
+            .collect(),
+        AclType::IPV6 => device
+            .ipv6_addr
+            .map(|addr| RuleTargetHost::Ip(addr.into()))
+            .into_iter()
+            .collect(),
+    }
+}
+
+/// Checks whether an address belongs to the address family of an ipv4/ipv6 ACL.
+fn is_address_family(addr: IpAddr, acl_type: AclType) -> bool {
+    match acl_type {
+        AclType::IPV4 => addr.is_ipv4(),
+        AclType::IPV6 => addr.is_ipv6(),
+        AclType::Ethernet => false,
+    }
+}
+
+/// Resolves `controller` and `my-controller` matches into the addresses of the registered controllers.
+/// Controllers for `my-controller` matches are registered under the MUD-URL of the device.
+/// Controllers only known by their host can't be matched by ethernet ACLs, and controllers with an ip address only
+/// by ACLs of its address family.
+fn resolve_controllers(
+    device: &DeviceWithRefs,
+    mud: &AceMudMatches,
+    context: &NetworkContext,
+    acl_type: AclType,
+) -> Vec<RuleTargetHost> {
+    context
+        .controllers
+        .iter()
+        .filter(|c| {
+            mud.controller.as_ref() == Some(&c.uri) || (mud.my_controller && device.mud_url.as_ref() == Some(&c.uri))
+        })
+        .flat_map(|c| {
+            let mut hosts = Vec::new();
+            if let Some(controller_device) = c.device_id.and_then(|id| context.devices.iter().find(|d| d.id == id)) {
+                hosts.extend(device_hosts(controller_device, acl_type));
+            }
+            if let Some(host) = c.host.as_ref().filter(|_| acl_type != AclType::Ethernet) {
+                match host.parse::<IpAddr>() {
+                    Ok(addr) if is_address_family(addr, acl_type) => hosts.push(RuleTargetHost::Ip(addr)),
+                    Ok(_) => {},
+                    Err(_) => hosts.push(RuleTargetHost::Hostname(host.clone())),
+                }
+            }
+            hosts
+        })
+        .collect()
+}
+
+/// Returns the manufacturer of a device as defined by RFC 8520, i.e. the authority of its MUD-URL.
+fn get_manufacturer(mud_url: &str) -> Option<String> {
+    Url::parse(mud_url).ok()?.host_str().map(String::from)
+}
+
+/// Checks whether the matches on the side of the device apply to the device, i.e. whether the network contains one of
+/// its addresses. The controller can't resolve names of devices, so accepting ACEs with a name on the side of the
+/// device don't apply, which keeps the resulting rules from being more permissive than the MUD-File.
+fn matches_device(device: &DeviceWithRefs, network: Option<&str>, has_dns_name: bool, action: &AceAction) -> bool {
+    if has_dns_name && *action == AceAction::Accept {
+        return false;
+    }
+    match network.map(str::parse::<IpNetwork>) {
+        None => true,
+        Some(Ok(network)) => {
+            device.ipv4_addr.map_or(false, |addr| network.contains(addr.into()))
+                || device.ipv6_addr.map_or(false, |addr| network.contains(addr.into()))
+        },
+        Some(Err(_)) => false,
+    }
+}
+
+/// Checks whether the mac address match on the side of the device applies to the device. Accepting ACEs don't apply
+/// to devices with an unknown mac address, which keeps the resulting rules from being more permissive than the MUD-File.
+fn matches_device_mac(device: &DeviceWithRefs, mac: Option<&str>, action: &AceAction) -> bool {
+    let mac = match mac {
+        None => return true,
+        Some(mac) => mac.parse::<MacAddr>().ok(),
+    };
+    match (mac, device.mac_addr.and_then(|m| m.to_string().parse::<MacAddr>().ok())) {
+        (Some(mac), Some(device_mac)) => mac == device_mac,
+        (Some(_), None) => *action == AceAction::Deny,
+        (None, _) => false,
+    }
+}
+
+/// Checks whether a rule target host lies within the local networks. Hostnames can't be resolved by the controller, so
+/// they are never considered local, while mac addresses always are.
+fn is_local_host(host: &RuleTargetHost, local_networks: &[IpNetwork]) -> bool {
+    let network = match host {
+        RuleTargetHost::Ip(addr) => IpNetwork::from(*addr),
+        RuleTargetHost::Network(network) => *network,
+        RuleTargetHost::Mac(_) => return true,
+        _ => return false,
+    };
+    local_networks
+        .iter()
+        .any(|n| n.prefix() <= network.prefix() && n.contains(network.network()))
+}
+
+/// Converts a network into a rule target host, single addresses are matched exactly.
+fn convert_network(network: IpNetwork) -> RuleTargetHost {
+    if network.prefix() == max_prefix(network) {
+        RuleTargetHost::Ip(network.ip())
+    } else {
+        RuleTargetHost::Network(network)
+    }
+}
+
+/// Returns the prefix length of a single address in the address family of the given network.
+fn max_prefix(network: IpNetwork) -> u8 {
+    match network {
+        IpNetwork::V4(_) => 32,
+        IpNetwork::V6(_) => 128,
+    }
+}
+
+/// Converts an `AcePort` into the port notation of the enforcer, i.e. `443` or `1024:2048`.
+/// Ports that can't be expressed as a single range (`neq`) are split into multiple ranges.
+fn convert_port(port: &AcePort) -> Vec<String> {
+    match port {
+        AcePort::Single(port) => vec![port.to_string()],
+        AcePort::Range(lower, upper) => vec![format!("{}:{}", lower, upper)],
+        AcePort::Lte(port) => vec![format!("{}:{}", MIN_PORT, port)],
+        AcePort::Gte(port) => vec![format!("{}:{}", port, MAX_PORT)],
+        AcePort::Neq(port) => {
+            let mut ranges = Vec::new();
+            if *port > MIN_PORT {
+                ranges.push(format!("{}:{}", MIN_PORT, port - 1));
+            }
+            if *port < MAX_PORT {
+                ranges.push(format!("{}:{}", port + 1, MAX_PORT));
+            }
+            ranges
+        },
     }
 }
 
+/// Converts an optional `AcePort` into the port ranges to generate rules for, where `None` matches any port.
+fn convert_optional_port(port: Option<&AcePort>) -> Vec<Option<String>> {
+    port.map_or_else(|| vec![None], |port| convert_port(port).into_iter().map(Some).collect())
+}
+
 pub async fn get_config_version(pool: &DbConnection) -> String {
     get_config_value(ConfigKeys::FirewallConfigVersion.as_ref(), pool)
         .await
         .unwrap_or_else(|_| "0".to_string())
 }
 
+/// Marks the firewall configuration as outdated, so the enforcers fetch a new one.
+/// This has to be called whenever devices change, as the MUD abstractions of other devices may depend on them.
 pub async fn update_config_version(pool: &DbConnection) -> Result<()> {
     let old_config_version = get_config_value(ConfigKeys::FirewallConfigVersion.as_ref(), pool)
         .await
@@ -144,11 +870,82 @@ pub async fn update_config_version(pool: &DbConnection) -> Result<()> {
 
 #[cfg(test)]
 mod tests {
-    use chrono::Utc;
-    use namib_shared::macaddr::MacAddr;
+    use chrono::{NaiveDateTime, Utc};
 
     use super::*;
-    use crate::models::{Ace, AceAction, AceMatches, AceProtocol, Acl, AclDirection, AclType, Device, MudData};
+    use crate::{
+        models::{
+            Ace, AceAction, AceEthMatches, AceIcmpMatches, AceMatches, AceProtocol, Acl, AclDirection, AclType, Device,
+            MudData,
+        },
+        services::mud_service::parser::parse_mud,
+    };
+
+    fn empty_context() -> NetworkContext<'static> {
+        NetworkContext {
+            devices: &[],
+            local_networks: &[],
+            controllers: &[],
+            signature_policy: MudSignaturePolicy::Warn,
+            unsupported_device_policy: UnsupportedDevicePolicy::Allow,
+            exceptions: &[],
+        }
+    }
+
+    fn device_with_mud_url(id: i64, mud_url: &str, ip_addr: &str) -> DeviceWithRefs {
+        let ip_addr = ip_addr.parse::<IpAddr>().unwrap();
+        DeviceWithRefs {
+            inner: Device {
+                id,
+                name: None,
+                mac_addr: None,
+                duid: None,
+                ipv4_addr: match ip_addr {
+                    IpAddr::V4(addr) => Some(addr),
+                    IpAddr::V6(_) => None,
+                },
+                ipv6_addr: match ip_addr {
+                    IpAddr::V4(_) => None,
+                    IpAddr::V6(addr) => Some(addr),
+                },
+                hostname: "".to_string(),
+                vendor_class: "".to_string(),
+                mud_url: Some(mud_url.to_string()),
+                collect_info: false,
+                last_interaction: Utc::now().naive_utc(),
+                clipart: None,
+                acl_override: Vec::new(),
+                room_id: None,
+            },
+            mud_data: None,
+            room: None,
+        }
+    }
+
+    fn device_with_mud_profile(mud_profile_path: &str, mud_profile_url: &str) -> Result<DeviceWithRefs> {
+        let mud_data = parse_mud(mud_profile_url.to_string(), &std::fs::read_to_string(mud_profile_path)?)?;
+
+        Ok(DeviceWithRefs {
+            inner: Device {
+                id: 0,
+                name: None,
+                mac_addr: Some("aa:bb:cc:dd:ee:ff".parse::<MacAddr>().unwrap().into()),
+                duid: None,
+                ipv4_addr: "127.0.0.1".parse().ok(),
+                ipv6_addr: None,
+                hostname: "".to_string(),
+                vendor_class: "".to_string(),
+                mud_url: Some(mud_profile_url.to_string()),
+                collect_info: false,
+                last_interaction: Utc::now().naive_utc(),
+                clipart: None,
+                acl_override: Vec::new(),
+                room_id: None,
+            },
+            mud_data: Some(mud_data),
+            room: None,
+        })
+    }
 
     #[test]
     fn test_acl_merging() -> Result<()> {
@@ -163,10 +960,15 @@ mod tests {
                     matches: AceMatches {
                         protocol: Some(AceProtocol::Tcp),
                         direction_initiated: None,
-                        address_mask: None,
-                        dnsname: None,
+                        source_network: None,
+                        destination_network: None,
+                        source_dnsname: None,
+                        destination_dnsname: None,
                         source_port: None,
                         destination_port: None,
+                        icmp: None,
+                        eth: None,
+                        mud: None,
                     },
                 }],
             },
@@ -180,10 +982,15 @@ mod tests {
                     matches: AceMatches {
                         protocol: Some(AceProtocol::Tcp),
                         direction_initiated: None,
-                        address_mask: None,
-                        dnsname: None,
+                        source_network: None,
+                        destination_network: None,
+                        source_dnsname: None,
+                        destination_dnsname: None,
                         source_port: None,
                         destination_port: None,
+                        icmp: None,
+                        eth: None,
+                        mud: None,
                     },
                 }],
             },
@@ -200,10 +1007,15 @@ mod tests {
                     matches: AceMatches {
                         protocol: Some(AceProtocol::Tcp),
                         direction_initiated: None,
-                        address_mask: None,
-                        dnsname: None,
+                        source_network: None,
+                        destination_network: None,
+                        source_dnsname: None,
+                        destination_dnsname: None,
                         source_port: None,
                         destination_port: None,
+                        icmp: None,
+                        eth: None,
+                        mud: None,
                     },
                 }],
             },
@@ -217,10 +1029,15 @@ mod tests {
                     matches: AceMatches {
                         protocol: Some(AceProtocol::Udp),
                         direction_initiated: None,
-                        address_mask: None,
-                        dnsname: None,
+                        source_network: None,
+                        destination_network: None,
+                        source_dnsname: None,
+                        destination_dnsname: None,
                         source_port: None,
                         destination_port: None,
+                        icmp: None,
+                        eth: None,
+                        mud: None,
                     },
                 }],
             },
@@ -263,11 +1080,18 @@ mod tests {
         let mud_data = MudData {
             url: "example.com/.well-known/mud".to_string(),
             masa_url: None,
+            mud_signature: None,
+            signature_status: None,
             last_update: "some_last_update".to_string(),
             systeminfo: Some("some_systeminfo".to_string()),
             mfg_name: Some("some_mfg_name".to_string()),
             model_name: Some("some_model_name".to_string()),
             documentation: Some("some_documentation".to_string()),
+            is_supported: true,
+            cache_validity: None,
+            fetch_status: None,
+            parent_url: None,
+            ace_patches: Vec::new(),
             expiration: Utc::now(),
             acllist: vec![Acl {
                 name: "some_acl_name".to_string(),
@@ -279,10 +1103,15 @@ mod tests {
                     matches: AceMatches {
                         protocol: Some(AceProtocol::Tcp),
                         direction_initiated: None,
-                        address_mask: None,
-                        dnsname: Some(String::from("www.example.test")),
+                        source_network: None,
+                        destination_network: None,
+                        source_dnsname: Some(String::from("www.example.test")),
+                        destination_dnsname: None,
                         source_port: None,
                         destination_port: None,
+                        icmp: None,
+                        eth: None,
+                        mud: None,
                     },
                 }],
             }],
@@ -296,10 +1125,15 @@ mod tests {
                     matches: AceMatches {
                         protocol: Some(AceProtocol::Udp),
                         direction_initiated: None,
-                        address_mask: None,
-                        dnsname: Some(String::from("www.example.test")),
+                        source_network: None,
+                        destination_network: None,
+                        source_dnsname: Some(String::from("www.example.test")),
+                        destination_dnsname: None,
                         source_port: None,
                         destination_port: None,
+                        icmp: None,
+                        eth: None,
+                        mud: None,
                     },
                 }],
             }],
@@ -319,13 +1153,14 @@ mod tests {
                 last_interaction: Utc::now().naive_utc(),
                 collect_info: false,
                 clipart: None,
+                acl_override: Vec::new(),
                 room_id: None,
             },
             mud_data: Some(mud_data),
             room: None,
         };
 
-        let x = convert_device_to_fw_rules(&device);
+        let x = convert_device_to_fw_rules(&device, &empty_context());
 
         let resulting_device = FirewallDevice {
             id: device.id,
@@ -333,21 +1168,21 @@ mod tests {
             ipv6_addr: device.ipv6_addr,
             rules: vec![
                 FirewallRule::new(
-                    RuleName::new(String::from("rule_0")),
+                    RuleName::new(String::from("rule.0.some_acl_name.overriden_ace")),
                     RuleTarget::new(Some(RuleTargetHost::Hostname(String::from("www.example.test"))), None),
                     RuleTarget::new(Some(RuleTargetHost::FirewallDevice), None),
                     Protocol::Udp,
                     Verdict::Reject,
                 ),
                 FirewallRule::new(
-                    RuleName::new(String::from("rule_default_1")),
+                    RuleName::new(String::from("rule.0.default-out")),
                     RuleTarget::new(Some(RuleTargetHost::FirewallDevice), None),
                     RuleTarget::new(None, None),
                     Protocol::All,
                     Verdict::Reject,
                 ),
                 FirewallRule::new(
-                    RuleName::new(String::from("rule_default_2")),
+                    RuleName::new(String::from("rule.0.default-in")),
                     RuleTarget::new(None, None),
                     RuleTarget::new(Some(RuleTargetHost::FirewallDevice), None),
                     Protocol::All,
@@ -367,11 +1202,18 @@ mod tests {
         let mud_data = MudData {
             url: "example.com/.well-known/mud".to_string(),
             masa_url: None,
+            mud_signature: None,
+            signature_status: None,
             last_update: "some_last_update".to_string(),
             systeminfo: Some("some_systeminfo".to_string()),
             mfg_name: Some("some_mfg_name".to_string()),
             model_name: Some("some_model_name".to_string()),
             documentation: Some("some_documentation".to_string()),
+            is_supported: true,
+            cache_validity: None,
+            fetch_status: None,
+            parent_url: None,
+            ace_patches: Vec::new(),
             expiration: Utc::now(),
             acllist: vec![Acl {
                 name: "some_acl_name".to_string(),
@@ -383,10 +1225,15 @@ mod tests {
                     matches: AceMatches {
                         protocol: Some(AceProtocol::Tcp),
                         direction_initiated: None,
-                        address_mask: None,
-                        dnsname: Some(String::from("www.example.test")),
+                        source_network: None,
+                        destination_network: None,
+                        source_dnsname: Some(String::from("www.example.test")),
+                        destination_dnsname: None,
                         source_port: None,
                         destination_port: None,
+                        icmp: None,
+                        eth: None,
+                        mud: None,
                     },
                 }],
             }],
@@ -407,13 +1254,14 @@ mod tests {
                 collect_info: true,
                 last_interaction: Utc::now().naive_utc(),
                 clipart: None,
+                acl_override: Vec::new(),
                 room_id: None,
             },
             mud_data: Some(mud_data),
             room: None,
         };
 
-        let x = convert_device_to_fw_rules(&device);
+        let x = convert_device_to_fw_rules(&device, &empty_context());
 
         let resulting_device = FirewallDevice {
             id: device.id,
@@ -421,21 +1269,21 @@ mod tests {
             ipv6_addr: device.ipv6_addr,
             rules: vec![
                 FirewallRule::new(
-                    RuleName::new(String::from("rule_0")),
+                    RuleName::new(String::from("rule.0.some_acl_name.some_ace_name")),
                     RuleTarget::new(Some(RuleTargetHost::Hostname(String::from("www.example.test"))), None),
                     RuleTarget::new(Some(RuleTargetHost::FirewallDevice), None),
                     Protocol::Tcp,
                     Verdict::Accept,
                 ),
                 FirewallRule::new(
-                    RuleName::new(String::from("rule_default_1")),
+                    RuleName::new(String::from("rule.0.default-out")),
                     RuleTarget::new(Some(RuleTargetHost::FirewallDevice), None),
                     RuleTarget::new(None, None),
                     Protocol::All,
                     Verdict::Reject,
                 ),
                 FirewallRule::new(
-                    RuleName::new(String::from("rule_default_2")),
+                    RuleName::new(String::from("rule.0.default-in")),
                     RuleTarget::new(None, None),
                     RuleTarget::new(Some(RuleTargetHost::FirewallDevice), None),
                     Protocol::All,
@@ -449,4 +1297,1438 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_mud_abstractions() -> Result<()> {
+        let mud_ace = |name: &str, mud: AceMudMatches| Ace {
+            name: name.to_string(),
+            action: AceAction::Accept,
+            matches: AceMatches {
+                protocol: None,
+                direction_initiated: None,
+                source_network: None,
+                destination_network: None,
+                source_dnsname: None,
+                destination_dnsname: None,
+                source_port: None,
+                destination_port: None,
+                icmp: None,
+                eth: None,
+                mud: Some(mud),
+            },
+        };
+        let no_mud = AceMudMatches {
+            local_networks: false,
+            same_manufacturer: false,
+            manufacturer: None,
+            model: None,
+            controller: None,
+            my_controller: false,
+        };
+
+        let mut device = device_with_mud_url(0, "https://lighting.example.com/lightbulb2000", "192.168.0.1");
+        device.mud_data = Some(MudData {
+            url: "https://lighting.example.com/lightbulb2000".to_string(),
+            masa_url: None,
+            mud_signature: None,
+            signature_status: None,
+            last_update: "some_last_update".to_string(),
+            systeminfo: None,
+            mfg_name: None,
+            model_name: None,
+            documentation: None,
+            is_supported: true,
+            cache_validity: None,
+            fetch_status: None,
+            parent_url: None,
+            ace_patches: Vec::new(),
+            expiration: Utc::now(),
+            acllist: vec![Acl {
+                name: "some_acl_name".to_string(),
+                packet_direction: AclDirection::FromDevice,
+                acl_type: AclType::IPV4,
+                ace: vec![
+                    mud_ace(
+                        "local_networks",
+                        AceMudMatches {
+                            local_networks: true,
+                            ..no_mud.clone()
+                        },
+                    ),
+                    mud_ace(
+                        "same_manufacturer",
+                        AceMudMatches {
+                            same_manufacturer: true,
+                            ..no_mud.clone()
+                        },
+                    ),
+                    mud_ace(
+                        "model",
+                        AceMudMatches {
+                            model: Some("https://lighting.example.com/lightbulb2000".to_string()),
+                            ..no_mud.clone()
+                        },
+                    ),
+                    mud_ace(
+                        "manufacturer",
+                        AceMudMatches {
+                            manufacturer: Some("switches.example.com".to_string()),
+                            ..no_mud
+                        },
+                    ),
+                ],
+            }],
+            acl_override: Vec::default(),
+        });
+        let mut dual_stack_device = device_with_mud_url(3, "https://lighting.example.com/lightbulb2000", "192.168.0.4");
+        dual_stack_device.inner.ipv6_addr = Some("fd00::4".parse()?);
+        let devices = vec![
+            device.clone(),
+            device_with_mud_url(1, "https://lighting.example.com/switch", "192.168.0.2"),
+            device_with_mud_url(2, "https://switches.example.com/switch", "192.168.0.3"),
+            dual_stack_device,
+        ];
+        let local_networks: Vec<IpNetwork> = vec!["192.168.0.0/24".parse().unwrap()];
+        let context = NetworkContext {
+            devices: &devices,
+            local_networks: &local_networks,
+            controllers: &[],
+            signature_policy: MudSignaturePolicy::Warn,
+            unsupported_device_policy: UnsupportedDevicePolicy::Allow,
+            exceptions: &[],
+        };
+
+        let x = convert_device_to_fw_rules(&device, &context);
+
+        let rule = |name: &str, host: RuleTargetHost| {
+            FirewallRule::new(
+                RuleName::new(String::from(name)),
+                RuleTarget::new(Some(RuleTargetHost::FirewallDevice), None),
+                RuleTarget::new(Some(host), None),
+                Protocol::All,
+                Verdict::Accept,
+            )
+        };
+        assert_eq!(
+            x.rules[..5],
+            [
+                rule(
+                    "rule.0.some_acl_name.local_networks",
+                    RuleTargetHost::Network("192.168.0.0/24".parse()?)
+                ),
+                rule(
+                    &target_rule_name(
+                        "rule.0.some_acl_name.same_manufacturer",
+                        Some(&RuleTargetHost::Ip("192.168.0.2".parse()?)),
+                        None,
+                        None
+                    ),
+                    RuleTargetHost::Ip("192.168.0.2".parse()?)
+                ),
+                rule(
+                    &target_rule_name(
+                        "rule.0.some_acl_name.same_manufacturer",
+                        Some(&RuleTargetHost::Ip("192.168.0.4".parse()?)),
+                        None,
+                        None
+                    ),
+                    RuleTargetHost::Ip("192.168.0.4".parse()?)
+                ),
+                rule("rule.0.some_acl_name.model", RuleTargetHost::Ip("192.168.0.4".parse()?)),
+                rule(
+                    "rule.0.some_acl_name.manufacturer",
+                    RuleTargetHost::Ip("192.168.0.3".parse()?)
+                ),
+            ]
+        );
+        assert_eq!(x.rules.len(), 7);
+
+        // the rules of an ACE matching multiple hosts keep their names when the hosts are resolved in another order
+        let reordered_devices: Vec<DeviceWithRefs> = devices.iter().rev().cloned().collect();
+        let reordered_context = NetworkContext {
+            devices: &reordered_devices,
+            local_networks: &local_networks,
+            controllers: &[],
+            signature_policy: MudSignaturePolicy::Warn,
+            unsupported_device_policy: UnsupportedDevicePolicy::Allow,
+            exceptions: &[],
+        };
+        let y = convert_device_to_fw_rules(&device, &reordered_context);
+        assert_eq!(y.rules[1], x.rules[2]);
+        assert_eq!(y.rules[2], x.rules[1]);
+
+        // ipv6 ACLs only match the ipv6 addresses of devices and local networks
+        device.mud_data.as_mut().unwrap().acllist[0].acl_type = AclType::IPV6;
+        let z = convert_device_to_fw_rules(&device, &context);
+        assert_eq!(
+            z.rules[..2],
+            [
+                rule(
+                    "rule.0.some_acl_name.same_manufacturer",
+                    RuleTargetHost::Ip("fd00::4".parse()?)
+                ),
+                rule("rule.0.some_acl_name.model", RuleTargetHost::Ip("fd00::4".parse()?)),
+            ]
+        );
+        assert_eq!(z.rules.len(), 4);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_controller_matches() -> Result<()> {
+        let controller_ace = |name: &str, controller: Option<&str>, my_controller: bool| Ace {
+            name: name.to_string(),
+            action: AceAction::Accept,
+            matches: AceMatches {
+                protocol: None,
+                direction_initiated: None,
+                source_network: None,
+                destination_network: None,
+                source_dnsname: None,
+                destination_dnsname: None,
+                source_port: None,
+                destination_port: None,
+                icmp: None,
+                eth: None,
+                mud: Some(AceMudMatches {
+                    local_networks: false,
+                    same_manufacturer: false,
+                    manufacturer: None,
+                    model: None,
+                    controller: controller.map(String::from),
+                    my_controller,
+                }),
+            },
+        };
+
+        let mut device = device_with_mud_url(0, "https://lighting.example.com/lightbulb2000", "192.168.0.1");
+        device.mud_data = Some(MudData {
+            url: "https://lighting.example.com/lightbulb2000".to_string(),
+            masa_url: None,
+            mud_signature: None,
+            signature_status: None,
+            last_update: "some_last_update".to_string(),
+            systeminfo: None,
+            mfg_name: None,
+            model_name: None,
+            documentation: None,
+            is_supported: true,
+            cache_validity: None,
+            fetch_status: None,
+            parent_url: None,
+            ace_patches: Vec::new(),
+            expiration: Utc::now(),
+            acllist: vec![Acl {
+                name: "some_acl_name".to_string(),
+                packet_direction: AclDirection::ToDevice,
+                acl_type: AclType::IPV4,
+                ace: vec![
+                    controller_ace("controller", Some("urn:ietf:params:mud:dns"), false),
+                    controller_ace("my_controller", None, true),
+                    controller_ace("unknown_controller", Some("urn:ietf:params:mud:gateway"), false),
+                ],
+            }],
+            acl_override: Vec::default(),
+        });
+        let devices = vec![
+            device.clone(),
+            device_with_mud_url(1, "https://lighting.example.com/bridge", "192.168.0.2"),
+        ];
+        let controllers = vec![
+            ControllerMapping {
+                id: 1,
+                uri: "urn:ietf:params:mud:dns".to_string(),
+                device_id: None,
+                host: Some("dns.example.com".to_string()),
+            },
+            ControllerMapping {
+                id: 2,
+                uri: "https://lighting.example.com/lightbulb2000".to_string(),
+                device_id: Some(1),
+                host: None,
+            },
+        ];
+        let context = NetworkContext {
+            devices: &devices,
+            local_networks: &[],
+            controllers: &controllers,
+            signature_policy: MudSignaturePolicy::Warn,
+            unsupported_device_policy: UnsupportedDevicePolicy::Allow,
+            exceptions: &[],
+        };
+
+        let x = convert_device_to_fw_rules(&device, &context);
+
+        let rule = |name: &str, host: RuleTargetHost| {
+            FirewallRule::new(
+                RuleName::new(String::from(name)),
+                RuleTarget::new(Some(host), None),
+                RuleTarget::new(Some(RuleTargetHost::FirewallDevice), None),
+                Protocol::All,
+                Verdict::Accept,
+            )
+        };
+        assert_eq!(
+            x.rules[..2],
+            [
+                rule(
+                    "rule.0.some_acl_name.controller",
+                    RuleTargetHost::Hostname(String::from("dns.example.com"))
+                ),
+                rule(
+                    "rule.0.some_acl_name.my_controller",
+                    RuleTargetHost::Ip("192.168.0.2".parse()?)
+                ),
+            ]
+        );
+        assert_eq!(x.rules.len(), 4);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_device_side_matches() -> Result<()> {
+        let ace = |name: &str, action: AceAction, source_network: Option<&str>, source_dnsname: Option<&str>| Ace {
+            name: name.to_string(),
+            action,
+            matches: AceMatches {
+                protocol: Some(AceProtocol::Tcp),
+                direction_initiated: None,
+                source_network: source_network.map(String::from),
+                destination_network: Some(String::from("192.0.2.0/24")),
+                source_dnsname: source_dnsname.map(String::from),
+                destination_dnsname: None,
+                source_port: None,
+                destination_port: None,
+                icmp: None,
+                eth: None,
+                mud: None,
+            },
+        };
+
+        let mut device = device_with_mud_url(0, "https://lighting.example.com/lightbulb2000", "192.168.0.1");
+        device.mud_data = Some(MudData {
+            url: "https://lighting.example.com/lightbulb2000".to_string(),
+            masa_url: None,
+            mud_signature: None,
+            signature_status: None,
+            last_update: "some_last_update".to_string(),
+            systeminfo: None,
+            mfg_name: None,
+            model_name: None,
+            documentation: None,
+            is_supported: true,
+            cache_validity: None,
+            fetch_status: None,
+            parent_url: None,
+            ace_patches: Vec::new(),
+            expiration: Utc::now(),
+            acllist: vec![Acl {
+                name: "from_device_acl".to_string(),
+                packet_direction: AclDirection::FromDevice,
+                acl_type: AclType::IPV4,
+                ace: vec![
+                    ace("device_network", AceAction::Accept, Some("192.168.0.0/24"), None),
+                    ace("other_network", AceAction::Accept, Some("10.0.0.0/8"), None),
+                    ace(
+                        "accepted_device_name",
+                        AceAction::Accept,
+                        None,
+                        Some("lightbulb.example.com"),
+                    ),
+                    ace(
+                        "denied_device_name",
+                        AceAction::Deny,
+                        None,
+                        Some("lightbulb.example.com"),
+                    ),
+                ],
+            }],
+            acl_override: Vec::default(),
+        });
+
+        let x = convert_device_to_fw_rules(&device, &empty_context());
+
+        let rule = |name: &str, verdict: Verdict| {
+            FirewallRule::new(
+                RuleName::new(String::from(name)),
+                RuleTarget::new(Some(RuleTargetHost::FirewallDevice), None),
+                RuleTarget::new(Some(RuleTargetHost::Network("192.0.2.0/24".parse().unwrap())), None),
+                Protocol::Tcp,
+                verdict,
+            )
+        };
+        assert_eq!(
+            x.rules[..2],
+            [
+                rule("rule.0.from_device_acl.device_network", Verdict::Accept),
+                rule("rule.0.from_device_acl.denied_device_name", Verdict::Reject)
+            ]
+        );
+        assert_eq!(x.rules.len(), 4);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_any_host_aces() -> Result<()> {
+        let mut device =
+            device_with_mud_profile("tests/mud_tests/Amazon-Echo.json", "https://amazonecho.com/amazonecho")?;
+        let ace =
+            |name: &str, action: AceAction, protocol: Option<AceProtocol>, destination_port: Option<AcePort>| Ace {
+                name: name.to_string(),
+                action,
+                matches: AceMatches {
+                    protocol,
+                    direction_initiated: None,
+                    source_network: None,
+                    destination_network: None,
+                    source_dnsname: None,
+                    destination_dnsname: None,
+                    source_port: None,
+                    destination_port,
+                    icmp: None,
+                    eth: None,
+                    mud: None,
+                },
+            };
+        let mud_data = device.mud_data.as_mut().unwrap();
+        mud_data.acllist = vec![Acl {
+            name: "any_acl".to_string(),
+            packet_direction: AclDirection::FromDevice,
+            acl_type: AclType::IPV4,
+            ace: vec![
+                ace("deny_any", AceAction::Deny, None, None),
+                ace(
+                    "https_only",
+                    AceAction::Accept,
+                    Some(AceProtocol::Tcp),
+                    Some(AcePort::Single(443)),
+                ),
+            ],
+        }];
+        let local_networks: Vec<IpNetwork> = vec!["192.168.0.0/24".parse().unwrap()];
+        let mut context = empty_context();
+        context.local_networks = &local_networks;
+
+        let deny_any_rule = FirewallRule::new(
+            RuleName::new(String::from("rule.0.any_acl.deny_any")),
+            RuleTarget::new(Some(RuleTargetHost::FirewallDevice), None),
+            RuleTarget::new(None, None),
+            Protocol::All,
+            Verdict::Reject,
+        );
+        let https_rule = FirewallRule::new(
+            RuleName::new(String::from("rule.0.any_acl.https_only")),
+            RuleTarget::new(Some(RuleTargetHost::FirewallDevice), None),
+            RuleTarget::new(None, Some(String::from("443"))),
+            Protocol::Tcp,
+            Verdict::Accept,
+        );
+
+        // ACEs without remote matches match any host
+        let x = convert_device_to_fw_rules(&device, &context);
+        assert_eq!(x.rules[..2], [deny_any_rule.clone(), https_rule]);
+        assert_eq!(x.rules.len(), 4);
+
+        // accepting any host isn't local, so end-of-life devices only keep the denying rule
+        device.mud_data.as_mut().unwrap().is_supported = false;
+        context.unsupported_device_policy = UnsupportedDevicePolicy::LocalOnly;
+        let x = convert_device_to_fw_rules(&device, &context);
+        assert_eq!(x.rules[0], deny_any_rule);
+        assert_eq!(x.rules.len(), 3);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_direction_initiated() -> Result<()> {
+        let tcp_ace = |name: &str, packet_direction: AclDirection, direction_initiated: Option<AclDirection>| Ace {
+            name: name.to_string(),
+            action: AceAction::Accept,
+            matches: AceMatches {
+                protocol: Some(AceProtocol::Tcp),
+                direction_initiated,
+                source_network: None,
+                destination_network: None,
+                source_dnsname: match packet_direction {
+                    AclDirection::ToDevice => Some(String::from("www.example.test")),
+                    AclDirection::FromDevice => None,
+                },
+                destination_dnsname: match packet_direction {
+                    AclDirection::FromDevice => Some(String::from("www.example.test")),
+                    AclDirection::ToDevice => None,
+                },
+                source_port: None,
+                destination_port: None,
+                icmp: None,
+                eth: None,
+                mud: None,
+            },
+        };
+
+        let mut device = device_with_mud_url(0, "https://lighting.example.com/lightbulb2000", "192.168.0.1");
+        device.mud_data = Some(MudData {
+            url: "https://lighting.example.com/lightbulb2000".to_string(),
+            masa_url: None,
+            mud_signature: None,
+            signature_status: None,
+            last_update: "some_last_update".to_string(),
+            systeminfo: None,
+            mfg_name: None,
+            model_name: None,
+            documentation: None,
+            is_supported: true,
+            cache_validity: None,
+            fetch_status: None,
+            parent_url: None,
+            ace_patches: Vec::new(),
+            expiration: Utc::now(),
+            acllist: vec![
+                Acl {
+                    name: "from_device_acl".to_string(),
+                    packet_direction: AclDirection::FromDevice,
+                    acl_type: AclType::IPV4,
+                    ace: vec![
+                        tcp_ace(
+                            "initiated_from_device",
+                            AclDirection::FromDevice,
+                            Some(AclDirection::FromDevice),
+                        ),
+                        tcp_ace(
+                            "reply_from_device",
+                            AclDirection::FromDevice,
+                            Some(AclDirection::ToDevice),
+                        ),
+                    ],
+                },
+                Acl {
+                    name: "to_device_acl".to_string(),
+                    packet_direction: AclDirection::ToDevice,
+                    acl_type: AclType::IPV4,
+                    ace: vec![
+                        tcp_ace(
+                            "reply_to_device",
+                            AclDirection::ToDevice,
+                            Some(AclDirection::FromDevice),
+                        ),
+                        tcp_ace(
+                            "initiated_to_device",
+                            AclDirection::ToDevice,
+                            Some(AclDirection::ToDevice),
+                        ),
+                        tcp_ace("any_to_device", AclDirection::ToDevice, None),
+                    ],
+                },
+            ],
+            acl_override: Vec::default(),
+        });
+
+        let x = convert_device_to_fw_rules(&device, &empty_context());
+
+        let remote = || RuleTarget::new(Some(RuleTargetHost::Hostname(String::from("www.example.test"))), None);
+        let firewall_device = || RuleTarget::new(Some(RuleTargetHost::FirewallDevice), None);
+        let new_or_established = || vec![ConnectionState::New, ConnectionState::Established];
+        let established = || vec![ConnectionState::Established];
+        assert_eq!(
+            x.rules[..5],
+            [
+                FirewallRule::new(
+                    RuleName::new(String::from("rule.0.from_device_acl.initiated_from_device")),
+                    firewall_device(),
+                    remote(),
+                    Protocol::Tcp,
+                    Verdict::Accept,
+                )
+                .with_connection_states(new_or_established()),
+                FirewallRule::new(
+                    RuleName::new(String::from("rule.0.from_device_acl.reply_from_device")),
+                    firewall_device(),
+                    remote(),
+                    Protocol::Tcp,
+                    Verdict::Accept,
+                )
+                .with_connection_states(established()),
+                FirewallRule::new(
+                    RuleName::new(String::from("rule.0.to_device_acl.reply_to_device")),
+                    remote(),
+                    firewall_device(),
+                    Protocol::Tcp,
+                    Verdict::Accept,
+                )
+                .with_connection_states(established()),
+                FirewallRule::new(
+                    RuleName::new(String::from("rule.0.to_device_acl.initiated_to_device")),
+                    remote(),
+                    firewall_device(),
+                    Protocol::Tcp,
+                    Verdict::Accept,
+                )
+                .with_connection_states(new_or_established()),
+                FirewallRule::new(
+                    RuleName::new(String::from("rule.0.to_device_acl.any_to_device")),
+                    remote(),
+                    firewall_device(),
+                    Protocol::Tcp,
+                    Verdict::Accept,
+                ),
+            ]
+        );
+        assert_eq!(x.rules.len(), 7);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_port_conversion() {
+        assert_eq!(convert_port(&AcePort::Single(443)), vec!["443"]);
+        assert_eq!(convert_port(&AcePort::Range(9000, 9050)), vec!["9000:9050"]);
+        assert_eq!(convert_port(&AcePort::Lte(1023)), vec!["0:1023"]);
+        assert_eq!(convert_port(&AcePort::Gte(49152)), vec!["49152:65535"]);
+        assert_eq!(convert_port(&AcePort::Neq(80)), vec!["0:79", "81:65535"]);
+        assert_eq!(convert_port(&AcePort::Neq(0)), vec!["1:65535"]);
+    }
+
+    #[test]
+    fn test_rule_name_parts() {
+        assert_eq!(rule_name_part("from-ipv4-amazonecho_1"), "from-ipv4-amazonecho_1");
+        assert!(rule_name_part("override acl").starts_with("override_acl-"));
+        assert_ne!(rule_name_part("override acl"), rule_name_part("override.acl"));
+        assert_ne!(rule_name_part("override acl"), rule_name_part("override_acl"));
+        assert_eq!(rule_name_part("override acl"), rule_name_part("override acl"));
+    }
+
+    #[test]
+    fn test_network_conversion() {
+        assert_eq!(
+            convert_network("192.0.2.1/32".parse().unwrap()),
+            RuleTargetHost::Ip("192.0.2.1".parse().unwrap())
+        );
+        assert_eq!(
+            convert_network("192.0.2.0/24".parse().unwrap()),
+            RuleTargetHost::Network("192.0.2.0/24".parse().unwrap())
+        );
+        assert_eq!(
+            convert_network("ff00::/8".parse().unwrap()),
+            RuleTargetHost::Network("ff00::/8".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn test_local_hosts() {
+        let local_networks = ["192.168.0.0/16".parse().unwrap()];
+        assert!(is_local_host(
+            &RuleTargetHost::Network("192.168.1.0/24".parse().unwrap()),
+            &local_networks
+        ));
+        assert!(!is_local_host(
+            &RuleTargetHost::Network("192.0.0.0/8".parse().unwrap()),
+            &local_networks
+        ));
+        assert!(is_local_host(
+            &RuleTargetHost::Ip("192.168.1.1".parse().unwrap()),
+            &local_networks
+        ));
+        assert!(!is_local_host(
+            &RuleTargetHost::Hostname(String::from("192.168.1.0/24")),
+            &local_networks
+        ));
+    }
+
+    #[test]
+    fn test_other_protocols() -> Result<()> {
+        let protocol_ace = |name: &str, action: AceAction, protocol: u32, icmp: Option<AceIcmpMatches>| Ace {
+            name: name.to_string(),
+            action,
+            matches: AceMatches {
+                protocol: Some(AceProtocol::Protocol(protocol)),
+                direction_initiated: None,
+                source_network: None,
+                destination_network: None,
+                source_dnsname: None,
+                destination_dnsname: Some(String::from("www.example.test")),
+                source_port: None,
+                destination_port: None,
+                icmp,
+                eth: None,
+                mud: None,
+            },
+        };
+
+        let echo_request = || AceIcmpMatches {
+            icmp_type: Some(8),
+            code: Some(0),
+        };
+
+        let mut device = device_with_mud_url(0, "https://lighting.example.com/lightbulb2000", "192.168.0.1");
+        device.mud_data = Some(MudData {
+            url: "https://lighting.example.com/lightbulb2000".to_string(),
+            masa_url: None,
+            mud_signature: None,
+            signature_status: None,
+            last_update: "some_last_update".to_string(),
+            systeminfo: None,
+            mfg_name: None,
+            model_name: None,
+            documentation: None,
+            is_supported: true,
+            cache_validity: None,
+            fetch_status: None,
+            parent_url: None,
+            ace_patches: Vec::new(),
+            expiration: Utc::now(),
+            acllist: vec![Acl {
+                name: "some_acl_name".to_string(),
+                packet_direction: AclDirection::FromDevice,
+                acl_type: AclType::IPV4,
+                ace: vec![
+                    protocol_ace("accepted_icmp", AceAction::Accept, 1, Some(echo_request())),
+                    protocol_ace("denied_icmp", AceAction::Deny, 1, Some(echo_request())),
+                    protocol_ace("accepted_icmpv6", AceAction::Accept, 58, None),
+                    protocol_ace("accepted_gre", AceAction::Accept, 47, None),
+                    protocol_ace("invalid_protocol", AceAction::Accept, 256, None),
+                ],
+            }],
+            acl_override: Vec::default(),
+        });
+
+        let x = convert_device_to_fw_rules(&device, &empty_context());
+
+        let rule = |name: &str, protocol: Protocol, verdict: Verdict| {
+            FirewallRule::new(
+                RuleName::new(format!("rule.0.some_acl_name.{}", name)),
+                RuleTarget::new(Some(RuleTargetHost::FirewallDevice), None),
+                RuleTarget::new(Some(RuleTargetHost::Hostname(String::from("www.example.test"))), None),
+                protocol,
+                verdict,
+            )
+        };
+        let echo_request_protocol = || Protocol::Icmp {
+            icmp_type: Some(8),
+            code: Some(0),
+        };
+        assert_eq!(
+            x.rules[..4],
+            [
+                rule("accepted_icmp", echo_request_protocol(), Verdict::Accept),
+                rule("denied_icmp", echo_request_protocol(), Verdict::Reject),
+                rule(
+                    "accepted_icmpv6",
+                    Protocol::Icmpv6 {
+                        icmp_type: None,
+                        code: None
+                    },
+                    Verdict::Accept
+                ),
+                rule("accepted_gre", Protocol::Number(47), Verdict::Accept),
+            ]
+        );
+        assert_eq!(x.rules.len(), 6);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_ethernet_acls() -> Result<()> {
+        let eth_ace = |name: &str, action: AceAction, eth: AceEthMatches| Ace {
+            name: name.to_string(),
+            action,
+            matches: AceMatches {
+                protocol: None,
+                direction_initiated: None,
+                source_network: None,
+                destination_network: None,
+                source_dnsname: None,
+                destination_dnsname: None,
+                source_port: None,
+                destination_port: None,
+                icmp: None,
+                eth: Some(eth),
+                mud: None,
+            },
+        };
+
+        let mut device =
+            device_with_mud_profile("tests/mud_tests/Amazon-Echo.json", "https://amazonecho.com/amazonecho")?;
+        let mud_data = device.mud_data.as_mut().unwrap();
+        mud_data.acllist.retain(|acl| acl.acl_type == AclType::Ethernet);
+        assert!(!mud_data.acllist.is_empty());
+        mud_data.acllist.push(Acl {
+            name: "mac_acl".to_string(),
+            packet_direction: AclDirection::FromDevice,
+            acl_type: AclType::Ethernet,
+            ace: vec![
+                eth_ace(
+                    "to_gateway",
+                    AceAction::Accept,
+                    AceEthMatches {
+                        ethertype: None,
+                        source_mac_address: None,
+                        destination_mac_address: Some("11:22:33:44:55:66".to_string()),
+                    },
+                ),
+                eth_ace(
+                    "from_other_device",
+                    AceAction::Accept,
+                    AceEthMatches {
+                        ethertype: None,
+                        source_mac_address: Some("00:00:00:00:00:01".to_string()),
+                        destination_mac_address: None,
+                    },
+                ),
+                eth_ace(
+                    "from_own_mac",
+                    AceAction::Deny,
+                    AceEthMatches {
+                        ethertype: Some(0x86dd),
+                        source_mac_address: Some("aa:bb:cc:dd:ee:ff".to_string()),
+                        destination_mac_address: None,
+                    },
+                ),
+            ],
+        });
+        let local_networks: Vec<IpNetwork> = vec!["192.168.0.0/24".parse().unwrap()];
+        let context = NetworkContext {
+            devices: &[],
+            local_networks: &local_networks,
+            controllers: &[],
+            signature_policy: MudSignaturePolicy::Warn,
+            unsupported_device_policy: UnsupportedDevicePolicy::Allow,
+            exceptions: &[],
+        };
+
+        let x = convert_device_to_fw_rules(&device, &context);
+
+        let rule = |name: &str, remote_host: Option<RuleTargetHost>, ethertype: Option<u16>, verdict: Verdict| {
+            FirewallRule::new(
+                RuleName::new(String::from(name)),
+                RuleTarget::new(Some(RuleTargetHost::FirewallDevice), None),
+                RuleTarget::new(remote_host, None),
+                Protocol::Ethernet { ethertype },
+                verdict,
+            )
+        };
+        assert_eq!(
+            x.rules[..4],
+            [
+                rule(
+                    "rule.0.from-ethernet-amazonecho.from-ethernet-amazonecho-0",
+                    None,
+                    Some(0x0006),
+                    Verdict::Accept
+                ),
+                rule(
+                    "rule.0.from-ethernet-amazonecho.from-ethernet-amazonecho-1",
+                    None,
+                    Some(0x888e),
+                    Verdict::Accept
+                ),
+                rule(
+                    "rule.0.mac_acl.to_gateway",
+                    Some(RuleTargetHost::Mac(
+                        "11:22:33:44:55:66".parse::<MacAddr>().unwrap().into()
+                    )),
+                    None,
+                    Verdict::Accept
+                ),
+                rule("rule.0.mac_acl.from_own_mac", None, Some(0x86dd), Verdict::Reject),
+            ]
+        );
+        assert_eq!(x.rules.len(), 6);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_split_port_ranges() -> Result<()> {
+        let mut device = device_with_mud_url(0, "https://lighting.example.com/lightbulb2000", "192.168.0.1");
+        device.mud_data = Some(MudData {
+            url: "https://lighting.example.com/lightbulb2000".to_string(),
+            masa_url: None,
+            mud_signature: None,
+            signature_status: None,
+            last_update: "some_last_update".to_string(),
+            systeminfo: None,
+            mfg_name: None,
+            model_name: None,
+            documentation: None,
+            is_supported: true,
+            cache_validity: None,
+            fetch_status: None,
+            parent_url: None,
+            ace_patches: Vec::new(),
+            expiration: Utc::now(),
+            acllist: vec![Acl {
+                name: "some_acl_name".to_string(),
+                packet_direction: AclDirection::FromDevice,
+                acl_type: AclType::IPV4,
+                ace: vec![Ace {
+                    name: "some_ace_name".to_string(),
+                    action: AceAction::Accept,
+                    matches: AceMatches {
+                        protocol: Some(AceProtocol::Udp),
+                        direction_initiated: None,
+                        source_network: None,
+                        destination_network: None,
+                        source_dnsname: None,
+                        destination_dnsname: Some(String::from("www.example.test")),
+                        source_port: Some(AcePort::Gte(49152)),
+                        destination_port: Some(AcePort::Neq(53)),
+                        icmp: None,
+                        eth: None,
+                        mud: None,
+                    },
+                }],
+            }],
+            acl_override: Vec::default(),
+        });
+
+        let x = convert_device_to_fw_rules(&device, &empty_context());
+
+        let port_rule_name = |destination_port: &str| {
+            target_rule_name(
+                "rule.0.some_acl_name.some_ace_name",
+                Some(&RuleTargetHost::Hostname(String::from("www.example.test"))),
+                Some("49152:65535"),
+                Some(destination_port),
+            )
+        };
+        let rule = |name: &str, destination_port: &str| {
+            FirewallRule::new(
+                RuleName::new(String::from(name)),
+                RuleTarget::new(Some(RuleTargetHost::FirewallDevice), Some(String::from("49152:65535"))),
+                RuleTarget::new(
+                    Some(RuleTargetHost::Hostname(String::from("www.example.test"))),
+                    Some(String::from(destination_port)),
+                ),
+                Protocol::Udp,
+                Verdict::Accept,
+            )
+        };
+        assert_eq!(
+            x.rules[..2],
+            [
+                rule(&port_rule_name("0:52"), "0:52"),
+                rule(&port_rule_name("54:65535"), "54:65535")
+            ]
+        );
+        assert_eq!(x.rules.len(), 4);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_amazon_echo_networks() -> Result<()> {
+        let device = device_with_mud_profile("tests/mud_tests/Amazon-Echo.json", "https://amazonecho.com/amazonecho")?;
+
+        let x = convert_device_to_fw_rules(&device, &empty_context());
+
+        assert!(x.rules.contains(&FirewallRule::new(
+            RuleName::new(String::from("rule.0.from-ipv4-amazonecho.from-ipv4-amazonecho-13")),
+            RuleTarget::new(Some(RuleTargetHost::FirewallDevice), None),
+            RuleTarget::new(
+                Some(RuleTargetHost::Ip("208.67.220.220".parse().unwrap())),
+                Some(String::from("53"))
+            ),
+            Protocol::Udp,
+            Verdict::Accept,
+        )));
+        assert!(x.rules.contains(&FirewallRule::new(
+            RuleName::new(String::from("rule.0.to-ipv4-amazonecho.to-ipv4-amazonecho-10")),
+            RuleTarget::new(
+                Some(RuleTargetHost::Ip("208.67.220.220".parse().unwrap())),
+                Some(String::from("53"))
+            ),
+            RuleTarget::new(Some(RuleTargetHost::FirewallDevice), None),
+            Protocol::Udp,
+            Verdict::Accept,
+        )));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_amazon_echo_ports() -> Result<()> {
+        let device = device_with_mud_profile("tests/mud_tests/Amazon-Echo.json", "https://amazonecho.com/amazonecho")?;
+
+        let x = convert_device_to_fw_rules(&device, &empty_context());
+
+        assert!(x.rules.contains(
+            &FirewallRule::new(
+                RuleName::new(String::from("rule.0.from-ipv4-amazonecho.from-ipv4-amazonecho-0")),
+                RuleTarget::new(Some(RuleTargetHost::FirewallDevice), None),
+                RuleTarget::new(
+                    Some(RuleTargetHost::Hostname(String::from("dcape-na.amazon.com"))),
+                    Some(String::from("443"))
+                ),
+                Protocol::Tcp,
+                Verdict::Accept,
+            )
+            .with_connection_states(vec![ConnectionState::New, ConnectionState::Established])
+        ));
+        assert!(x.rules.contains(&FirewallRule::new(
+            RuleName::new(String::from("rule.0.from-ipv4-amazonecho.from-ipv4-amazonecho-2")),
+            RuleTarget::new(Some(RuleTargetHost::FirewallDevice), None),
+            RuleTarget::new(
+                Some(RuleTargetHost::Hostname(String::from("3.north-america.pool.ntp.org"))),
+                Some(String::from("123"))
+            ),
+            Protocol::Udp,
+            Verdict::Accept,
+        )));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_ring_doorbell_ports() -> Result<()> {
+        let device = device_with_mud_profile(
+            "tests/mud_tests/Ring-Doorbell.json",
+            "https://ringdoorbell.com/ringdoorbell",
+        )?;
+
+        let x = convert_device_to_fw_rules(&device, &empty_context());
+
+        assert!(x.rules.contains(
+            &FirewallRule::new(
+                RuleName::new(String::from("rule.0.from-ipv4-ringdoorbell.from-ipv4-ringdoorbell-3")),
+                RuleTarget::new(Some(RuleTargetHost::FirewallDevice), None),
+                RuleTarget::new(
+                    Some(RuleTargetHost::Hostname(String::from("wu.ring.com"))),
+                    Some(String::from("443"))
+                ),
+                Protocol::Tcp,
+                Verdict::Accept,
+            )
+            .with_connection_states(vec![ConnectionState::New, ConnectionState::Established])
+        ));
+        assert!(x.rules.contains(&FirewallRule::new(
+            RuleName::new(String::from("rule.0.to-ipv4-ringdoorbell.to-ipv4-ringdoorbell-0")),
+            RuleTarget::new(
+                Some(RuleTargetHost::Hostname(String::from("fw.ring.com"))),
+                Some(String::from("80"))
+            ),
+            RuleTarget::new(Some(RuleTargetHost::FirewallDevice), None),
+            Protocol::Tcp,
+            Verdict::Accept,
+        )));
+        Ok(())
+    }
+
+    #[test]
+    fn test_signature_quarantine() -> Result<()> {
+        let mut device =
+            device_with_mud_profile("tests/mud_tests/Amazon-Echo.json", "https://amazonecho.com/amazonecho")?;
+        let mud_data = device.mud_data.as_mut().unwrap();
+        mud_data.signature_status = Some(MudSignatureStatus::Unsigned);
+        mud_data.acl_override = vec![Acl {
+            name: "override_acl".to_string(),
+            packet_direction: AclDirection::FromDevice,
+            acl_type: AclType::IPV4,
+            ace: vec![Ace {
+                name: "override_ace".to_string(),
+                action: AceAction::Accept,
+                matches: AceMatches {
+                    protocol: Some(AceProtocol::Tcp),
+                    direction_initiated: None,
+                    source_network: None,
+                    destination_network: None,
+                    source_dnsname: None,
+                    destination_dnsname: Some(String::from("www.example.test")),
+                    source_port: None,
+                    destination_port: None,
+                    icmp: None,
+                    eth: None,
+                    mud: None,
+                },
+            }],
+        }];
+        let mut context = empty_context();
+
+        // warn accepts the MUD-File
+        assert!(convert_device_to_fw_rules(&device, &context).rules.len() > 3);
+
+        // quarantine only keeps the overrides and the default rules
+        context.signature_policy = MudSignaturePolicy::Quarantine;
+        let x = convert_device_to_fw_rules(&device, &context);
+        assert_eq!(
+            x.rules,
+            vec![
+                FirewallRule::new(
+                    RuleName::new(String::from("rule.0.override_acl.override_ace")),
+                    RuleTarget::new(Some(RuleTargetHost::FirewallDevice), None),
+                    RuleTarget::new(Some(RuleTargetHost::Hostname(String::from("www.example.test"))), None),
+                    Protocol::Tcp,
+                    Verdict::Accept,
+                ),
+                FirewallRule::new(
+                    RuleName::new(String::from("rule.0.default-out")),
+                    RuleTarget::new(Some(RuleTargetHost::FirewallDevice), None),
+                    RuleTarget::new(None, None),
+                    Protocol::All,
+                    Verdict::Reject,
+                ),
+                FirewallRule::new(
+                    RuleName::new(String::from("rule.0.default-in")),
+                    RuleTarget::new(None, None),
+                    RuleTarget::new(Some(RuleTargetHost::FirewallDevice), None),
+                    Protocol::All,
+                    Verdict::Reject,
+                ),
+            ]
+        );
+
+        // valid signatures are never quarantined
+        device.mud_data.as_mut().unwrap().signature_status = Some(MudSignatureStatus::Valid);
+        assert!(convert_device_to_fw_rules(&device, &context).rules.len() > 3);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_unsupported_device_policy() -> Result<()> {
+        let mut device =
+            device_with_mud_profile("tests/mud_tests/Amazon-Echo.json", "https://amazonecho.com/amazonecho")?;
+        let ace = |name: &str, destination_dnsname: &str| Ace {
+            name: name.to_string(),
+            action: AceAction::Accept,
+            matches: AceMatches {
+                protocol: Some(AceProtocol::Tcp),
+                direction_initiated: None,
+                source_network: None,
+                destination_network: None,
+                source_dnsname: None,
+                destination_dnsname: Some(destination_dnsname.to_string()),
+                source_port: None,
+                destination_port: None,
+                icmp: None,
+                eth: None,
+                mud: None,
+            },
+        };
+        let mud_data = device.mud_data.as_mut().unwrap();
+        mud_data.is_supported = false;
+        mud_data.acllist = vec![Acl {
+            name: "mud_acl".to_string(),
+            packet_direction: AclDirection::FromDevice,
+            acl_type: AclType::IPV4,
+            ace: vec![ace("local_ace", "192.168.0.10"), ace("remote_ace", "www.example.test")],
+        }];
+        let local_networks: Vec<IpNetwork> = vec!["192.168.0.0/24".parse().unwrap()];
+        let mut context = empty_context();
+        context.local_networks = &local_networks;
+
+        let rule = |name: &str, host: RuleTargetHost| {
+            FirewallRule::new(
+                RuleName::new(name.to_string()),
+                RuleTarget::new(Some(RuleTargetHost::FirewallDevice), None),
+                RuleTarget::new(Some(host), None),
+                Protocol::Tcp,
+                Verdict::Accept,
+            )
+        };
+        let local_rule = || {
+            rule(
+                "rule.0.mud_acl.local_ace",
+                RuleTargetHost::Ip("192.168.0.10".parse().unwrap()),
+            )
+        };
+        let remote_rule = || {
+            rule(
+                "rule.0.mud_acl.remote_ace",
+                RuleTargetHost::Hostname("www.example.test".to_string()),
+            )
+        };
+
+        // allow applies the MUD-File as usual
+        let x = convert_device_to_fw_rules(&device, &context);
+        assert_eq!(x.rules[..2], [local_rule(), remote_rule()]);
+
+        // local only drops the rules to hosts outside of the local networks
+        context.unsupported_device_policy = UnsupportedDevicePolicy::LocalOnly;
+        let x = convert_device_to_fw_rules(&device, &context);
+        assert_eq!(x.rules.len(), 3);
+        assert_eq!(x.rules[0], local_rule());
+
+        // block only keeps the default rules
+        context.unsupported_device_policy = UnsupportedDevicePolicy::Block;
+        assert_eq!(convert_device_to_fw_rules(&device, &context).rules.len(), 2);
+
+        // the policy doesn't affect supported devices
+        device.mud_data.as_mut().unwrap().is_supported = true;
+        assert_eq!(convert_device_to_fw_rules(&device, &context).rules.len(), 4);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_device_overrides() -> Result<()> {
+        let mut device =
+            device_with_mud_profile("tests/mud_tests/Amazon-Echo.json", "https://amazonecho.com/amazonecho")?;
+        let acl = |name: &str, destination_dnsname: &str| Acl {
+            name: name.to_string(),
+            packet_direction: AclDirection::FromDevice,
+            acl_type: AclType::IPV4,
+            ace: vec![Ace {
+                name: format!("{}_ace", name),
+                action: AceAction::Accept,
+                matches: AceMatches {
+                    protocol: Some(AceProtocol::Tcp),
+                    direction_initiated: None,
+                    source_network: None,
+                    destination_network: None,
+                    source_dnsname: None,
+                    destination_dnsname: Some(destination_dnsname.to_string()),
+                    source_port: None,
+                    destination_port: None,
+                    icmp: None,
+                    eth: None,
+                    mud: None,
+                },
+            }],
+        };
+        let mud_data = device.mud_data.as_mut().unwrap();
+        mud_data.acllist = vec![acl("mud_acl", "mud.example.test")];
+        mud_data.acl_override = vec![acl("override_acl", "profile.example.test")];
+        let context = empty_context();
+        let rule = |name: &str, host: &str| {
+            FirewallRule::new(
+                RuleName::new(name.to_string()),
+                RuleTarget::new(Some(RuleTargetHost::FirewallDevice), None),
+                RuleTarget::new(Some(RuleTargetHost::Hostname(host.to_string())), None),
+                Protocol::Tcp,
+                Verdict::Accept,
+            )
+        };
+        let x = convert_device_to_fw_rules(&device, &context);
+        assert_eq!(x.rules.len(), 4);
+        assert_eq!(
+            x.rules[..2],
+            [
+                rule("rule.0.mud_acl.mud_acl_ace", "mud.example.test"),
+                rule("rule.0.override_acl.override_acl_ace", "profile.example.test")
+            ]
+        );
+
+        // the overrides of the device apply after the overrides of the profile
+        device.inner.acl_override = vec![
+            acl("override_acl", "device.example.test"),
+            acl("device_acl", "other.example.test"),
+        ];
+        let x = convert_device_to_fw_rules(&device, &context);
+        assert_eq!(x.rules.len(), 5);
+        assert_eq!(
+            x.rules[..3],
+            [
+                rule("rule.0.mud_acl.mud_acl_ace", "mud.example.test"),
+                rule("rule.0.override_acl.override_acl_ace", "device.example.test"),
+                rule("rule.0.device_acl.device_acl_ace", "other.example.test"),
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_blocked_authority() {
+        // devices of blocked authorities have a MUD-URL, but no MUD-Profile
+        let mut device = device_with_mud_url(0, "https://blocked.example.test/device.json", "192.168.0.2");
+        device.inner.acl_override = vec![Acl {
+            name: "device_acl".to_string(),
+            packet_direction: AclDirection::FromDevice,
+            acl_type: AclType::IPV4,
+            ace: vec![Ace {
+                name: "device_ace".to_string(),
+                action: AceAction::Accept,
+                matches: AceMatches {
+                    protocol: Some(AceProtocol::Tcp),
+                    direction_initiated: None,
+                    source_network: None,
+                    destination_network: None,
+                    source_dnsname: None,
+                    destination_dnsname: Some("www.example.test".to_string()),
+                    source_port: None,
+                    destination_port: None,
+                    icmp: None,
+                    eth: None,
+                    mud: None,
+                },
+            }],
+        }];
+        let x = convert_device_to_fw_rules(&device, &empty_context());
+        assert_eq!(
+            x.rules,
+            vec![
+                FirewallRule::new(
+                    RuleName::new(String::from("rule.0.device_acl.device_ace")),
+                    RuleTarget::new(Some(RuleTargetHost::FirewallDevice), None),
+                    RuleTarget::new(Some(RuleTargetHost::Hostname(String::from("www.example.test"))), None),
+                    Protocol::Tcp,
+                    Verdict::Accept,
+                ),
+                FirewallRule::new(
+                    RuleName::new(String::from("rule.0.default-out")),
+                    RuleTarget::new(Some(RuleTargetHost::FirewallDevice), None),
+                    RuleTarget::new(None, None),
+                    Protocol::All,
+                    Verdict::Reject,
+                ),
+                FirewallRule::new(
+                    RuleName::new(String::from("rule.0.default-in")),
+                    RuleTarget::new(None, None),
+                    RuleTarget::new(Some(RuleTargetHost::FirewallDevice), None),
+                    Protocol::All,
+                    Verdict::Reject,
+                ),
+            ]
+        );
+
+        // devices without a MUD-URL aren't restricted
+        device.inner.mud_url = None;
+        assert!(convert_device_to_fw_rules(&device, &empty_context()).rules.is_empty());
+    }
+
+    #[test]
+    fn test_policy_exceptions() -> Result<()> {
+        let mut device =
+            device_with_mud_profile("tests/mud_tests/Amazon-Echo.json", "https://amazonecho.com/amazonecho")?;
+        device.mud_data.as_mut().unwrap().acllist = Vec::new();
+        device.inner.room_id = Some(1);
+        let exception = |device_id: Option<i64>, room_id: Option<i64>, expires_at: NaiveDateTime| PolicyException {
+            id: 0,
+            device_id,
+            room_id,
+            mud_url: None,
+            acls: vec![Acl {
+                name: "support_acl".to_string(),
+                packet_direction: AclDirection::FromDevice,
+                acl_type: AclType::IPV4,
+                ace: vec![Ace {
+                    name: "support_ace".to_string(),
+                    action: AceAction::Accept,
+                    matches: AceMatches {
+                        protocol: Some(AceProtocol::Tcp),
+                        direction_initiated: None,
+                        source_network: None,
+                        destination_network: None,
+                        source_dnsname: None,
+                        destination_dnsname: Some("support.example.test".to_string()),
+                        source_port: None,
+                        destination_port: None,
+                        icmp: None,
+                        eth: None,
+                        mud: None,
+                    },
+                }],
+            }],
+            reason: "remote support".to_string(),
+            created_by: "admin".to_string(),
+            created_at: Utc::now().naive_utc(),
+            expires_at,
+            retired_at: None,
+        };
+        let support_rule = FirewallRule::new(
+            RuleName::new(String::from("rule.0.exception-0.support_acl.support_ace")),
+            RuleTarget::new(Some(RuleTargetHost::FirewallDevice), None),
+            RuleTarget::new(
+                Some(RuleTargetHost::Hostname(String::from("support.example.test"))),
+                None,
+            ),
+            Protocol::Tcp,
+            Verdict::Accept,
+        );
+        let tomorrow = Utc::now().naive_utc() + chrono::Duration::days(1);
+        let yesterday = Utc::now().naive_utc() - chrono::Duration::days(1);
+        let mut context = empty_context();
+
+        // active exceptions of the device or its room apply
+        for exceptions in &[
+            vec![exception(Some(0), None, tomorrow)],
+            vec![exception(None, Some(1), tomorrow)],
+        ] {
+            context.exceptions = exceptions;
+            let x = convert_device_to_fw_rules(&device, &context);
+            assert_eq!(x.rules.len(), 3);
+            assert_eq!(x.rules[0], support_rule);
+        }
+
+        // expired exceptions and exceptions of other devices don't
+        for exceptions in &[
+            vec![exception(Some(0), None, yesterday)],
+            vec![exception(Some(1), Some(2), tomorrow)],
+        ] {
+            context.exceptions = exceptions;
+            assert_eq!(convert_device_to_fw_rules(&device, &context).rules.len(), 2);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_stable_rule_names() -> Result<()> {
+        let mut device =
+            device_with_mud_profile("tests/mud_tests/Amazon-Echo.json", "https://amazonecho.com/amazonecho")?;
+        let ace = |name: &str| Ace {
+            name: name.to_string(),
+            action: AceAction::Accept,
+            matches: AceMatches {
+                protocol: Some(AceProtocol::Tcp),
+                direction_initiated: None,
+                source_network: None,
+                destination_network: None,
+                source_dnsname: None,
+                destination_dnsname: Some("www.example.test".to_string()),
+                source_port: None,
+                destination_port: None,
+                icmp: None,
+                eth: None,
+                mud: None,
+            },
+        };
+        let mut override_acl = Acl {
+            name: "override acl".to_string(),
+            packet_direction: AclDirection::FromDevice,
+            acl_type: AclType::IPV4,
+            ace: vec![ace("override_ace")],
+        };
+        device.mud_data.as_mut().unwrap().acl_override = vec![override_acl.clone()];
+        let context = empty_context();
+
+        let (x, provenance) = convert_device_to_fw_rules_with_provenance(&device, &context);
+        assert_eq!(x.rules.len(), provenance.len());
+        assert_eq!(
+            provenance[0],
+            RuleProvenance {
+                rule_name: "rule.0.from-ipv4-amazonecho.from-ipv4-amazonecho-0".to_string(),
+                device_id: 0,
+                mud_url: Some("https://amazonecho.com/amazonecho".to_string()),
+                acl_name: Some("from-ipv4-amazonecho".to_string()),
+                ace_name: Some("from-ipv4-amazonecho-0".to_string()),
+                source: RuleSource::Mud,
+                is_override: false,
+                exception_id: None,
+            }
+        );
+        let override_provenance = provenance
+            .iter()
+            .find(|p| p.source == RuleSource::ProfileOverride)
+            .unwrap();
+        assert_eq!(
+            override_provenance.rule_name,
+            format!("rule.0.{}.override_ace", rule_name_part("override acl"))
+        );
+        assert!(override_provenance.is_override);
+        assert_eq!(provenance.last().unwrap().rule_name, "rule.0.default-in");
+        assert_eq!(provenance.last().unwrap().source, RuleSource::Default);
+
+        // inserting an ACE doesn't rename the rules of the other ACEs
+        override_acl.ace.insert(0, ace("inserted_ace"));
+        device.mud_data.as_mut().unwrap().acl_override = vec![override_acl];
+        let (y, _) = convert_device_to_fw_rules_with_provenance(&device, &context);
+        assert_eq!(y.rules.len(), x.rules.len() + 1);
+        assert!(x.rules.iter().all(|rule| y.rules.contains(rule)));
+
+        Ok(())
+    }
 }
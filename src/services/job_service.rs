@@ -8,21 +8,19 @@ use tokio::time::sleep;
 
 use crate::{
     db::DbConnection,
-    services::{acme_service, mud_service},
+    services::{acme_service, mud_service, policy_exception_service},
 };
 
-/// Create new job scheduler that update the expired mud profiles.
+/// Create new job scheduler that rescans the MUD repository and starts the queues refreshing the mud profiles and
+/// retiring expired policy exceptions.
 /// conn is the current database connection.
 pub async fn start_jobs(conn: DbConnection) {
     info!("Start scheduler");
+    tokio::spawn(mud_service::refresh::run_refresh_queue(conn.clone()));
+    tokio::spawn(policy_exception_service::run_expiry_queue(conn));
     let mut scheduler = Scheduler::new();
-    scheduler.every(1.hour()).run(move || {
-        let conn = conn.clone();
-        tokio::spawn(async move {
-            if let Err(e) = mud_service::update_outdated_profiles(&conn).await {
-                warn!("Failed to update outdated profiles: {:?}", e);
-            }
-        });
+    scheduler.every(10.minutes()).run(|| {
+        tokio::task::spawn_blocking(mud_service::repository::rescan_repository);
     });
     scheduler.every(6.hours()).run(|| {
         tokio::spawn(async {
@@ -3,6 +3,7 @@
 
 pub mod acme_service;
 pub mod config_service;
+pub mod controller_mapping_service;
 pub mod device_service;
 pub mod enforcer_service;
 pub mod firewall_configuration_service;
@@ -10,6 +11,7 @@ pub mod job_service;
 pub mod log_service;
 pub mod mud_service;
 pub mod neo4things_service;
+pub mod policy_exception_service;
 pub mod role_service;
 pub mod room_service;
 pub mod user_config_service;
@@ -0,0 +1,369 @@
+// Copyright 2020-2021, Benjamin Ludewig, Florian Bonetti, Jeffrey Munstermann, Luca Nittscher, Hugo Damer, Michael Bach
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use paperclip::actix::Apiv2Schema;
+
+use crate::{
+    db::DbConnection,
+    error::Result,
+    models::{Ace, AcePatch, AcePatchOperation, Acl, MudData},
+    services::mud_service::{get_effective_mud, inheritance, source::configured_sources},
+};
+
+/// An `AcePatch` that doesn't apply cleanly, because the patched ACLs changed since the patch was made.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Apiv2Schema)]
+pub struct AcePatchConflict {
+    pub acl_name: String,
+    pub ace_name: String,
+    pub reason: AcePatchConflictReason,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Apiv2Schema)]
+pub enum AcePatchConflictReason {
+    /// The patched ACL doesn't exist, the patch is skipped.
+    AclMissing,
+    /// The ACE to replace or remove doesn't exist, the patch is skipped.
+    AceMissing,
+    /// The ACE to add exists already, it is replaced instead.
+    AceExists,
+    /// The ACE to replace or remove changed since the patch was made, the patch is applied anyway.
+    AceChanged,
+}
+
+/// The ACLs of a MUD-Profile after applying its `ace_patches`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PatchedAcls {
+    /// The parent MUD-Profile the patched ACLs are inherited from, as it applies to devices.
+    pub parent: Option<MudData>,
+    pub acls: Vec<Acl>,
+    pub conflicts: Vec<AcePatchConflict>,
+}
+
+/// Replaces the ACLs of the MUD-Profile with the patched ones, inheriting them from its parent if it has one.
+/// Whether the device is supported and the signature status are taken from the parent, since the ACLs come from it.
+pub async fn apply_patches(mut mud_data: MudData, pool: &DbConnection) -> Result<MudData> {
+    if mud_data.parent_url.is_none() && mud_data.ace_patches.is_empty() {
+        return Ok(mud_data);
+    }
+    let patched = get_patched_acls(&mud_data, pool).await?;
+    mud_data.acllist = patched.acls;
+    if let Some(parent) = patched.parent {
+        mud_data.is_supported = parent.is_supported;
+        mud_data.signature_status = parent.signature_status;
+    }
+    Ok(mud_data)
+}
+
+/// Evaluates the patched ACLs of the MUD-Profile, whose ACLs must not be patched yet.
+/// The parent is evaluated whenever the MUD-Profile is, so changes of the parent apply to it immediately.
+pub async fn get_patched_acls(mud_data: &MudData, pool: &DbConnection) -> Result<PatchedAcls> {
+    let parent = inheritance::get_parent(mud_data, pool).await?;
+    let upstream = match &parent {
+        Some(parent) => inheritance::inheritable_acls(parent),
+        None => mud_data.acllist.clone(),
+    };
+    let (acls, conflicts) = apply_ace_patches(&upstream, &mud_data.ace_patches);
+    for conflict in &conflicts {
+        debug!(
+            "Patch of ACE {} of ACL {} in {} conflicts with the upstream ACLs: {:?}",
+            conflict.ace_name, conflict.acl_name, mud_data.url, conflict.reason
+        );
+    }
+    Ok(PatchedAcls {
+        parent,
+        acls,
+        conflicts,
+    })
+}
+
+/// Returns the ACLs the ACE patches of the MUD-Profile apply to, i.e. the ones of its parent or its own ones.
+pub async fn get_upstream_acls(url: &str, pool: &DbConnection) -> Result<Vec<Acl>> {
+    let mud_data = get_effective_mud(url, &configured_sources(), pool).await?;
+    Ok(match inheritance::get_parent(&mud_data, pool).await? {
+        Some(parent) => inheritance::inheritable_acls(&parent),
+        None => mud_data.acllist,
+    })
+}
+
+/// Returns the ACE patches of the MUD-Profile that don't apply cleanly to the current upstream ACLs.
+pub async fn get_conflicts(url: &str, pool: &DbConnection) -> Result<Vec<AcePatchConflict>> {
+    let mud_data = get_effective_mud(url, &configured_sources(), pool).await?;
+    Ok(get_patched_acls(&mud_data, pool).await?.conflicts)
+}
+
+/// Applies the patches to the ACLs in order. Returns the patched ACLs and the patches that didn't apply cleanly.
+pub fn apply_ace_patches(acls: &[Acl], patches: &[AcePatch]) -> (Vec<Acl>, Vec<AcePatchConflict>) {
+    let mut acls = acls.to_vec();
+    let mut conflicts = Vec::new();
+    for patch in patches {
+        let conflict = |reason| AcePatchConflict {
+            acl_name: patch.acl_name.clone(),
+            ace_name: patch.ace_name.clone(),
+            reason,
+        };
+        let acl = match acls.iter_mut().find(|acl| acl.name == patch.acl_name) {
+            Some(acl) => acl,
+            None => {
+                conflicts.push(conflict(AcePatchConflictReason::AclMissing));
+                continue;
+            },
+        };
+        let new_ace = patch.ace.clone().map(|ace| Ace {
+            name: patch.ace_name.clone(),
+            ..ace
+        });
+        match (
+            patch.operation,
+            acl.ace.iter().position(|ace| ace.name == patch.ace_name),
+        ) {
+            (AcePatchOperation::Add, None) => acl.ace.extend(new_ace),
+            (AcePatchOperation::Replace, None) | (AcePatchOperation::Remove, None) => {
+                conflicts.push(conflict(AcePatchConflictReason::AceMissing))
+            },
+            (operation, Some(index)) => {
+                if operation == AcePatchOperation::Add {
+                    conflicts.push(conflict(AcePatchConflictReason::AceExists));
+                } else if patch.base.as_ref().map_or(false, |base| *base != acl.ace[index]) {
+                    conflicts.push(conflict(AcePatchConflictReason::AceChanged));
+                }
+                match (operation, new_ace) {
+                    (AcePatchOperation::Remove, _) => {
+                        acl.ace.remove(index);
+                    },
+                    (_, Some(ace)) => acl.ace[index] = ace,
+                    (_, None) => {},
+                }
+            },
+        }
+    }
+    (acls, conflicts)
+}
+
+/// Merges the patch into the patches, so there is at most one patch per ACE.
+/// A patch of an ACE added by an earlier patch changes or drops that patch. Otherwise the patch must apply cleanly to
+/// the upstream ACLs and the state of the patched ACE is recorded, so later changes of the upstream ACE are detected.
+pub fn merge_ace_patch(
+    patches: &mut Vec<AcePatch>,
+    upstream: &[Acl],
+    mut patch: AcePatch,
+) -> std::result::Result<(), AcePatchConflictReason> {
+    let existing = patches
+        .iter()
+        .position(|p| p.acl_name == patch.acl_name && p.ace_name == patch.ace_name);
+    if let Some(index) = existing {
+        if patches[index].operation == AcePatchOperation::Add {
+            match patch.operation {
+                AcePatchOperation::Remove => {
+                    patches.remove(index);
+                },
+                _ => patches[index].ace = patch.ace,
+            }
+            return Ok(());
+        }
+    }
+
+    let upstream_ace = upstream
+        .iter()
+        .find(|acl| acl.name == patch.acl_name)
+        .ok_or(AcePatchConflictReason::AclMissing)?
+        .ace
+        .iter()
+        .find(|ace| ace.name == patch.ace_name);
+    match (patch.operation, upstream_ace) {
+        (AcePatchOperation::Add, Some(_)) => return Err(AcePatchConflictReason::AceExists),
+        (AcePatchOperation::Replace, None) | (AcePatchOperation::Remove, None) => {
+            return Err(AcePatchConflictReason::AceMissing)
+        },
+        _ => {},
+    }
+    if patch.base.is_none() {
+        patch.base = upstream_ace.cloned();
+    }
+    match existing {
+        Some(index) => patches[index] = patch,
+        None => patches.push(patch),
+    }
+    Ok(())
+}
+
+/// Drops the patch of the ACE, so the upstream ACE applies again. Returns whether the ACE was patched.
+pub fn revert_ace_patch(patches: &mut Vec<AcePatch>, acl_name: &str, ace_name: &str) -> bool {
+    let len = patches.len();
+    patches.retain(|p| p.acl_name != acl_name || p.ace_name != ace_name);
+    patches.len() != len
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{AceAction, AceMatches, AclDirection, AclType};
+
+    fn ace(name: &str, action: AceAction) -> Ace {
+        Ace {
+            name: name.to_string(),
+            action,
+            matches: AceMatches {
+                protocol: None,
+                direction_initiated: None,
+                source_network: None,
+                destination_network: None,
+                source_dnsname: None,
+                destination_dnsname: None,
+                source_port: None,
+                destination_port: None,
+                icmp: None,
+                eth: None,
+                mud: None,
+            },
+        }
+    }
+
+    fn acl(ace: Vec<Ace>) -> Acl {
+        Acl {
+            name: "acl".to_string(),
+            packet_direction: AclDirection::FromDevice,
+            acl_type: AclType::IPV4,
+            ace,
+        }
+    }
+
+    fn patch(acl_name: &str, ace_name: &str, operation: AcePatchOperation, ace: Option<Ace>) -> AcePatch {
+        AcePatch {
+            acl_name: acl_name.to_string(),
+            ace_name: ace_name.to_string(),
+            operation,
+            ace,
+            base: None,
+        }
+    }
+
+    #[test]
+    fn test_apply_ace_patches() {
+        let parent = vec![acl(vec![
+            ace("kept", AceAction::Accept),
+            ace("replaced", AceAction::Accept),
+            ace("removed", AceAction::Accept),
+        ])];
+        let mut patches = Vec::new();
+        for p in vec![
+            patch(
+                "acl",
+                "replaced",
+                AcePatchOperation::Replace,
+                Some(ace("", AceAction::Deny)),
+            ),
+            patch("acl", "removed", AcePatchOperation::Remove, None),
+            patch(
+                "acl",
+                "added",
+                AcePatchOperation::Add,
+                Some(ace("added", AceAction::Accept)),
+            ),
+        ] {
+            assert_eq!(merge_ace_patch(&mut patches, &parent, p), Ok(()));
+        }
+        assert_eq!(patches[0].base, Some(ace("replaced", AceAction::Accept)));
+        assert_eq!(patches[2].base, None);
+
+        assert_eq!(
+            apply_ace_patches(&parent, &patches),
+            (
+                vec![acl(vec![
+                    ace("kept", AceAction::Accept),
+                    ace("replaced", AceAction::Deny),
+                    ace("added", AceAction::Accept),
+                ])],
+                vec![]
+            )
+        );
+
+        // the parent changes the replaced ACE, drops the removed one and adds the added one itself
+        let changed_parent = vec![acl(vec![
+            ace("kept", AceAction::Accept),
+            ace("replaced", AceAction::Deny),
+            ace("added", AceAction::Deny),
+        ])];
+        let (acls, conflicts) = apply_ace_patches(&changed_parent, &patches);
+        assert_eq!(
+            acls,
+            vec![acl(vec![
+                ace("kept", AceAction::Accept),
+                ace("replaced", AceAction::Deny),
+                ace("added", AceAction::Accept),
+            ])]
+        );
+        assert_eq!(
+            conflicts
+                .iter()
+                .map(|c| (c.ace_name.as_str(), c.reason))
+                .collect::<Vec<_>>(),
+            vec![
+                ("replaced", AcePatchConflictReason::AceChanged),
+                ("removed", AcePatchConflictReason::AceMissing),
+                ("added", AcePatchConflictReason::AceExists),
+            ]
+        );
+
+        let (acls, conflicts) = apply_ace_patches(&[], &patches);
+        assert!(acls.is_empty());
+        assert!(conflicts.iter().all(|c| c.reason == AcePatchConflictReason::AclMissing));
+    }
+
+    #[test]
+    fn test_merge_ace_patch() {
+        let upstream = vec![acl(vec![ace("kept", AceAction::Accept)])];
+        let mut patches = Vec::new();
+
+        // patches must apply cleanly to the upstream ACLs
+        for (p, reason) in vec![
+            (
+                patch("other", "kept", AcePatchOperation::Remove, None),
+                AcePatchConflictReason::AclMissing,
+            ),
+            (
+                patch("acl", "missing", AcePatchOperation::Remove, None),
+                AcePatchConflictReason::AceMissing,
+            ),
+            (
+                patch("acl", "kept", AcePatchOperation::Add, Some(ace("", AceAction::Deny))),
+                AcePatchConflictReason::AceExists,
+            ),
+        ] {
+            assert_eq!(merge_ace_patch(&mut patches, &upstream, p), Err(reason));
+        }
+        assert!(patches.is_empty());
+
+        // a later patch of the same ACE replaces the earlier one
+        let remove = patch("acl", "kept", AcePatchOperation::Remove, None);
+        let replace = patch(
+            "acl",
+            "kept",
+            AcePatchOperation::Replace,
+            Some(ace("", AceAction::Deny)),
+        );
+        assert_eq!(merge_ace_patch(&mut patches, &upstream, remove), Ok(()));
+        assert_eq!(merge_ace_patch(&mut patches, &upstream, replace), Ok(()));
+        assert_eq!(patches.len(), 1);
+        assert_eq!(patches[0].operation, AcePatchOperation::Replace);
+
+        // patches of added ACEs change the added ACE
+        let add = patch("acl", "added", AcePatchOperation::Add, Some(ace("", AceAction::Accept)));
+        let replace = patch(
+            "acl",
+            "added",
+            AcePatchOperation::Replace,
+            Some(ace("", AceAction::Deny)),
+        );
+        assert_eq!(merge_ace_patch(&mut patches, &upstream, add), Ok(()));
+        assert_eq!(merge_ace_patch(&mut patches, &upstream, replace), Ok(()));
+        assert_eq!(patches[1].operation, AcePatchOperation::Add);
+        assert_eq!(patches[1].ace, Some(ace("", AceAction::Deny)));
+        let remove = patch("acl", "added", AcePatchOperation::Remove, None);
+        assert_eq!(merge_ace_patch(&mut patches, &upstream, remove), Ok(()));
+        assert_eq!(patches.len(), 1);
+
+        assert!(revert_ace_patch(&mut patches, "acl", "kept"));
+        assert!(!revert_ace_patch(&mut patches, "acl", "kept"));
+        assert!(patches.is_empty());
+    }
+}
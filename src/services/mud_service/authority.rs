@@ -0,0 +1,196 @@
+// Copyright 2020-2021, Benjamin Ludewig, Florian Bonetti, Jeffrey Munstermann, Luca Nittscher, Hugo Damer, Michael Bach
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use chrono::Utc;
+use paperclip::actix::Apiv2Schema;
+use url::Url;
+
+use crate::{
+    db::DbConnection,
+    error::Result,
+    models::{MudAuthorityDbo, MudDbo},
+    services::config_service::{get_config_value, ConfigKeys},
+};
+
+/// Decides how MUD-URLs of an authority, i.e. the host of the MUD-URL, are handled.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Apiv2Schema, strum::AsRefStr, strum::EnumString,
+)]
+pub enum MudAuthorityPolicy {
+    /// MUD-Files are fetched and applied automatically.
+    Trusted,
+    /// MUD-Files are fetched, but the devices using them only get the overrides until an admin approves the MUD-File.
+    Untrusted,
+    /// MUD-Files are never fetched, devices announcing such MUD-URLs are treated as if they announced none.
+    Blocked,
+}
+
+impl Default for MudAuthorityPolicy {
+    fn default() -> Self {
+        MudAuthorityPolicy::Trusted
+    }
+}
+
+/// Returns the authority of the MUD-URL, i.e. its lowercase host.
+pub fn get_authority(url: &str) -> Option<String> {
+    Url::parse(url)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_lowercase))
+}
+
+/// Returns all authorities with a policy set by the admin.
+pub async fn get_authorities(pool: &DbConnection) -> Result<Vec<MudAuthorityDbo>> {
+    Ok(
+        sqlx::query_as!(MudAuthorityDbo, "SELECT * FROM mud_authorities ORDER BY authority")
+            .fetch_all(pool)
+            .await?,
+    )
+}
+
+/// Sets the policy of the authority, which also applies to its subdomains unless they have a policy themselves.
+pub async fn set_authority_policy(authority: &str, policy: MudAuthorityPolicy, pool: &DbConnection) -> Result<()> {
+    let authority = authority.to_lowercase();
+    let policy = policy.as_ref();
+    sqlx::query!(
+        "INSERT INTO mud_authorities (authority, policy) VALUES ($1, $2) ON CONFLICT(authority) DO UPDATE SET policy = excluded.policy",
+        authority,
+        policy,
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Removes the policy of the authority, so the policy of its parent domain or the default policy applies again.
+pub async fn delete_authority_policy(authority: &str, pool: &DbConnection) -> Result<bool> {
+    let authority = authority.to_lowercase();
+    let del_count = sqlx::query!("DELETE FROM mud_authorities WHERE authority = $1", authority)
+        .execute(pool)
+        .await?;
+
+    Ok(del_count.rows_affected() == 1)
+}
+
+/// Returns the policy for the MUD-URL. The policy of the most specific authority containing the host of the MUD-URL
+/// applies, or the default policy configured by the admin if there is none.
+pub async fn get_authority_policy(url: &str, pool: &DbConnection) -> MudAuthorityPolicy {
+    let authorities = get_authorities(pool).await.unwrap_or_default();
+    match get_authority(url) {
+        Some(host) => match find_authority_policy(&host, &authorities) {
+            Some(policy) => policy,
+            None => get_default_authority_policy(pool).await,
+        },
+        // custom MUD-Profiles don't need to be URLs, they are created by the admin
+        None => MudAuthorityPolicy::Trusted,
+    }
+}
+
+/// Returns the default policy for authorities without a policy configured by the admin.
+pub async fn get_default_authority_policy(pool: &DbConnection) -> MudAuthorityPolicy {
+    get_config_value(ConfigKeys::DefaultMudAuthorityPolicy.as_ref(), pool)
+        .await
+        .unwrap_or_default()
+}
+
+fn find_authority_policy(host: &str, authorities: &[MudAuthorityDbo]) -> Option<MudAuthorityPolicy> {
+    // try the host first, then its parent domains
+    let mut domain = host;
+    loop {
+        if let Some(authority) = authorities.iter().find(|a| a.authority == domain) {
+            return authority.policy.parse().ok();
+        }
+        match domain.find('.') {
+            Some(index) => domain = &domain[index + 1..],
+            None => return None,
+        }
+    }
+}
+
+/// Whether the devices using the MUD-Profile only get the overrides, because its authority is untrusted and the
+/// MUD-File hasn't been approved yet.
+pub async fn is_pending_approval(mud: &MudDbo, pool: &DbConnection) -> bool {
+    mud.approved_at.is_none() && get_authority_policy(&mud.url, pool).await == MudAuthorityPolicy::Untrusted
+}
+
+/// Returns the MUD-Profiles that are pending approval.
+pub async fn get_pending_muds(pool: &DbConnection) -> Result<Vec<MudDbo>> {
+    let mut pending = Vec::new();
+    for mud in sqlx::query_as!(MudDbo, "SELECT * FROM mud_data WHERE approved_at IS NULL")
+        .fetch_all(pool)
+        .await?
+    {
+        if is_pending_approval(&mud, pool).await {
+            pending.push(mud);
+        }
+    }
+    Ok(pending)
+}
+
+/// Approves the MUD-Profile, so its ACLs apply although its authority is untrusted.
+pub async fn approve_mud(url: &str, pool: &DbConnection) -> Result<bool> {
+    let approved_at = Utc::now().naive_utc();
+    let upd_count = sqlx::query!("UPDATE mud_data SET approved_at = $1 WHERE url = $2", approved_at, url)
+        .execute(pool)
+        .await?;
+
+    Ok(upd_count.rows_affected() == 1)
+}
+
+/// Revokes the approval of the MUD-Profile, e.g. because its MUD-File changed since it was approved.
+pub async fn revoke_approval(url: &str, pool: &DbConnection) -> Result<()> {
+    sqlx::query!("UPDATE mud_data SET approved_at = NULL WHERE url = $1", url)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn authority(authority: &str, policy: MudAuthorityPolicy) -> MudAuthorityDbo {
+        MudAuthorityDbo {
+            authority: authority.to_string(),
+            policy: policy.as_ref().to_string(),
+        }
+    }
+
+    #[test]
+    fn test_find_authority_policy() {
+        let authorities = vec![
+            authority("example.com", MudAuthorityPolicy::Trusted),
+            authority("untrusted.example.com", MudAuthorityPolicy::Untrusted),
+            authority("evil.com", MudAuthorityPolicy::Blocked),
+        ];
+
+        assert_eq!(
+            find_authority_policy("example.com", &authorities),
+            Some(MudAuthorityPolicy::Trusted)
+        );
+        assert_eq!(
+            find_authority_policy("muds.example.com", &authorities),
+            Some(MudAuthorityPolicy::Trusted)
+        );
+        assert_eq!(
+            find_authority_policy("muds.untrusted.example.com", &authorities),
+            Some(MudAuthorityPolicy::Untrusted)
+        );
+        assert_eq!(
+            find_authority_policy("evil.com", &authorities),
+            Some(MudAuthorityPolicy::Blocked)
+        );
+        assert_eq!(find_authority_policy("notevil.com", &authorities), None);
+        assert_eq!(find_authority_policy("example.org", &authorities), None);
+    }
+
+    #[test]
+    fn test_get_authority() {
+        assert_eq!(
+            get_authority("https://MUDs.Example.com:8443/device.json"),
+            Some("muds.example.com".to_string())
+        );
+        assert_eq!(get_authority("not a url"), None);
+    }
+}
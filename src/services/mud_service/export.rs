@@ -0,0 +1,433 @@
+// Copyright 2020-2021, Benjamin Ludewig, Florian Bonetti, Jeffrey Munstermann, Luca Nittscher, Hugo Damer, Michael Bach
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use serde_json::Value;
+
+use super::json_models;
+use crate::{
+    models::{Ace, AceAction, AceMatches, AcePort, AceProtocol, Acl, AclDirection, AclType, MudData},
+    services::firewall_configuration_service::merge_acls,
+};
+
+/// The only MUD version specified by RFC8520.
+const MUD_VERSION: i64 = 1;
+/// IANA protocol number of TCP, exported as tcp match if the ACE also matches ports or the connection direction.
+const PROTOCOL_TCP: u32 = 6;
+/// IANA protocol number of UDP, exported as udp match if the ACE also matches ports.
+const PROTOCOL_UDP: u32 = 17;
+
+/// Converts the `MudData` back into a MUD-File, which is parsed into the same `MudData` by `parser::parse_mud`.
+/// If `merge_overrides` is set, the ACLs in `acl_override` replace the ACLs of the same name, the same way they are
+/// applied to the firewall rules.
+pub fn export_mud(mud_data: &MudData, merge_overrides: bool) -> json_models::MudJson {
+    let acls = if merge_overrides {
+        merge_acls(&mud_data.acllist, &mud_data.acl_override)
+    } else {
+        mud_data.acllist.iter().collect()
+    };
+
+    let mut exported_acls: Vec<json_models::Acl> = Vec::new();
+    for acl in &acls {
+        // an ACL referenced by both policies is only defined once
+        if exported_acls.iter().all(|exported| exported.name != acl.name) {
+            exported_acls.push(export_acl(acl));
+        }
+    }
+
+    json_models::MudJson {
+        mud: json_models::MudDefinition {
+            mud_version: MUD_VERSION,
+            mud_url: mud_data.url.clone(),
+            last_update: mud_data.last_update.clone(),
+            cache_validity: mud_data.cache_validity,
+            is_supported: mud_data.is_supported,
+            systeminfo: mud_data.systeminfo.clone(),
+            mfg_name: mud_data.mfg_name.clone(),
+            documentation: mud_data.documentation.clone(),
+            model_name: mud_data.model_name.clone(),
+            from_device_policy: export_policy(&acls, AclDirection::FromDevice),
+            to_device_policy: export_policy(&acls, AclDirection::ToDevice),
+            extensions: mud_data.masa_url.as_ref().map(|_| vec![String::from("masa")]),
+            masa_server: mud_data.masa_url.clone(),
+            // the exported MUD-File differs from the signed one, so the signature doesn't apply to it
+            mud_signature: None,
+        },
+        acls: json_models::IetfAccessControlListAcls { acl: exported_acls },
+    }
+}
+
+fn export_policy(acls: &[&Acl], direction: AclDirection) -> json_models::Policy {
+    json_models::Policy {
+        access_lists: json_models::AccessLists {
+            access_list: acls
+                .iter()
+                .filter(|acl| acl.packet_direction == direction)
+                .map(|acl| json_models::AccessList { name: acl.name.clone() })
+                .collect(),
+        },
+    }
+}
+
+fn export_acl(acl: &Acl) -> json_models::Acl {
+    json_models::Acl {
+        name: acl.name.clone(),
+        type_field: String::from(match acl.acl_type {
+            AclType::IPV4 => "ipv4-acl-type",
+            AclType::IPV6 => "ipv6-acl-type",
+            AclType::Ethernet => "eth-acl-type",
+        }),
+        aces: json_models::Aces {
+            ace: acl.ace.iter().map(|ace| export_ace(ace, acl)).collect(),
+        },
+    }
+}
+
+fn export_ace(ace: &Ace, acl: &Acl) -> json_models::Ace {
+    json_models::Ace {
+        name: ace.name.clone(),
+        matches: export_matches(&ace.matches, acl),
+        actions: json_models::Actions {
+            forwarding: String::from(match ace.action {
+                AceAction::Accept => "accept",
+                AceAction::Deny => "drop",
+            }),
+        },
+    }
+}
+
+fn export_matches(matches: &AceMatches, acl: &Acl) -> json_models::Matches {
+    let has_port_matches = matches.source_port.is_some() || matches.destination_port.is_some();
+    let source_port = matches.source_port.as_ref().map(export_port);
+    let destination_port = matches.destination_port.as_ref().map(export_port);
+
+    // tcp and udp given as protocol number are only exported as transport matches if they need one
+    let tcp = match matches.protocol {
+        Some(AceProtocol::Tcp) => true,
+        Some(AceProtocol::Protocol(PROTOCOL_TCP)) => has_port_matches || matches.direction_initiated.is_some(),
+        _ => false,
+    }
+    .then(|| json_models::Tcp {
+        source_port: source_port.clone(),
+        destination_port: destination_port.clone(),
+        direction_initiated: matches.direction_initiated.map(export_direction),
+    });
+    let udp = match matches.protocol {
+        Some(AceProtocol::Udp) => true,
+        Some(AceProtocol::Protocol(PROTOCOL_UDP)) => has_port_matches,
+        _ => false,
+    }
+    .then(|| json_models::Udp {
+        source_port,
+        destination_port,
+    });
+
+    let protocol = match matches.protocol {
+        Some(AceProtocol::Protocol(protocol)) => Some(protocol),
+        _ => None,
+    };
+    let has_ip_matches = protocol.is_some()
+        || matches.source_network.is_some()
+        || matches.destination_network.is_some()
+        || matches.source_dnsname.is_some()
+        || matches.destination_dnsname.is_some();
+    let (ipv4, ipv6) = match acl.acl_type {
+        AclType::IPV4 if has_ip_matches => (
+            Some(json_models::Ipv4 {
+                protocol,
+                source_ipv4_network: matches.source_network.clone(),
+                destination_ipv4_network: matches.destination_network.clone(),
+                src_dnsname: matches.source_dnsname.clone(),
+                dst_dnsname: matches.destination_dnsname.clone(),
+            }),
+            None,
+        ),
+        AclType::IPV6 if has_ip_matches => (
+            None,
+            Some(json_models::Ipv6 {
+                protocol,
+                source_ipv6_network: matches.source_network.clone(),
+                destination_ipv6_network: matches.destination_network.clone(),
+                dst_dnsname: matches.destination_dnsname.clone(),
+                src_dnsname: matches.source_dnsname.clone(),
+            }),
+        ),
+        _ => (None, None),
+    };
+
+    json_models::Matches {
+        ipv4,
+        ipv6,
+        tcp,
+        udp,
+        icmp: matches.icmp.as_ref().map(|icmp| json_models::Icmp {
+            type_field: icmp.icmp_type,
+            code: icmp.code,
+        }),
+        eth: matches.eth.as_ref().map(|eth| json_models::Eth {
+            destination_mac_address: eth.destination_mac_address.clone(),
+            source_mac_address: eth.source_mac_address.clone(),
+            ethertype: eth.ethertype.map(Value::from),
+        }),
+        mud: matches.mud.as_ref().map(|mud| json_models::MudExtension {
+            controller: mud.controller.clone(),
+            // leaf nodes of type `empty` are encoded as `[null]`, see https://tools.ietf.org/html/rfc7951#section-6.9
+            my_controller: mud.my_controller.then(|| vec![Value::Null]),
+            local_networks: mud.local_networks.then(empty_leaf),
+            same_manufacturer: mud.same_manufacturer.then(empty_leaf),
+            manufacturer: mud.manufacturer.clone(),
+            model: mud.model.clone(),
+        }),
+    }
+}
+
+fn export_port(port: &AcePort) -> json_models::Port {
+    let (operator, port) = match *port {
+        AcePort::Single(port) => ("eq", port),
+        AcePort::Lte(port) => ("lte", port),
+        AcePort::Gte(port) => ("gte", port),
+        AcePort::Neq(port) => ("neq", port),
+        AcePort::Range(lower_port, upper_port) => {
+            return json_models::Port {
+                operator: None,
+                port: None,
+                lower_port: Some(lower_port),
+                upper_port: Some(upper_port),
+            };
+        },
+    };
+    json_models::Port {
+        operator: Some(String::from(operator)),
+        port: Some(port),
+        lower_port: None,
+        upper_port: None,
+    }
+}
+
+fn export_direction(direction: AclDirection) -> String {
+    String::from(match direction {
+        AclDirection::FromDevice => "from-device",
+        AclDirection::ToDevice => "to-device",
+    })
+}
+
+fn empty_leaf() -> Value {
+    Value::Array(vec![Value::Null])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        error::Result,
+        models::{AceEthMatches, AceIcmpMatches, AceMudMatches},
+        services::mud_service::parser::parse_mud,
+    };
+
+    /// Exports the `MudData` and parses it again, keeping the expiration, which depends on the time of parsing.
+    fn round_trip(mud_data: &MudData, merge_overrides: bool) -> Result<MudData> {
+        let json = serde_json::to_string(&export_mud(mud_data, merge_overrides))?;
+        let mut parsed = parse_mud(mud_data.url.clone(), &json)?;
+        parsed.expiration = mud_data.expiration;
+        Ok(parsed)
+    }
+
+    #[test]
+    fn test_round_trip_examples() -> Result<()> {
+        for (path, url) in &[
+            (
+                "tests/mud_tests/MUD-Profile-example.json",
+                "https://lighting.example.com/lightbulb2000",
+            ),
+            ("tests/mud_tests/Amazon-Echo.json", "https://amazonecho.com/amazonecho"),
+            (
+                "tests/mud_tests/Ring-Doorbell.json",
+                "https://ringdoorbell.com/ringdoorbell",
+            ),
+            (
+                "tests/mud_tests/August-Doorbell.json",
+                "https://augustdoorbellcam.com/augustdoorbellcam",
+            ),
+        ] {
+            let mud_data = parse_mud(url.to_string(), &std::fs::read_to_string(path)?)?;
+            assert!(mud_data.cache_validity.is_some());
+            assert_eq!(round_trip(&mud_data, false)?, mud_data);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_exported_keys() -> Result<()> {
+        let mud_data = parse_mud(
+            "https://amazonecho.com/amazonecho".to_string(),
+            &std::fs::read_to_string("tests/mud_tests/Amazon-Echo.json")?,
+        )?;
+
+        let exported = serde_json::to_value(&export_mud(&mud_data, false))?;
+        let mut keys: Vec<&String> = exported.as_object().unwrap().keys().collect();
+        keys.sort();
+        assert_eq!(keys, vec!["ietf-access-control-list:acls", "ietf-mud:mud"]);
+        assert_eq!(exported["ietf-mud:mud"]["cache-validity"], 100);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_round_trip_matches() -> Result<()> {
+        let ace = |name: &str, matches: AceMatches| Ace {
+            name: name.to_string(),
+            action: AceAction::Accept,
+            matches,
+        };
+        let no_matches = AceMatches {
+            protocol: None,
+            direction_initiated: None,
+            source_network: None,
+            destination_network: None,
+            source_dnsname: None,
+            destination_dnsname: None,
+            source_port: None,
+            destination_port: None,
+            icmp: None,
+            eth: None,
+            mud: None,
+        };
+        let mud_data = MudData {
+            url: "https://example.com/device".to_string(),
+            masa_url: Some("https://masa.example.com".to_string()),
+            mud_signature: None,
+            signature_status: None,
+            last_update: "2021-05-26T09:30:12+02:00".to_string(),
+            systeminfo: Some("Example Device".to_string()),
+            mfg_name: Some("Example".to_string()),
+            model_name: Some("Device".to_string()),
+            documentation: None,
+            is_supported: true,
+            cache_validity: None,
+            fetch_status: None,
+            parent_url: None,
+            ace_patches: Vec::new(),
+            expiration: chrono::Utc::now(),
+            acllist: vec![
+                Acl {
+                    name: "from-ipv4".to_string(),
+                    packet_direction: AclDirection::FromDevice,
+                    acl_type: AclType::IPV4,
+                    ace: vec![
+                        ace(
+                            "tcp",
+                            AceMatches {
+                                protocol: Some(AceProtocol::Tcp),
+                                direction_initiated: Some(AclDirection::FromDevice),
+                                destination_dnsname: Some("www.example.com".to_string()),
+                                destination_port: Some(AcePort::Single(443)),
+                                ..no_matches.clone()
+                            },
+                        ),
+                        ace(
+                            "udp",
+                            AceMatches {
+                                protocol: Some(AceProtocol::Protocol(PROTOCOL_UDP)),
+                                source_network: Some("10.0.0.0/8".to_string()),
+                                destination_network: Some("192.0.2.0/24".to_string()),
+                                source_port: Some(AcePort::Range(1024, 2048)),
+                                destination_port: Some(AcePort::Gte(8000)),
+                                ..no_matches.clone()
+                            },
+                        ),
+                        ace(
+                            "icmp",
+                            AceMatches {
+                                protocol: Some(AceProtocol::Protocol(1)),
+                                icmp: Some(AceIcmpMatches {
+                                    icmp_type: Some(8),
+                                    code: Some(0),
+                                }),
+                                ..no_matches.clone()
+                            },
+                        ),
+                        ace(
+                            "mud",
+                            AceMatches {
+                                mud: Some(AceMudMatches {
+                                    local_networks: true,
+                                    same_manufacturer: false,
+                                    manufacturer: None,
+                                    model: None,
+                                    controller: None,
+                                    my_controller: false,
+                                }),
+                                ..no_matches.clone()
+                            },
+                        ),
+                    ],
+                },
+                Acl {
+                    name: "to-ipv6".to_string(),
+                    packet_direction: AclDirection::ToDevice,
+                    acl_type: AclType::IPV6,
+                    ace: vec![ace(
+                        "tcp",
+                        AceMatches {
+                            protocol: Some(AceProtocol::Protocol(PROTOCOL_TCP)),
+                            source_dnsname: Some("www.example.com".to_string()),
+                            source_port: Some(AcePort::Neq(22)),
+                            mud: Some(AceMudMatches {
+                                local_networks: false,
+                                same_manufacturer: true,
+                                manufacturer: None,
+                                model: Some("https://example.com/other-device".to_string()),
+                                controller: Some("urn:ietf:params:mud:dns".to_string()),
+                                my_controller: true,
+                            }),
+                            ..no_matches.clone()
+                        },
+                    )],
+                },
+                Acl {
+                    name: "from-eth".to_string(),
+                    packet_direction: AclDirection::FromDevice,
+                    acl_type: AclType::Ethernet,
+                    ace: vec![ace(
+                        "eth",
+                        AceMatches {
+                            eth: Some(AceEthMatches {
+                                ethertype: Some(0x888e),
+                                source_mac_address: None,
+                                destination_mac_address: Some("01:80:c2:00:00:03".to_string()),
+                            }),
+                            ..no_matches.clone()
+                        },
+                    )],
+                },
+            ],
+            acl_override: Vec::default(),
+        };
+
+        assert_eq!(round_trip(&mud_data, false)?, mud_data);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_export_overrides() -> Result<()> {
+        let mut mud_data = parse_mud(
+            "https://amazonecho.com/amazonecho".to_string(),
+            &std::fs::read_to_string("tests/mud_tests/Amazon-Echo.json")?,
+        )?;
+        let mut acl_override = mud_data.acllist[0].clone();
+        acl_override.ace.truncate(1);
+        mud_data.acl_override = vec![acl_override.clone()];
+
+        let exported = export_mud(&mud_data, false);
+        assert_eq!(exported.acls.acl[0].aces.ace.len(), mud_data.acllist[0].ace.len());
+
+        let merged = round_trip(&mud_data, true)?;
+        assert_eq!(merged.acllist.len(), mud_data.acllist.len());
+        assert!(merged.acllist.contains(&acl_override));
+        assert!(!merged.acllist.contains(&mud_data.acllist[0]));
+        assert!(merged.acl_override.is_empty());
+
+        Ok(())
+    }
+}
@@ -1,46 +1,116 @@
 // Copyright 2020-2021, Benjamin Ludewig, Florian Bonetti, Jeffrey Munstermann, Luca Nittscher, Hugo Damer, Michael Bach
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
-use std::time::Duration;
+use std::{
+    fs,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    time::Duration,
+};
 
 use const_format::concatcp;
 use encoding_rs::{CoderResult, Encoding, UTF_8};
+use ipnetwork::IpNetwork;
+use lazy_static::lazy_static;
 use mime::Mime;
 use reqwest::{
-    header::{ACCEPT, ACCEPT_LANGUAGE, CONTENT_TYPE, USER_AGENT},
+    header::{
+        HeaderMap, HeaderName, HeaderValue, ACCEPT, ACCEPT_LANGUAGE, CONTENT_TYPE, ETAG, IF_MODIFIED_SINCE,
+        IF_NONE_MATCH, LAST_MODIFIED, LOCATION, USER_AGENT,
+    },
     redirect::Policy,
+    Certificate, Proxy, Response, StatusCode,
 };
 use snafu::ensure;
+use tokio::net::lookup_host;
+use url::{Host, Url};
 
-use crate::{error, error::Result, VERSION};
+use crate::{app_config::APP_CONFIG, error, error::Result, VERSION};
 
-/// Request timeout of 15 seconds
-const TIMEOUT: Duration = Duration::from_secs(15);
-/// Maximum 3xx redirects to follow
-const MAX_REDIRECTS: usize = 5;
-/// Maximum response body size: 10MB
-const MAX_LEN: usize = 10_000_000;
 /// `Accept` header value specified by RFC8520
 const ACCEPT_HEADER_VALUE: &str = "application/mud+json";
+/// `Accept` header value for the detached signature of a MUD-File, see https://tools.ietf.org/html/rfc8520#section-13.2
+const ACCEPT_SIGNATURE_HEADER_VALUE: &str = "application/pkcs7-signature";
 /// `Accept-Language` header value, prefer english or german.
 const ACCEPT_LANGUAGE_HEADER_VALUE: &str = "en, de, *;q=0.5";
 /// `User-Agent` header value.
 const USER_AGENT_HEADER_VALUE: &str = concatcp!("NAMIB-MUD-Controller/", VERSION);
 
+lazy_static! {
+    /// The CA certificate configured by `MUD_FETCH_CA_CERTS`, loaded once by `init_ca_certs`.
+    /// `None` if none is configured or it couldn't be loaded, in which case only the system trust store is used.
+    static ref CA_CERTS: Option<Certificate> = match load_ca_certs() {
+        Ok(ca_certs) => ca_certs,
+        Err(e) => {
+            error!("Failed to load the CA certificates for fetching MUD-Files: {:?}", e);
+            None
+        },
+    };
+}
+
+/// Loads the CA certificates, so they don't have to be read for every request.
+pub fn init_ca_certs() {
+    lazy_static::initialize(&CA_CERTS);
+}
+
+fn load_ca_certs() -> Result<Option<Certificate>> {
+    Ok(match &APP_CONFIG.mud_fetch_ca_certs {
+        Some(ca_certs) => Some(Certificate::from_pem(&fs::read(ca_certs)?)?),
+        None => None,
+    })
+}
+
+/// The `ETag` and `Last-Modified` response headers of a fetched MUD-File, used to make conditional requests.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CacheValidators {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
+
+/// The result of fetching a MUD-File.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FetchedMud {
+    /// The MUD-File and the validators of the response.
+    Modified(String, CacheValidators),
+    /// The MUD-File didn't change since it was fetched with the given validators.
+    NotModified,
+}
+
 /// Fetch MUD-URL Data respecting timeout, redirects and maximum response length.
 /// Respects the request headers specified by https://tools.ietf.org/html/rfc8520#section-1.6
-pub async fn fetch_mud(url: &str) -> Result<String> {
+/// If validators of a previous response are given, the request is conditional and may return `FetchedMud::NotModified`.
+pub async fn fetch_mud(url: &str, validators: Option<&CacheValidators>) -> Result<FetchedMud> {
+    let mut headers = HeaderMap::new();
+    headers.insert(ACCEPT, HeaderValue::from_static(ACCEPT_HEADER_VALUE));
+    headers.insert(ACCEPT_LANGUAGE, HeaderValue::from_static(ACCEPT_LANGUAGE_HEADER_VALUE));
+    if let Some(validators) = validators {
+        // validators that aren't valid header values can't have been received as header, so they are skipped
+        if let Some(etag) = validators.etag.as_deref().and_then(|v| HeaderValue::from_str(v).ok()) {
+            headers.insert(IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = validators
+            .last_modified
+            .as_deref()
+            .and_then(|v| HeaderValue::from_str(v).ok())
+        {
+            headers.insert(IF_MODIFIED_SINCE, last_modified);
+        }
+    }
     // start the response
-    let mut response = reqwest::Client::builder()
-        .redirect(Policy::limited(MAX_REDIRECTS))
-        .timeout(TIMEOUT)
-        .build()?
-        .get(url)
-        .header(ACCEPT, ACCEPT_HEADER_VALUE)
-        .header(ACCEPT_LANGUAGE, ACCEPT_LANGUAGE_HEADER_VALUE)
-        .header(USER_AGENT, USER_AGENT_HEADER_VALUE)
-        .send()
-        .await?;
+    let mut response = send_request(url, headers).await?;
+    if validators.is_some() && response.status() == StatusCode::NOT_MODIFIED {
+        return Ok(FetchedMud::NotModified);
+    }
+    let header_value = |name: HeaderName| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(String::from)
+    };
+    let validators = CacheValidators {
+        etag: header_value(ETAG),
+        last_modified: header_value(LAST_MODIFIED),
+    };
     // try to get the content type
     let content_type = response
         .headers()
@@ -52,7 +122,7 @@ pub async fn fetch_mud(url: &str) -> Result<String> {
         .as_ref()
         .and_then(|mime| mime.get_param("charset").map(|charset| charset.as_str()))
         .unwrap_or("utf-8");
-    // iterate over the chunks in the response, decoding them and aborting if the MAX_LEN is exceeded
+    // iterate over the chunks in the response, decoding them and aborting if the maximum size is exceeded
     // adapted from https://docs.rs/encoding_rs/0.8.28/encoding_rs/
     let encoding = Encoding::for_label(encoding_name.as_bytes()).unwrap_or(UTF_8);
     let mut decoder = encoding.new_decoder();
@@ -61,7 +131,10 @@ pub async fn fetch_mud(url: &str) -> Result<String> {
     let mut bytes_in_buffer = 0usize;
     let buffer: &mut str = std::str::from_utf8_mut(&mut buffer_bytes[..]).unwrap();
     while let Some(chunk) = response.chunk().await? {
-        ensure!(output.len() + chunk.len() < MAX_LEN, error::MudFileInvalid);
+        ensure!(
+            output.len() + chunk.len() < APP_CONFIG.mud_fetch_max_size,
+            error::MudFileInvalid
+        );
         let mut total_read_from_current_input = 0usize;
         loop {
             let (result, read, written, had_errors) = decoder.decode_to_str(
@@ -99,5 +172,239 @@ pub async fn fetch_mud(url: &str) -> Result<String> {
             },
         }
     }
+    Ok(FetchedMud::Modified(output, validators))
+}
+
+/// Fetch the detached signature of a MUD-File, respecting the same limits as `fetch_mud`.
+pub async fn fetch_mud_signature(url: &str) -> Result<Vec<u8>> {
+    let mut headers = HeaderMap::new();
+    headers.insert(ACCEPT, HeaderValue::from_static(ACCEPT_SIGNATURE_HEADER_VALUE));
+    let mut response = send_request(url, headers).await?.error_for_status()?;
+    let mut output = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        ensure!(
+            output.len() + chunk.len() < APP_CONFIG.mud_fetch_max_size,
+            error::MudFileInvalid
+        );
+        output.extend_from_slice(&chunk);
+    }
     Ok(output)
 }
+
+/// Sends a GET request to the url. Redirects are followed manually, so the address of every target can be checked
+/// before connecting to it, see `resolve_target`.
+async fn send_request(url: &str, mut headers: HeaderMap) -> Result<Response> {
+    headers.insert(USER_AGENT, HeaderValue::from_static(USER_AGENT_HEADER_VALUE));
+    let mut url = parse_target(url, None)?;
+    for _ in 0..=APP_CONFIG.mud_fetch_max_redirects {
+        let addr = resolve_target(&url).await?;
+        let response = build_client(&url, addr)?
+            .get(url.clone())
+            .headers(headers.clone())
+            .send()
+            .await?;
+        let location = response.headers().get(LOCATION).and_then(|value| value.to_str().ok());
+        match location {
+            Some(location) if response.status().is_redirection() && response.status() != StatusCode::NOT_MODIFIED => {
+                debug!("Following redirect from {} to {}", url, location);
+                url = parse_target(location, Some(&url))?;
+            },
+            _ => return Ok(response),
+        }
+    }
+    error::MudError {
+        message: format!("Too many redirects fetching {}", url),
+    }
+    .fail()
+}
+
+/// Parses the url of a MUD-File or signature, which may be relative to the url of a redirecting response.
+/// Only https urls are accepted.
+fn parse_target(url_str: &str, base: Option<&Url>) -> Result<Url> {
+    let url = match base {
+        Some(base) => base.join(url_str),
+        None => Url::parse(url_str),
+    };
+    match url {
+        Ok(url) if url.scheme() == "https" => Ok(url),
+        Ok(url) => error::MudError {
+            message: format!("Refusing to fetch {}, only https urls are allowed", url),
+        }
+        .fail(),
+        Err(e) => error::MudError {
+            message: format!("Invalid url {}: {}", url_str, e),
+        }
+        .fail(),
+    }
+}
+
+/// Resolves the host of the url and checks that none of its addresses is forbidden, see `is_forbidden_address`.
+/// Returns the address to connect to, so the host can't resolve to a different address afterwards.
+/// If a proxy is configured, it connects to the host itself, so the host is checked but no address is returned.
+async fn resolve_target(url: &Url) -> Result<Option<SocketAddr>> {
+    let port = url.port_or_known_default().unwrap_or(443);
+    let addrs: Vec<SocketAddr> = match url.host() {
+        Some(Host::Ipv4(ip)) => vec![SocketAddr::new(ip.into(), port)],
+        Some(Host::Ipv6(ip)) => vec![SocketAddr::new(ip.into(), port)],
+        Some(Host::Domain(domain)) => lookup_host((domain, port)).await?.collect(),
+        None => Vec::new(),
+    };
+    if let Some(addr) = addrs
+        .iter()
+        .find(|addr| is_forbidden_address(addr.ip(), &APP_CONFIG.mud_fetch_allowed_networks))
+    {
+        return error::MudError {
+            message: format!("Refusing to fetch {} from forbidden address {}", url, addr.ip()),
+        }
+        .fail();
+    }
+    match addrs.first() {
+        Some(_) if APP_CONFIG.mud_fetch_proxy.is_some() => Ok(None),
+        Some(addr) => Ok(Some(*addr)),
+        None => error::MudError {
+            message: format!("Could not resolve the host of {}", url),
+        }
+        .fail(),
+    }
+}
+
+/// Whether MUD-Files must not be fetched from the address, i.e. it is a loopback, link-local, private, shared
+/// (carrier-grade NAT), multicast, reserved or unspecified address that isn't contained in one of the
+/// `allowed_networks`. IPv4-mapped, NAT64 (64:ff9b::/96), 6to4 (2002::/16) and Teredo (2001::/32) addresses are
+/// checked by the IPv4 addresses they embed.
+fn is_forbidden_address(ip: IpAddr, allowed_networks: &[IpNetwork]) -> bool {
+    if allowed_networks.iter().any(|network| network.contains(ip)) {
+        return false;
+    }
+    match ip {
+        IpAddr::V4(ip) => {
+            let octets = ip.octets();
+            // 0.0.0.0/8 ("this network") and 100.64.0.0/10 (shared address space, RFC 6598)
+            let is_this_network = octets[0] == 0;
+            let is_shared = octets[0] == 100 && octets[1] & 0xc0 == 64;
+            // 240.0.0.0/4 (reserved), including the broadcast address
+            let is_reserved = octets[0] >= 240;
+            ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_multicast()
+                || is_this_network
+                || is_shared
+                || is_reserved
+        },
+        IpAddr::V6(ip) => {
+            let segments = ip.segments();
+            let is_ipv4_mapped = segments[..5].iter().all(|s| *s == 0) && segments[5] == 0xffff;
+            let is_nat64 = segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0];
+            let is_unique_local = segments[0] & 0xfe00 == 0xfc00;
+            let is_link_local = segments[0] & 0xffc0 == 0xfe80;
+            let octets = ip.octets();
+            let is_forbidden_ipv4 =
+                |octets: [u8; 4]| is_forbidden_address(Ipv4Addr::from(octets).into(), allowed_networks);
+            if is_ipv4_mapped || is_nat64 {
+                return is_forbidden_ipv4([octets[12], octets[13], octets[14], octets[15]]);
+            }
+            // 6to4 embeds the IPv4 address of the relay after the prefix
+            if segments[0] == 0x2002 && is_forbidden_ipv4([octets[2], octets[3], octets[4], octets[5]]) {
+                return true;
+            }
+            // Teredo embeds the IPv4 address of the server after the prefix and the inverted one of the client last
+            if segments[..2] == [0x2001, 0]
+                && (is_forbidden_ipv4([octets[4], octets[5], octets[6], octets[7]])
+                    || is_forbidden_ipv4([!octets[12], !octets[13], !octets[14], !octets[15]]))
+            {
+                return true;
+            }
+            ip.is_loopback() || ip.is_unspecified() || ip.is_multicast() || is_unique_local || is_link_local
+        },
+    }
+}
+
+/// Builds the client for a single request to the url, which connects to the checked address of its host.
+fn build_client(url: &Url, addr: Option<SocketAddr>) -> Result<reqwest::Client> {
+    let mut builder = reqwest::Client::builder()
+        .redirect(Policy::none())
+        .timeout(Duration::from_secs(APP_CONFIG.mud_fetch_timeout));
+    builder = match &APP_CONFIG.mud_fetch_proxy {
+        Some(proxy) => builder.proxy(Proxy::all(proxy)?),
+        None => builder.no_proxy(),
+    };
+    if let Some(ca_certs) = CA_CERTS.as_ref() {
+        builder = builder.add_root_certificate(ca_certs.clone());
+    }
+    if let (Some(Host::Domain(domain)), Some(addr)) = (url.host(), addr) {
+        builder = builder.resolve(domain, addr);
+    }
+    Ok(builder.build()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_forbidden_address() {
+        let forbidden = [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "0.0.0.0",
+            "255.255.255.255",
+            "::1",
+            "::",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+            "::ffff:192.168.1.1",
+            "0.1.2.3",
+            "100.64.0.1",
+            "100.127.255.254",
+            "64:ff9b::7f00:1",
+            "64:ff9b::10.1.2.3",
+            "64:ff9b::100.64.0.1",
+            "224.0.0.1",
+            "239.255.255.250",
+            "240.0.0.1",
+            "ff02::1",
+            "2002:c0a8:0101::1",
+            "2001:0:c0a8:101::1",
+            "2001:0:5db8:d822::3f57:fefe",
+        ];
+        for ip in &forbidden {
+            assert!(is_forbidden_address(ip.parse().unwrap(), &[]), "{} is forbidden", ip);
+        }
+        for ip in &[
+            "93.184.216.34",
+            "2606:2800:220:1:248:1893:25c8:1946",
+            "::ffff:93.184.216.34",
+            "64:ff9b::93.184.216.34",
+            "100.63.255.255",
+            "100.128.0.1",
+            "223.255.255.255",
+            "2002:5db8:d822::1",
+            "2001:0:5db8:d822::a247:27dd",
+        ] {
+            assert!(!is_forbidden_address(ip.parse().unwrap(), &[]), "{} is allowed", ip);
+        }
+
+        let allowed_networks = ["192.168.1.0/24".parse().unwrap(), "fd00::/64".parse().unwrap()];
+        assert!(!is_forbidden_address("192.168.1.1".parse().unwrap(), &allowed_networks));
+        assert!(!is_forbidden_address("fd00::1".parse().unwrap(), &allowed_networks));
+        assert!(is_forbidden_address("192.168.2.1".parse().unwrap(), &allowed_networks));
+        assert!(is_forbidden_address("127.0.0.1".parse().unwrap(), &allowed_networks));
+    }
+
+    #[test]
+    fn test_parse_target() {
+        let base = Url::parse("https://example.com/muds/device.json").unwrap();
+        assert_eq!(
+            parse_target("device.p7s", Some(&base)).unwrap().as_str(),
+            "https://example.com/muds/device.p7s"
+        );
+        assert!(parse_target("http://example.com/device.json", None).is_err());
+        assert!(parse_target("http://example.com/device.json", Some(&base)).is_err());
+        assert!(parse_target("not a url", None).is_err());
+    }
+}
@@ -0,0 +1,47 @@
+// Copyright 2020-2021, Benjamin Ludewig, Florian Bonetti, Jeffrey Munstermann, Luca Nittscher, Hugo Damer, Michael Bach
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use crate::{
+    db::DbConnection,
+    error::Result,
+    models::{Acl, MudData},
+    services::{
+        firewall_configuration_service::merge_acls,
+        mud_service::{ace_patch::apply_ace_patches, get_all_muds, get_effective_mud, source::configured_sources},
+    },
+};
+
+/// Returns the parent of a custom MUD-Profile as it applies to devices, `None` if it doesn't have a parent.
+/// Only one level of inheritance is supported, the parent of the parent is ignored.
+pub async fn get_parent(mud_data: &MudData, pool: &DbConnection) -> Result<Option<MudData>> {
+    let parent_url = match &mud_data.parent_url {
+        Some(parent_url) => parent_url,
+        None => return Ok(None),
+    };
+    let mut parent = get_effective_mud(parent_url, &configured_sources(), pool).await?;
+    if parent.parent_url.is_none() {
+        parent.acllist = apply_ace_patches(&parent.acllist, &parent.ace_patches).0;
+    }
+    Ok(Some(parent))
+}
+
+/// Returns the ACLs custom MUD-Profiles inherit from the parent, i.e. its ACLs merged with its overrides.
+pub fn inheritable_acls(parent: &MudData) -> Vec<Acl> {
+    merge_acls(&parent.acllist, &parent.acl_override)
+        .into_iter()
+        .cloned()
+        .collect()
+}
+
+/// Returns the MUD-URLs of the custom MUD-Profiles inheriting from the MUD-Profile.
+pub async fn get_children(url: &str, pool: &DbConnection) -> Result<Vec<String>> {
+    Ok(get_all_muds(pool)
+        .await?
+        .into_iter()
+        .filter(|mud| {
+            mud.parse_data()
+                .map_or(false, |mud_data| mud_data.parent_url.as_deref() == Some(url))
+        })
+        .map(|mud| mud.url)
+        .collect())
+}
@@ -6,7 +6,7 @@ pub struct MudJson {
     #[serde(rename = "ietf-mud:mud")]
     pub mud: MudDefinition,
     #[serde(
-        alias = "ietf-access-control-list:acls",
+        rename = "ietf-access-control-list:acls",
         alias = "ietf-access-control-list:access-lists"
     )]
     pub acls: IetfAccessControlListAcls,
@@ -20,23 +20,28 @@ pub struct MudDefinition {
     pub mud_url: String,
     #[serde(rename = "last-update")]
     pub last_update: String,
-    #[serde(rename = "cache-validity")]
+    #[serde(rename = "cache-validity", skip_serializing_if = "Option::is_none")]
     pub cache_validity: Option<i64>,
     #[serde(rename = "is-supported")]
     pub is_supported: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub systeminfo: Option<String>,
-    #[serde(rename = "mfg-name")]
+    #[serde(rename = "mfg-name", skip_serializing_if = "Option::is_none")]
     pub mfg_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub documentation: Option<String>,
-    #[serde(rename = "model-name")]
+    #[serde(rename = "model-name", skip_serializing_if = "Option::is_none")]
     pub model_name: Option<String>,
     #[serde(rename = "from-device-policy")]
     pub from_device_policy: Policy,
     #[serde(rename = "to-device-policy")]
     pub to_device_policy: Policy,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub extensions: Option<Vec<String>>,
-    #[serde(rename = "ietf-mud-brski-masa:masa-server")]
+    #[serde(rename = "ietf-mud-brski-masa:masa-server", skip_serializing_if = "Option::is_none")]
     pub masa_server: Option<String>,
+    #[serde(rename = "mud-signature", skip_serializing_if = "Option::is_none")]
+    pub mud_signature: Option<String>,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -83,79 +88,111 @@ pub struct Ace {
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Matches {
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub ipv4: Option<Ipv4>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub ipv6: Option<Ipv6>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub tcp: Option<Tcp>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub udp: Option<Udp>,
-    #[serde(rename = "ietf-mud:mud")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub icmp: Option<Icmp>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub eth: Option<Eth>,
+    #[serde(rename = "ietf-mud:mud", skip_serializing_if = "Option::is_none")]
     pub mud: Option<MudExtension>,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Tcp {
-    #[serde(rename = "source-port")]
+    #[serde(rename = "source-port", skip_serializing_if = "Option::is_none")]
     pub source_port: Option<Port>,
-    #[serde(rename = "destination-port")]
+    #[serde(rename = "destination-port", skip_serializing_if = "Option::is_none")]
     pub destination_port: Option<Port>,
-    #[serde(rename = "ietf-mud:direction-initiated")]
+    #[serde(rename = "ietf-mud:direction-initiated", skip_serializing_if = "Option::is_none")]
     pub direction_initiated: Option<String>,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Udp {
-    #[serde(rename = "source-port")]
+    #[serde(rename = "source-port", skip_serializing_if = "Option::is_none")]
     pub source_port: Option<Port>,
-    #[serde(rename = "destination-port")]
+    #[serde(rename = "destination-port", skip_serializing_if = "Option::is_none")]
     pub destination_port: Option<Port>,
 }
 
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Eth {
+    #[serde(rename = "destination-mac-address", skip_serializing_if = "Option::is_none")]
+    pub destination_mac_address: Option<String>,
+    #[serde(rename = "source-mac-address", skip_serializing_if = "Option::is_none")]
+    pub source_mac_address: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ethertype: Option<serde_json::Value>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Icmp {
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub type_field: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<u8>,
+}
+
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Port {
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub operator: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub port: Option<u32>,
-    #[serde(rename = "lower-port")]
+    #[serde(rename = "lower-port", skip_serializing_if = "Option::is_none")]
     pub lower_port: Option<u32>,
-    #[serde(rename = "upper-port")]
+    #[serde(rename = "upper-port", skip_serializing_if = "Option::is_none")]
     pub upper_port: Option<u32>,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct MudExtension {
-    #[serde(rename = "controller")]
+    #[serde(rename = "controller", skip_serializing_if = "Option::is_none")]
     pub controller: Option<String>,
-    #[serde(rename = "my-controller")]
+    #[serde(rename = "my-controller", skip_serializing_if = "Option::is_none")]
     pub my_controller: Option<Vec<serde_json::Value>>,
-    #[serde(rename = "local-networks")]
+    #[serde(rename = "local-networks", skip_serializing_if = "Option::is_none")]
     pub local_networks: Option<serde_json::Value>,
-    #[serde(rename = "same-manufacturer")]
+    #[serde(rename = "same-manufacturer", skip_serializing_if = "Option::is_none")]
     pub same_manufacturer: Option<serde_json::Value>,
-    pub manufacturer: Option<serde_json::Value>,
-    pub model: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub manufacturer: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Ipv4 {
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub protocol: Option<u32>,
-    #[serde(rename = "source-ipv4-network")]
+    #[serde(rename = "source-ipv4-network", skip_serializing_if = "Option::is_none")]
     pub source_ipv4_network: Option<String>,
-    #[serde(rename = "destination-ipv4-network")]
+    #[serde(rename = "destination-ipv4-network", skip_serializing_if = "Option::is_none")]
     pub destination_ipv4_network: Option<String>,
-    #[serde(rename = "ietf-acldns:src-dnsname")]
+    #[serde(rename = "ietf-acldns:src-dnsname", skip_serializing_if = "Option::is_none")]
     pub src_dnsname: Option<String>,
-    #[serde(rename = "ietf-acldns:dst-dnsname")]
+    #[serde(rename = "ietf-acldns:dst-dnsname", skip_serializing_if = "Option::is_none")]
     pub dst_dnsname: Option<String>,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Ipv6 {
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub protocol: Option<u32>,
-    #[serde(rename = "source-ipv6-network")]
+    #[serde(rename = "source-ipv6-network", skip_serializing_if = "Option::is_none")]
     pub source_ipv6_network: Option<String>,
-    #[serde(rename = "destination-ipv6-network")]
+    #[serde(rename = "destination-ipv6-network", skip_serializing_if = "Option::is_none")]
     pub destination_ipv6_network: Option<String>,
-    #[serde(rename = "ietf-acldns:dst-dnsname")]
+    #[serde(rename = "ietf-acldns:dst-dnsname", skip_serializing_if = "Option::is_none")]
     pub dst_dnsname: Option<String>,
-    #[serde(rename = "ietf-acldns:src-dnsname")]
+    #[serde(rename = "ietf-acldns:src-dnsname", skip_serializing_if = "Option::is_none")]
     pub src_dnsname: Option<String>,
 }
 
@@ -1,48 +1,77 @@
 // Copyright 2020-2021, Benjamin Ludewig, Florian Bonetti, Jeffrey Munstermann, Luca Nittscher, Hugo Damer, Michael Bach
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
-use chrono::{DateTime, NaiveDate, TimeZone, Utc};
+use chrono::{DateTime, Duration, NaiveDate, NaiveDateTime, TimeZone, Utc};
 use url::Url;
 
 use crate::{
     db::DbConnection,
     error,
     error::Result,
-    models::{Acl, MudData, MudDbo, MudDboRefresh},
-    services::{firewall_configuration_service::update_config_version, mud_service::fetch::fetch_mud},
+    models::{parse_stored_mud_data, Acl, MudData, MudDbo, MudDboRefresh, MudSignatureStatus},
+    services::{
+        firewall_configuration_service::update_config_version,
+        mud_service::{
+            authority::MudAuthorityPolicy,
+            signature::{get_signature_policy, verify_mud_signature, MudSignaturePolicy},
+            source::{configured_sources, fetch_from_sources, MudSource, SourcedMud},
+        },
+    },
 };
 
+pub mod ace_patch;
+pub mod authority;
+pub mod export;
 mod fetch;
+pub mod inheritance;
 pub mod json_models;
 pub mod parser;
+pub mod refresh;
+pub mod repository;
+pub mod revision;
+pub mod signature;
+pub mod source;
+pub mod validation;
+
+pub use fetch::init_ca_certs;
 
 /// Writes the `MudDbo` to the database.
-/// Upserts data by `MudDbo::url`
+/// Upserts data by `MudDbo::url` and records a new revision if the data changed
 pub async fn upsert_mud(mud_profile: &MudDbo, pool: &DbConnection) -> Result<()> {
     sqlx::query!(
-        "INSERT INTO mud_data (url, data, created_at, expiration) VALUES ($1, $2, $3, $4) ON CONFLICT(url) DO UPDATE SET data = excluded.data, created_at = excluded.created_at, expiration = excluded.expiration",
+        "INSERT INTO mud_data (url, data, created_at, expiration, signature_status, etag, last_modified, source) VALUES ($1, $2, $3, $4, $5, $6, $7, $8) ON CONFLICT(url) DO UPDATE SET data = excluded.data, created_at = excluded.created_at, expiration = excluded.expiration, signature_status = excluded.signature_status, etag = excluded.etag, last_modified = excluded.last_modified, source = excluded.source",
         mud_profile.url,
         mud_profile.data,
         mud_profile.created_at,
         mud_profile.expiration,
+        mud_profile.signature_status,
+        mud_profile.etag,
+        mud_profile.last_modified,
+        mud_profile.source,
     )
         .execute(pool)
         .await?;
+    revision::record_revision(&mud_profile.url, &mud_profile.data, pool).await?;
 
     Ok(())
 }
 
-/// Creates MUD Profile using `MudDbo` Data
+/// Creates MUD Profile using `MudDbo` Data and records it as the first revision
 pub async fn create_mud(mud_profile: &MudDbo, pool: &DbConnection) -> Result<()> {
     let _ins_count = sqlx::query!(
-        "INSERT INTO mud_data (url, data, created_at, expiration) VALUES ($1, $2, $3, $4)",
+        "INSERT INTO mud_data (url, data, created_at, expiration, signature_status, etag, last_modified, source) VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
         mud_profile.url,
         mud_profile.data,
         mud_profile.created_at,
         mud_profile.expiration,
+        mud_profile.signature_status,
+        mud_profile.etag,
+        mud_profile.last_modified,
+        mud_profile.source,
     )
     .execute(pool)
     .await?;
+    revision::record_revision(&mud_profile.url, &mud_profile.data, pool).await?;
 
     Ok(())
 }
@@ -64,6 +93,26 @@ pub async fn get_all_muds(pool: &DbConnection) -> Result<Vec<MudDbo>> {
     Ok(data)
 }
 
+/// Rewrites the stored data of all MUD-Profiles in the current format, upgrading data stored by older versions.
+pub async fn upgrade_stored_muds(pool: &DbConnection) -> Result<()> {
+    for mud in get_all_muds(pool).await? {
+        let data = match parse_stored_mud_data(&mud.data) {
+            Ok(mud_data) => serde_json::to_string(&mud_data)?,
+            Err(e) => {
+                warn!("Could not upgrade MUD-Profile {}: {:?}", mud.url, e);
+                continue;
+            },
+        };
+        if data != mud.data {
+            sqlx::query!("UPDATE mud_data SET data = $1 WHERE url = $2", data, mud.url)
+                .execute(pool)
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
 /// Deletes a MUD-Profile using the MUD-URL/-Name
 pub async fn delete_mud(url: &str, pool: &DbConnection) -> Result<bool> {
     let del_count = sqlx::query!("DELETE FROM mud_data WHERE url = $1", url)
@@ -82,12 +131,15 @@ pub async fn is_mud_used(url: &str, pool: &DbConnection) -> Result<bool> {
     Ok(device_using_mud.is_some())
 }
 
-/// This function return `MudDboRefresh` they only containing url and expiration
+/// This function return `MudDboRefresh` they only containing url, expiration and the next scheduled refresh
 /// to reduce payload.
 async fn get_all_mud_expiration(pool: &DbConnection) -> Result<Vec<MudDboRefresh>> {
-    Ok(sqlx::query_as!(MudDboRefresh, "SELECT url, expiration FROM mud_data")
-        .fetch_all(pool)
-        .await?)
+    Ok(sqlx::query_as!(
+        MudDboRefresh,
+        "SELECT mud_data.url, mud_data.expiration, mud_fetch_status.next_refresh FROM mud_data LEFT JOIN mud_fetch_status ON mud_data.url = mud_fetch_status.url"
+    )
+    .fetch_all(pool)
+    .await?)
 }
 
 /// Returns a MUD-Profile Data using the MUD-URL
@@ -95,8 +147,47 @@ async fn get_all_mud_expiration(pool: &DbConnection) -> Result<Vec<MudDboRefresh
 /// If it exists, uses existing data from DB
 /// This function is mainly used in the `RPCServer`, where it's used to save Device's MUD-URLs which are being sent via DHCP
 /// Local MUD-Profiles can be loaded *BUT NOT CREATED* through this function, since they have an expiration far far in the future
+/// If the MUD-Profile is pinned to a revision, the MUD-File of that revision is returned instead of the latest one
+/// MUD-URLs of blocked authorities are refused, MUD-Files of untrusted authorities only apply once an admin approved them
+/// Custom MUD-Profiles with a parent inherit the ACLs of the parent, see `inheritance`
+/// The ACE patches of the MUD-Profile are applied to its ACLs, see `ace_patch`
+/// MUD-Files are retrieved from the sources configured in `MUD_SOURCES`
 pub async fn get_or_fetch_mud(url: &str, pool: &DbConnection) -> Result<MudData> {
-    let mut expired_mud_data: Option<MudData> = None;
+    get_or_fetch_mud_from_sources(url, &configured_sources(), pool).await
+}
+
+/// Like `get_or_fetch_mud`, but retrieves MUD-Files from the given sources instead of the configured ones.
+pub async fn get_or_fetch_mud_from_sources(
+    url: &str,
+    sources: &[Box<dyn MudSource>],
+    pool: &DbConnection,
+) -> Result<MudData> {
+    let mud_data = get_effective_mud(url, sources, pool).await?;
+    ace_patch::apply_patches(mud_data, pool).await
+}
+
+/// Like `get_or_fetch_mud_from_sources`, but without evaluating the parent and the ACE patches.
+async fn get_effective_mud(url: &str, sources: &[Box<dyn MudSource>], pool: &DbConnection) -> Result<MudData> {
+    if authority::get_authority_policy(url, pool).await == MudAuthorityPolicy::Blocked {
+        return error::MudError {
+            message: format!("The authority of {} is blocked", url),
+        }
+        .fail();
+    }
+    let mud_data = get_or_fetch_latest_mud(url, sources, pool).await?;
+    let mut mud_data = revision::apply_pinned_revision(mud_data, pool).await?;
+    // MUD-Files of untrusted authorities are held until an admin approves them, only the overrides apply until then
+    if let Some(mud) = get_mud(url, pool).await {
+        if authority::is_pending_approval(&mud, pool).await {
+            mud_data.acllist = Vec::new();
+        }
+    }
+    Ok(mud_data)
+}
+
+/// Like `get_effective_mud`, but ignores pinned revisions and approvals.
+async fn get_or_fetch_latest_mud(url: &str, sources: &[Box<dyn MudSource>], pool: &DbConnection) -> Result<MudData> {
+    let mut expired_mud: Option<(MudDbo, MudData)> = None;
 
     // lookup datenbank ob schon existiert und nicht abgelaufen
     if let Some(mud) = get_mud(url, pool).await {
@@ -104,7 +195,7 @@ pub async fn get_or_fetch_mud(url: &str, pool: &DbConnection) -> Result<MudData>
             if mud.expiration > Utc::now().naive_utc() {
                 return Ok(mud_data);
             }
-            expired_mud_data = Some(mud_data);
+            expired_mud = Some((mud, mud_data));
         }
     }
 
@@ -113,40 +204,142 @@ pub async fn get_or_fetch_mud(url: &str, pool: &DbConnection) -> Result<MudData>
         error::MudFileInvalid {}.fail()?;
     }
 
-    // wenn nicht: fetch
     // falls ein fehler auftritt verwende die alte mud_data
-    let mud_json = match fetch_mud(url).await {
-        Ok(m) => m,
-        Err(e) => return expired_mud_data.ok_or(e),
+    match fetch_and_store_mud(url, expired_mud.clone(), sources, pool).await {
+        Ok(mud_data) => Ok(mud_data),
+        Err(e) => match expired_mud {
+            Some((_, mud_data)) => {
+                warn!(
+                    "Using expired MUD-Profile {}, since it couldn't be refreshed: {:?}",
+                    url, e
+                );
+                Ok(mud_data)
+            },
+            None => Err(e),
+        },
+    }
+}
+
+/// Retrieves the MUD-File from the sources and stores it as MUD-Profile, keeping the overrides of the expired one.
+/// Unlike `get_or_fetch_mud`, errors are returned instead of falling back to the expired MUD-Profile.
+async fn fetch_and_store_mud(
+    url: &str,
+    expired_mud: Option<(MudDbo, MudData)>,
+    sources: &[Box<dyn MudSource>],
+    pool: &DbConnection,
+) -> Result<MudData> {
+    // die sources der reihe nach fragen, bedingt falls die alte mud_data validators hat
+    let (source, mud_file) = match fetch_from_sources(url, expired_mud.as_ref().map(|(mud, _)| mud), sources).await? {
+        (source, SourcedMud::Modified(mud_file)) => (source, mud_file),
+        (_, SourcedMud::NotModified) => {
+            return match expired_mud {
+                Some((mud, mud_data)) => extend_mud_expiration(mud, mud_data, pool).await,
+                None => error::MudFileInvalid {}.fail(),
+            };
+        },
     };
 
     // ruf parse_mud auf
-    // falls ein fehler auftritt verwende die alte mud_data
-    let mut data = match parser::parse_mud(url.to_string(), mud_json.as_str()) {
-        Ok(d) => d,
-        Err(e) => return expired_mud_data.ok_or(e),
-    };
-    // acl_override von alter mud_data übernehmen
-    if let Some(MudData { acl_override, .. }) = expired_mud_data {
-        data.acl_override = acl_override;
+    let mud_json = mud_file.mud_json;
+    let mut data = parser::parse_mud(url.to_string(), mud_json.as_str())?;
+    // acl_override und ace_patches von alter mud_data übernehmen
+    if let Some((
+        _,
+        MudData {
+            acl_override,
+            ace_patches,
+            ..
+        },
+    )) = &expired_mud
+    {
+        data.acl_override = acl_override.clone();
+        data.ace_patches = ace_patches.clone();
     }
 
+    // signatur prüfen und die signature policy anwenden
+    let signature_status = verify_mud_signature(&data, mud_json.as_str(), mud_file.signature.as_deref()).await;
+    if signature_status != MudSignatureStatus::Valid {
+        match get_signature_policy(pool).await {
+            MudSignaturePolicy::Reject => error::MudError {
+                message: format!("MUD-File signature is {}", signature_status.as_ref()),
+            }
+            .fail()?,
+            MudSignaturePolicy::Warn => warn!("Accepting MUD-File {} with {:?} signature", url, signature_status),
+            MudSignaturePolicy::Quarantine => warn!(
+                "Quarantining devices using MUD-File {} with {:?} signature",
+                url, signature_status
+            ),
+        }
+    }
+    data.signature_status = Some(signature_status);
+
+    // an approval only covers the MUD-File the admin reviewed, changed MUD-Files have to be approved again
+    let approved_at = match &expired_mud {
+        Some((mud, mud_data)) if has_same_content(mud_data, &data) => mud.approved_at,
+        _ => None,
+    };
+
     // speichern in db
+    let expired_mud = expired_mud.map(|(mud, _)| mud);
     let mud = MudDbo {
         url: url.to_string(),
         data: serde_json::to_string(&data)?,
         created_at: Utc::now().naive_utc(),
         expiration: data.expiration.naive_utc(),
+        signature_status: Some(signature_status.as_ref().to_string()),
+        etag: mud_file.validators.etag,
+        last_modified: mud_file.validators.last_modified,
+        pinned_revision_id: expired_mud.as_ref().and_then(|mud| mud.pinned_revision_id),
+        source: Some(source.to_string()),
+        approved_at,
     };
 
     debug!("new/updating mud profile: {:?}", mud);
 
     upsert_mud(&mud, pool).await?;
+    // `upsert_mud` keeps the approval of the stored MUD-Profile
+    if approved_at.is_none() {
+        authority::revoke_approval(url, pool).await?;
+    }
 
     // return muddata
     Ok(data)
 }
 
+/// Whether both MUD-Profiles have the same content, ignoring when they were fetched and the result of the signature
+/// verification.
+fn has_same_content(mud_data: &MudData, other: &MudData) -> bool {
+    MudData {
+        signature_status: other.signature_status,
+        fetch_status: other.fetch_status.clone(),
+        expiration: other.expiration,
+        ..mud_data.clone()
+    } == *other
+}
+
+/// Extends the expiration of a MUD-Profile whose MUD-File didn't change since it was fetched.
+async fn extend_mud_expiration(mud: MudDbo, mut mud_data: MudData, pool: &DbConnection) -> Result<MudData> {
+    // the profile expires the cache validity of the MUD-File after it was stored, see `parser::parse_mud`
+    let cache_validity = (mud.expiration - mud.created_at).clamp(Duration::hours(1), Duration::hours(168));
+    let now = Utc::now();
+    mud_data.expiration = now + cache_validity;
+    let mud = MudDbo {
+        data: serde_json::to_string(&mud_data)?,
+        created_at: now.naive_utc(),
+        expiration: mud_data.expiration.naive_utc(),
+        ..mud
+    };
+
+    debug!(
+        "MUD-File {} not modified, extending expiration to {}",
+        mud.url, mud.expiration
+    );
+
+    upsert_mud(&mud, pool).await?;
+
+    Ok(mud_data)
+}
+
 /// Checks if the given string is an URL. Used to check if the MUD-Profile being created is local or needs to be fetched.
 pub fn is_url(url: &str) -> bool {
     Url::parse(url).is_ok()
@@ -157,11 +350,18 @@ pub fn generate_empty_custom_mud_profile(url: &str, acl_override: Vec<Acl>) -> M
     MudData {
         url: url.to_string(),
         masa_url: None,
+        mud_signature: None,
+        signature_status: None,
         last_update: Utc::now().naive_utc().to_string(),
         systeminfo: None,
         mfg_name: None,
         model_name: None,
         documentation: None,
+        is_supported: true,
+        cache_validity: None,
+        fetch_status: None,
+        parent_url: None,
+        ace_patches: Vec::new(),
         expiration: get_custom_mud_expiration(),
         acllist: vec![],
         acl_override,
@@ -173,26 +373,83 @@ pub fn get_custom_mud_expiration() -> DateTime<Utc> {
     Utc.from_utc_datetime(&NaiveDate::from_ymd(2060, 1, 31).and_hms(0, 0, 0))
 }
 
+/// Refreshes the MUD-Profiles whose refresh is due, see `refresh`. MUD-URLs that fail to refresh are retried later
+/// and don't keep the other MUD-Profiles from being refreshed.
 pub async fn update_outdated_profiles(db_pool: &DbConnection) -> Result<()> {
+    update_outdated_profiles_from_sources(&configured_sources(), db_pool).await
+}
+
+/// Like `update_outdated_profiles`, but retrieves MUD-Files from the given sources instead of the configured ones.
+pub async fn update_outdated_profiles_from_sources(
+    sources: &[Box<dyn MudSource>],
+    db_pool: &DbConnection,
+) -> Result<()> {
     debug!("Update outdated profiles");
     let mud_data = get_all_mud_expiration(&db_pool).await?;
+    let now = Utc::now().naive_utc();
     let mud_vec: Vec<String> = mud_data
         .into_iter()
-        .filter(|mud| mud.expiration < Utc::now().naive_utc())
+        .filter(|mud| refresh::refresh_due_at(mud) < now)
         .map(|mud| mud.url)
         .collect();
     if mud_vec.is_empty() {
         return Ok(());
     }
-    update_mud_urls(mud_vec, &db_pool).await?;
-    update_config_version(&db_pool).await
+    // unchanged profiles result in the same firewall rules, so the enforcers don't have to reload them
+    if update_mud_urls(mud_vec, sources, &db_pool).await {
+        update_config_version(&db_pool).await?;
+    }
+    Ok(())
+}
+
+/// Returns when the next MUD-Profile is due to be refreshed, if there are any MUD-Profiles.
+pub async fn get_next_refresh(db_pool: &DbConnection) -> Result<Option<NaiveDateTime>> {
+    Ok(get_all_mud_expiration(&db_pool)
+        .await?
+        .iter()
+        .map(refresh::refresh_due_at)
+        .min())
 }
 
-async fn update_mud_urls(vec_url: Vec<String>, db_pool: &DbConnection) -> Result<()> {
+/// Updates the MUD-Profiles and returns whether any of them changed, apart from their expiration.
+async fn update_mud_urls(vec_url: Vec<String>, sources: &[Box<dyn MudSource>], db_pool: &DbConnection) -> bool {
+    let mut changed = false;
     for mud_url in vec_url {
         debug!("Try to update url: {}", mud_url);
-        let updated_mud = get_or_fetch_mud(&mud_url, db_pool).await?;
-        debug!("Updated mud profile: {:#?}", updated_mud);
+        match refresh_mud(&mud_url, sources, db_pool).await {
+            Ok(mud_changed) => changed |= mud_changed,
+            Err(e) => warn!("Failed to update MUD-Profile {}: {:?}", mud_url, e),
+        }
     }
-    Ok(())
+    changed
+}
+
+/// Refreshes the MUD-Profile if it is expired and records the result in its fetch status.
+/// Returns whether the MUD-Profile changed, apart from its expiration.
+async fn refresh_mud(url: &str, sources: &[Box<dyn MudSource>], db_pool: &DbConnection) -> Result<bool> {
+    let previous_mud = match get_mud(url, db_pool).await {
+        Some(mud) => mud,
+        None => return Ok(false),
+    };
+    let previous_mud_data = previous_mud.parse_data()?;
+    // the MUD-Profile may have been refreshed since, when it was requested for a device. That fetch isn't a refresh
+    // attempt of ours, so only the next refresh is rescheduled and the fetch status is left as it is
+    if previous_mud.expiration > Utc::now().naive_utc() {
+        refresh::reschedule(url, previous_mud.expiration, db_pool).await?;
+        return Ok(false);
+    }
+    let updated_mud =
+        match fetch_and_store_mud(url, Some((previous_mud, previous_mud_data.clone())), sources, db_pool).await {
+            Ok(updated_mud) => updated_mud,
+            Err(e) => {
+                refresh::record_failure(url, &e, db_pool).await?;
+                return Err(e);
+            },
+        };
+    debug!("Updated mud profile: {:#?}", updated_mud);
+    refresh::record_success(url, updated_mud.expiration.naive_utc(), db_pool).await?;
+    Ok(MudData {
+        expiration: updated_mud.expiration,
+        ..previous_mud_data
+    } != updated_mud)
 }
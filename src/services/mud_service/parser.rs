@@ -1,18 +1,28 @@
 // Copyright 2020-2021, Benjamin Ludewig, Florian Bonetti, Jeffrey Munstermann, Luca Nittscher, Hugo Damer, Michael Bach
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
-use std::{clone::Clone, net::IpAddr, str::FromStr};
+use std::{clone::Clone, convert::TryFrom};
 
 use chrono::{Duration, Utc};
+use ipnetwork::IpNetwork;
+use namib_shared::macaddr::MacAddr;
 use snafu::ensure;
 
 use super::json_models;
 use crate::{
     error,
     error::Result,
-    models::{Ace, AceAction, AceMatches, AcePort, AceProtocol, Acl, AclDirection, AclType, MudData},
+    models::{
+        Ace, AceAction, AceEthMatches, AceIcmpMatches, AceMatches, AceMudMatches, AcePort, AceProtocol, Acl,
+        AclDirection, AclType, MudData,
+    },
 };
 
+/// IANA protocol number of ICMP, used for icmp matches of ipv4 ACLs without an explicit protocol.
+const PROTOCOL_ICMP: u32 = 1;
+/// IANA protocol number of ICMPv6, used for icmp matches of ipv6 ACLs without an explicit protocol.
+const PROTOCOL_ICMPV6: u32 = 58;
+
 // inspired by https://github.com/CiscoDevNet/MUD-Manager by Cisco
 pub fn parse_mud(url: String, json: &str) -> Result<MudData> {
     let mud_json: json_models::MudJson = serde_json::from_str(json)?;
@@ -32,6 +42,19 @@ pub fn parse_mud(url: String, json: &str) -> Result<MudData> {
         }
     );
     let exptime = Utc::now() + Duration::hours(cachevalidity);
+    // a MUD-File served for another MUD-URL could be used to impersonate that device's policy
+    ensure!(
+        mud_data.mud_url == url,
+        error::MudError {
+            message: format!(
+                "MUD-File 'mud-url' {} doesn't match the MUD-URL {}",
+                mud_data.mud_url, url
+            )
+        }
+    );
+    if !mud_data.is_supported {
+        warn!("MUD-File {} states that the device is no longer supported", url);
+    }
 
     // parse masa
     let mut masa_uri = None;
@@ -61,11 +84,18 @@ pub fn parse_mud(url: String, json: &str) -> Result<MudData> {
     let data = MudData {
         url,
         masa_url: masa_uri,
+        mud_signature: mud_data.mud_signature.clone(),
+        signature_status: None,
         last_update: mud_data.last_update.clone(),
         systeminfo: mud_data.systeminfo.clone(),
         mfg_name: mud_data.mfg_name.clone(),
         model_name: mud_data.model_name.clone(),
         documentation: mud_data.documentation.clone(),
+        is_supported: mud_data.is_supported,
+        cache_validity: mud_data.cache_validity,
+        fetch_status: None,
+        parent_url: None,
+        ace_patches: Vec::new(),
         expiration: exptime,
         acllist,
         acl_override: Vec::default(),
@@ -90,22 +120,20 @@ fn parse_device_policy(
         for aclitem in &mud_json.acls.acl {
             if aclitem.name == access_list.name {
                 let mut ace: Vec<Ace> = Vec::new();
-                let acl_type = if aclitem.type_field == "ipv4-acl-type" {
-                    AclType::IPV4
-                } else {
-                    AclType::IPV6
-                };
+                let acl_type = parse_acl_type(&aclitem.type_field)?;
                 for aceitem in &aclitem.aces.ace {
                     let mut protocol = None;
                     let mut direction_initiated = None;
-                    let mut address_mask = None;
-                    let mut dnsname = None;
+                    let mut source_network = None;
+                    let mut destination_network = None;
+                    let mut source_dnsname = None;
+                    let mut destination_dnsname = None;
                     let mut source_port = None;
                     let mut destination_port = None;
                     if let Some(udp) = &aceitem.matches.udp {
                         protocol = Some(AceProtocol::Udp);
-                        source_port = udp.source_port.as_ref().and_then(|p| parse_mud_port(p).ok());
-                        destination_port = udp.destination_port.as_ref().and_then(|p| parse_mud_port(p).ok());
+                        source_port = udp.source_port.as_ref().map(parse_mud_port).transpose()?;
+                        destination_port = udp.destination_port.as_ref().map(parse_mud_port).transpose()?;
                     } else if let Some(tcp) = &aceitem.matches.tcp {
                         protocol = Some(AceProtocol::Tcp);
                         if let Some(dir) = &tcp.direction_initiated {
@@ -119,8 +147,8 @@ fn parse_device_policy(
                             });
                         }
 
-                        source_port = tcp.source_port.as_ref().and_then(|p| parse_mud_port(p).ok());
-                        destination_port = tcp.destination_port.as_ref().and_then(|p| parse_mud_port(p).ok());
+                        source_port = tcp.source_port.as_ref().map(parse_mud_port).transpose()?;
+                        destination_port = tcp.destination_port.as_ref().map(parse_mud_port).transpose()?;
                     }
                     if let Some(ipv6) = &aceitem.matches.ipv6 {
                         if acl_type != AclType::IPV6 {
@@ -129,13 +157,20 @@ fn parse_device_policy(
                             }
                             .fail()?
                         }
-                        protocol = ipv6.protocol.map(AceProtocol::Protocol);
-                        address_mask = ipv6
-                            .source_ipv6_network
-                            .as_ref()
-                            .or_else(|| ipv6.destination_ipv6_network.as_ref())
-                            .and_then(|srcip| IpAddr::from_str(srcip.as_str()).ok());
-                        dnsname = ipv6.dst_dnsname.clone().or_else(|| ipv6.src_dnsname.clone());
+                        protocol = ipv6
+                            .protocol
+                            .map(parse_protocol)
+                            .transpose()?
+                            .map(AceProtocol::Protocol)
+                            .or(protocol);
+                        source_network = ipv6.source_ipv6_network.as_deref().map(parse_network).transpose()?;
+                        destination_network = ipv6
+                            .destination_ipv6_network
+                            .as_deref()
+                            .map(parse_network)
+                            .transpose()?;
+                        source_dnsname = ipv6.src_dnsname.clone();
+                        destination_dnsname = ipv6.dst_dnsname.clone();
                     } else if let Some(ipv4) = &aceitem.matches.ipv4 {
                         if acl_type != AclType::IPV4 {
                             error::MudError {
@@ -143,17 +178,53 @@ fn parse_device_policy(
                             }
                             .fail()?
                         }
-                        protocol = ipv4.protocol.map(AceProtocol::Protocol);
-                        address_mask = ipv4
-                            .source_ipv4_network
-                            .as_ref()
-                            .or_else(|| ipv4.destination_ipv4_network.as_ref())
-                            .and_then(|srcip| IpAddr::from_str(srcip.as_str()).ok());
-                        dnsname = ipv4.dst_dnsname.clone().or_else(|| ipv4.src_dnsname.clone());
+                        protocol = ipv4
+                            .protocol
+                            .map(parse_protocol)
+                            .transpose()?
+                            .map(AceProtocol::Protocol)
+                            .or(protocol);
+                        source_network = ipv4.source_ipv4_network.as_deref().map(parse_network).transpose()?;
+                        destination_network = ipv4
+                            .destination_ipv4_network
+                            .as_deref()
+                            .map(parse_network)
+                            .transpose()?;
+                        source_dnsname = ipv4.src_dnsname.clone();
+                        destination_dnsname = ipv4.dst_dnsname.clone();
                     }
-                    if let Some(_mud) = &aceitem.matches.mud {
-                        // see https://github.com/CiscoDevNet/MUD-Manager/blob/master/src/mud_manager.c#L1472
+                    let icmp = aceitem.matches.icmp.as_ref().map(|icmp| AceIcmpMatches {
+                        icmp_type: icmp.type_field,
+                        code: icmp.code,
+                    });
+                    if icmp.is_some() && protocol.is_none() {
+                        protocol = Some(AceProtocol::Protocol(if acl_type == AclType::IPV6 {
+                            PROTOCOL_ICMPV6
+                        } else {
+                            PROTOCOL_ICMP
+                        }));
+                    }
+                    let eth = aceitem.matches.eth.as_ref().map(parse_eth_matches).transpose()?;
+                    if eth.is_some() && acl_type != AclType::Ethernet {
+                        error::MudError {
+                            message: String::from("Ethernet ACE in IP ACL"),
+                        }
+                        .fail()?
+                    }
+                    if acl_type == AclType::Ethernet && (protocol.is_some() || icmp.is_some()) {
+                        error::MudError {
+                            message: String::from("IP ACE in ethernet ACL"),
+                        }
+                        .fail()?
                     }
+                    let mud = aceitem.matches.mud.as_ref().map(|mud| AceMudMatches {
+                        local_networks: mud.local_networks.is_some(),
+                        same_manufacturer: mud.same_manufacturer.is_some(),
+                        manufacturer: mud.manufacturer.clone(),
+                        model: mud.model.clone(),
+                        controller: mud.controller.clone(),
+                        my_controller: mud.my_controller.is_some(),
+                    });
                     ace.push(Ace {
                         name: aceitem.name.clone(),
                         action: if aceitem.actions.forwarding == "accept" {
@@ -164,10 +235,15 @@ fn parse_device_policy(
                         matches: AceMatches {
                             protocol,
                             direction_initiated,
-                            address_mask: address_mask.map(|a| a.to_string()),
-                            dnsname,
+                            source_network: source_network.map(|n| n.to_string()),
+                            destination_network: destination_network.map(|n| n.to_string()),
+                            source_dnsname,
+                            destination_dnsname,
                             source_port,
                             destination_port,
+                            icmp,
+                            eth,
+                            mud,
                         },
                     })
                 }
@@ -193,16 +269,108 @@ fn parse_device_policy(
     Ok(())
 }
 
-fn parse_mud_port(port: &json_models::Port) -> Result<AcePort> {
+/// Parses the type of an ACL, with or without the module prefix of the identity.
+/// `ipv4`, `ipv6` and `ethernet-acl-type` are not defined by RFC 8519, but used by some MUD-File generators, so they
+/// are accepted as well, see `is_standard_acl_type`. Mixed ethernet and ip ACLs aren't supported.
+pub(super) fn parse_acl_type(acl_type: &str) -> Result<AclType> {
+    match acl_type.trim_start_matches("ietf-access-control-list:") {
+        "ipv4-acl-type" | "ipv4" => Ok(AclType::IPV4),
+        "ipv6-acl-type" | "ipv6" => Ok(AclType::IPV6),
+        "eth-acl-type" | "ethernet-acl-type" => Ok(AclType::Ethernet),
+        "mixed-eth-ipv4-acl-type" | "mixed-eth-ipv6-acl-type" | "mixed-eth-ipv4-ipv6-acl-type" => error::MudError {
+            message: format!("Mixed ethernet and IP ACL type '{}' is not supported", acl_type),
+        }
+        .fail(),
+        _ => error::MudError {
+            message: format!("Unsupported ACL type '{}'", acl_type),
+        }
+        .fail(),
+    }
+}
+
+/// Checks whether the type of an ACL is one of the identities defined by RFC 8519.
+pub(super) fn is_standard_acl_type(acl_type: &str) -> bool {
+    matches!(
+        acl_type.trim_start_matches("ietf-access-control-list:"),
+        "ipv4-acl-type" | "ipv6-acl-type" | "eth-acl-type"
+    )
+}
+
+fn parse_eth_matches(eth: &json_models::Eth) -> Result<AceEthMatches> {
+    Ok(AceEthMatches {
+        ethertype: eth.ethertype.as_ref().map(parse_ethertype).transpose()?,
+        source_mac_address: eth.source_mac_address.as_deref().map(parse_mac_address).transpose()?,
+        destination_mac_address: eth
+            .destination_mac_address
+            .as_deref()
+            .map(parse_mac_address)
+            .transpose()?,
+    })
+}
+
+/// Parses an ethertype, given either as a number or as a hexadecimal string like `0x0800`.
+pub(super) fn parse_ethertype(ethertype: &serde_json::Value) -> Result<u16> {
+    let parsed = match ethertype {
+        serde_json::Value::Number(number) => number.as_u64().and_then(|number| u16::try_from(number).ok()),
+        serde_json::Value::String(string) => match string.strip_prefix("0x") {
+            Some(hex) => u16::from_str_radix(hex, 16).ok(),
+            None => string.parse().ok(),
+        },
+        _ => None,
+    };
+    match parsed {
+        Some(ethertype) => Ok(ethertype),
+        None => error::MudError {
+            message: format!("Invalid ethertype '{}'", ethertype),
+        }
+        .fail(),
+    }
+}
+
+pub(super) fn parse_mac_address(mac_address: &str) -> Result<String> {
+    match mac_address.parse::<MacAddr>() {
+        Ok(_) => Ok(mac_address.to_lowercase()),
+        Err(_) => error::MudError {
+            message: format!("Invalid mac address '{}'", mac_address),
+        }
+        .fail(),
+    }
+}
+
+/// Checks that a protocol of an ipv4/ipv6 match is a valid IP protocol number.
+fn parse_protocol(protocol: u32) -> Result<u32> {
+    ensure!(
+        protocol <= u32::from(u8::MAX),
+        error::MudError {
+            message: format!("Invalid protocol number {}", protocol)
+        }
+    );
+    Ok(protocol)
+}
+
+/// Parses an ipv4/ipv6 network match, keeping the prefix length.
+/// Networks without a prefix length are treated as single addresses.
+pub(super) fn parse_network(network: &str) -> Result<IpNetwork> {
+    network.parse::<IpNetwork>().or_else(|_| {
+        error::MudError {
+            message: format!("Invalid network '{}'", network),
+        }
+        .fail()
+    })
+}
+
+pub(super) fn parse_mud_port(port: &json_models::Port) -> Result<AcePort> {
     match port {
-        json_models::Port { port: Some(p), .. } => {
-            ensure!(
-                port.operator == Some(String::from("eq")),
-                error::MudError {
-                    message: String::from("Only 'eq' operator is supported")
-                }
-            );
-            Ok(AcePort::Single(*p))
+        json_models::Port { port: Some(p), .. } => match port.operator.as_deref() {
+            // `eq` is the default operator of ietf-acl port matches
+            Some("eq") | None => Ok(AcePort::Single(*p)),
+            Some("lte") => Ok(AcePort::Lte(*p)),
+            Some("gte") => Ok(AcePort::Gte(*p)),
+            Some("neq") => Ok(AcePort::Neq(*p)),
+            Some(operator) => error::MudError {
+                message: format!("Unsupported port operator '{}'", operator),
+            }
+            .fail(),
         },
         json_models::Port {
             upper_port: Some(upper_port),
@@ -229,7 +397,7 @@ mod tests {
     use std::{fs::File, io::Read};
 
     use chrono::{offset::TimeZone, NaiveDateTime, Utc};
-    use serde_json::Value;
+    use serde_json::{json, Value};
 
     use super::*;
 
@@ -245,10 +413,22 @@ mod tests {
         let matches = AceMatches {
             protocol: None,
             direction_initiated: None,
-            address_mask: None,
-            dnsname: None,
+            source_network: None,
+            destination_network: None,
+            source_dnsname: None,
+            destination_dnsname: None,
             source_port: None,
             destination_port: None,
+            icmp: None,
+            eth: None,
+            mud: Some(AceMudMatches {
+                local_networks: false,
+                same_manufacturer: true,
+                manufacturer: None,
+                model: None,
+                controller: None,
+                my_controller: false,
+            }),
         };
 
         let mut ace_list_f: Vec<Ace> = Vec::new();
@@ -283,7 +463,7 @@ mod tests {
         let mut acl = Acl {
             name: "mud-52892-v4fr".to_string(),
             packet_direction: AclDirection::FromDevice,
-            acl_type: AclType::IPV6,
+            acl_type: AclType::IPV4,
             ace: ace_list_f,
         };
 
@@ -296,11 +476,18 @@ mod tests {
         let example = MudData {
             url: URL.to_string(),
             masa_url: None,
+            mud_signature: None,
+            signature_status: None,
             last_update: "2019-07-23T19:54:24".to_string(),
             systeminfo: Some("The BMS Example Light Bulb".to_string()),
             mfg_name: None,
             model_name: None,
             documentation: Some("https://lighting.example.com/lightbulb2000/documentation".to_string()),
+            is_supported: true,
+            cache_validity: Some(48),
+            fetch_status: None,
+            parent_url: None,
+            ace_patches: Vec::new(),
             expiration: mud.expiration,
             acllist: acl_list,
             acl_override: Vec::default(),
@@ -310,6 +497,98 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_port_operators() -> Result<()> {
+        let parse_port = |port: serde_json::Value| parse_mud_port(&serde_json::from_value(port).unwrap());
+
+        assert_eq!(
+            parse_port(json!({"operator": "eq", "port": 443}))?,
+            AcePort::Single(443)
+        );
+        assert_eq!(parse_port(json!({"port": 443}))?, AcePort::Single(443));
+        assert_eq!(
+            parse_port(json!({"operator": "lte", "port": 1023}))?,
+            AcePort::Lte(1023)
+        );
+        assert_eq!(
+            parse_port(json!({"operator": "gte", "port": 49152}))?,
+            AcePort::Gte(49152)
+        );
+        assert_eq!(parse_port(json!({"operator": "neq", "port": 80}))?, AcePort::Neq(80));
+        assert_eq!(
+            parse_port(json!({"lower-port": 9000, "upper-port": 9050}))?,
+            AcePort::Range(9000, 9050)
+        );
+        assert!(parse_port(json!({"operator": "lt", "port": 80})).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_acl_types() -> Result<()> {
+        assert_eq!(parse_acl_type("ipv4-acl-type")?, AclType::IPV4);
+        assert_eq!(parse_acl_type("ietf-access-control-list:ipv6-acl-type")?, AclType::IPV6);
+        assert_eq!(parse_acl_type("eth-acl-type")?, AclType::Ethernet);
+        assert_eq!(parse_acl_type("ethernet-acl-type")?, AclType::Ethernet);
+        assert_eq!(parse_acl_type("ipv4")?, AclType::IPV4);
+        assert!(is_standard_acl_type("ietf-access-control-list:eth-acl-type"));
+        assert!(!is_standard_acl_type("ethernet-acl-type"));
+        assert!(parse_acl_type("mixed-eth-ipv4-acl-type").is_err());
+        assert!(parse_acl_type("mpls-acl-type").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_protocol_numbers() -> Result<()> {
+        assert_eq!(parse_protocol(47)?, 47);
+        assert_eq!(parse_protocol(255)?, 255);
+        assert!(parse_protocol(256).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_eth_matches() -> Result<()> {
+        let eth = parse_eth_matches(&json_models::Eth {
+            destination_mac_address: Some("FF:FF:FF:FF:FF:FF".to_string()),
+            source_mac_address: None,
+            ethertype: Some(json!("0x888e")),
+        })?;
+        assert_eq!(
+            eth,
+            AceEthMatches {
+                ethertype: Some(0x888e),
+                source_mac_address: None,
+                destination_mac_address: Some("ff:ff:ff:ff:ff:ff".to_string()),
+            }
+        );
+        assert_eq!(parse_ethertype(&json!(2048))?, 0x0800);
+        assert!(parse_ethertype(&json!("0xfffff")).is_err());
+        assert!(parse_mac_address("ff:ff:ff:ff:ff").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_mud_url_and_is_supported() -> Result<()> {
+        const URL: &str = "https://lighting.example.com/lightbulb2000";
+        let mut mud_json: Value =
+            serde_json::from_str(&std::fs::read_to_string("tests/mud_tests/MUD-Profile-example.json")?)?;
+
+        assert!(parse_mud(
+            "https://lighting.example.com/lightbulb3000".to_string(),
+            &mud_json.to_string()
+        )
+        .is_err());
+        assert!(parse_mud(URL.to_string(), &mud_json.to_string())?.is_supported);
+
+        mud_json["ietf-mud:mud"]["is-supported"] = json!(false);
+        assert!(!parse_mud(URL.to_string(), &mud_json.to_string())?.is_supported);
+
+        Ok(())
+    }
+
     #[test]
     fn test_example_amazon_echo() -> Result<()> {
         compare_mud_accept(
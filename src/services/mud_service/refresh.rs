@@ -0,0 +1,163 @@
+// Copyright 2020-2021, Benjamin Ludewig, Florian Bonetti, Jeffrey Munstermann, Luca Nittscher, Hugo Damer, Michael Bach
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use std::{collections::HashMap, convert::TryFrom};
+
+use chrono::{Duration, NaiveDateTime, Utc};
+use rand::Rng;
+use tokio::time::sleep;
+
+use crate::{
+    db::DbConnection,
+    error::{Error, Result},
+    models::{MudDboRefresh, MudFetchStatus, MudFetchStatusDbo},
+    services::mud_service::{get_next_refresh, update_outdated_profiles},
+};
+
+/// The delay before retrying a failed refresh, doubled for every consecutive failure.
+const INITIAL_BACKOFF_MINUTES: i64 = 5;
+/// The maximum delay before retrying a failed refresh.
+const MAX_BACKOFF_HOURS: i64 = 24;
+/// The maximum random delay added to a refresh, so MUD-Profiles fetched together aren't refreshed together.
+const MAX_JITTER_MINUTES: i64 = 60;
+/// The maximum time the refresh queue sleeps, so newly fetched MUD-Profiles are picked up.
+const MAX_IDLE_MINUTES: i64 = 5;
+
+/// Refreshes the MUD-Profiles when they are due, sleeping until the next one is due in between.
+pub async fn run_refresh_queue(conn: DbConnection) {
+    loop {
+        if let Err(e) = update_outdated_profiles(&conn).await {
+            warn!("Failed to update outdated profiles: {:?}", e);
+        }
+        let next_refresh = get_next_refresh(&conn).await.ok().flatten();
+        let max_idle = Duration::minutes(MAX_IDLE_MINUTES);
+        let delay = next_refresh.map_or(max_idle, |next_refresh| {
+            (next_refresh - Utc::now().naive_utc()).clamp(Duration::seconds(1), max_idle)
+        });
+        debug!("Next MUD-Profile refresh in {}", delay);
+        sleep(delay.to_std().unwrap_or_default()).await;
+    }
+}
+
+/// Returns when the MUD-Profile is due to be refreshed: when it expires, unless a refresh is already scheduled.
+pub fn refresh_due_at(mud: &MudDboRefresh) -> NaiveDateTime {
+    mud.next_refresh.unwrap_or(mud.expiration)
+}
+
+/// Returns the fetch status of the MUD-Profile, if it was refreshed before.
+pub async fn get_fetch_status(url: &str, pool: &DbConnection) -> Result<Option<MudFetchStatus>> {
+    Ok(
+        sqlx::query_as!(MudFetchStatusDbo, "SELECT * FROM mud_fetch_status WHERE url = $1", url)
+            .fetch_optional(pool)
+            .await?
+            .map(MudFetchStatus::from),
+    )
+}
+
+/// Returns the fetch status of all MUD-Profiles that were refreshed before, by their MUD-URL.
+pub async fn get_all_fetch_status(pool: &DbConnection) -> Result<HashMap<String, MudFetchStatus>> {
+    Ok(sqlx::query_as!(MudFetchStatusDbo, "SELECT * FROM mud_fetch_status")
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .map(|status| (status.url.clone(), MudFetchStatus::from(status)))
+        .collect())
+}
+
+/// Records a successful refresh and schedules the next one after the MUD-Profile expires.
+pub async fn record_success(url: &str, expiration: NaiveDateTime, pool: &DbConnection) -> Result<()> {
+    let now = Utc::now().naive_utc();
+    let next_refresh = expiration + jitter(expiration - now);
+    sqlx::query!(
+        "INSERT INTO mud_fetch_status (url, last_success, consecutive_failures, next_refresh) VALUES ($1, $2, 0, $3) ON CONFLICT(url) DO UPDATE SET last_success = excluded.last_success, consecutive_failures = 0, next_refresh = excluded.next_refresh",
+        url,
+        now,
+        next_refresh,
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Schedules the next refresh after the MUD-Profile expires, without recording a refresh attempt.
+/// Used when the MUD-Profile was fetched elsewhere, e.g. because a device requested it while it was expired.
+pub async fn reschedule(url: &str, expiration: NaiveDateTime, pool: &DbConnection) -> Result<()> {
+    let next_refresh = expiration + jitter(expiration - Utc::now().naive_utc());
+    sqlx::query!(
+        "UPDATE mud_fetch_status SET next_refresh = $2 WHERE url = $1",
+        url,
+        next_refresh,
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Records a failed refresh and schedules a retry, backing off exponentially with the number of consecutive failures.
+pub async fn record_failure(url: &str, error: &Error, pool: &DbConnection) -> Result<()> {
+    let consecutive_failures = get_fetch_status(url, pool)
+        .await?
+        .map_or(0, |status| status.consecutive_failures)
+        + 1;
+    let now = Utc::now().naive_utc();
+    let delay = backoff(consecutive_failures);
+    let next_refresh = now + delay + jitter(delay);
+    let last_error = error.to_string();
+    warn!(
+        "Refreshing MUD-Profile {} failed {} times in a row, retrying at {}: {}",
+        url, consecutive_failures, next_refresh, last_error
+    );
+    sqlx::query!(
+        "INSERT INTO mud_fetch_status (url, last_error, last_error_at, consecutive_failures, next_refresh) VALUES ($1, $2, $3, $4, $5) ON CONFLICT(url) DO UPDATE SET last_error = excluded.last_error, last_error_at = excluded.last_error_at, consecutive_failures = excluded.consecutive_failures, next_refresh = excluded.next_refresh",
+        url,
+        last_error,
+        now,
+        consecutive_failures,
+        next_refresh,
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// The delay before retrying a refresh that failed `consecutive_failures` times in a row.
+fn backoff(consecutive_failures: i64) -> Duration {
+    let exponent = u32::try_from(consecutive_failures - 1).unwrap_or(0).min(16);
+    (Duration::minutes(INITIAL_BACKOFF_MINUTES) * 2_i32.pow(exponent)).min(Duration::hours(MAX_BACKOFF_HOURS))
+}
+
+/// A random delay of up to a tenth of the given delay, but at most `MAX_JITTER_MINUTES`.
+fn jitter(delay: Duration) -> Duration {
+    let max_jitter = (delay / 10).min(Duration::minutes(MAX_JITTER_MINUTES)).num_seconds();
+    if max_jitter <= 0 {
+        return Duration::zero();
+    }
+    Duration::seconds(rand::thread_rng().gen_range(0..=max_jitter))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff() {
+        assert_eq!(backoff(1), Duration::minutes(5));
+        assert_eq!(backoff(2), Duration::minutes(10));
+        assert_eq!(backoff(4), Duration::minutes(40));
+        assert_eq!(backoff(9), Duration::hours(24));
+        assert_eq!(backoff(1000), Duration::hours(24));
+    }
+
+    #[test]
+    fn test_jitter() {
+        for _ in 0..100 {
+            let jitter = jitter(Duration::hours(1));
+            assert!(jitter >= Duration::zero() && jitter <= Duration::minutes(6));
+        }
+        assert!(jitter(Duration::days(7)) <= Duration::minutes(MAX_JITTER_MINUTES));
+        assert_eq!(jitter(Duration::seconds(-10)), Duration::zero());
+    }
+}
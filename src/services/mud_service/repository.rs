@@ -0,0 +1,168 @@
+// Copyright 2020-2021, Benjamin Ludewig, Florian Bonetti, Jeffrey Munstermann, Luca Nittscher, Hugo Damer, Michael Bach
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::RwLock,
+};
+
+use chrono::{NaiveDateTime, Utc};
+use lazy_static::lazy_static;
+use paperclip::actix::Apiv2Schema;
+
+use super::json_models;
+use crate::{app_config::APP_CONFIG, error::Result};
+
+/// The file extension of MUD-Files in the repository.
+const MUD_EXTENSION: &str = "json";
+/// The file extension of the detached signatures stored next to the MUD-Files in the repository.
+const SIGNATURE_EXTENSION: &str = "p7s";
+
+lazy_static! {
+    /// The MUD-Files found by the last scan of the repository
+    static ref REPOSITORY_INDEX: RwLock<MudRepositoryIndex> = RwLock::new(MudRepositoryIndex::default());
+}
+
+/// The MUD-Files of the local repository, indexed by their `mud-url`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MudRepositoryIndex {
+    /// When the repository was last scanned, `None` if no repository is configured.
+    pub scanned_at: Option<NaiveDateTime>,
+    pub entries: BTreeMap<String, MudRepositoryEntry>,
+}
+
+/// A MUD-File of the local repository.
+#[derive(Debug, Clone, PartialEq, Serialize, Apiv2Schema)]
+pub struct MudRepositoryEntry {
+    pub mud_url: String,
+    pub path: String,
+    /// The detached signature stored next to the MUD-File, with the same name and the extension `p7s`.
+    pub signature_path: Option<String>,
+}
+
+/// A MUD-File read from the local repository.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LocalMud {
+    pub mud_json: String,
+    pub signature: Option<Vec<u8>>,
+}
+
+/// Rescans the repository configured by `MUD_REPOSITORY`, replacing the current index.
+pub fn rescan_repository() {
+    let index = match &APP_CONFIG.mud_repository {
+        Some(directory) => scan_directory(Path::new(directory)),
+        None => return,
+    };
+    info!("Found {} MUD-Files in the MUD repository", index.entries.len());
+    if let Ok(mut g) = REPOSITORY_INDEX.write() {
+        *g = index;
+    }
+}
+
+/// Returns the index of the last repository scan.
+pub fn get_repository_index() -> MudRepositoryIndex {
+    REPOSITORY_INDEX.read().map(|g| g.clone()).unwrap_or_default()
+}
+
+/// Reads the MUD-File for the MUD-URL from the repository, if the repository contains one.
+pub fn read_mud(url: &str) -> Result<Option<LocalMud>> {
+    let entry = match REPOSITORY_INDEX.read().ok().and_then(|g| g.entries.get(url).cloned()) {
+        Some(entry) => entry,
+        None => return Ok(None),
+    };
+    debug!("Reading MUD-File {} from {}", url, entry.path);
+    Ok(Some(LocalMud {
+        mud_json: fs::read_to_string(&entry.path)?,
+        signature: entry.signature_path.map(fs::read).transpose()?,
+    }))
+}
+
+/// Indexes the MUD-Files in the directory and its subdirectories by their `mud-url`.
+/// Files that aren't MUD-Files are skipped, if several files have the same `mud-url` the first one is used.
+pub fn scan_directory(directory: &Path) -> MudRepositoryIndex {
+    let mut index = MudRepositoryIndex {
+        scanned_at: Some(Utc::now().naive_utc()),
+        entries: BTreeMap::new(),
+    };
+    let mut directories = vec![directory.to_path_buf()];
+    while let Some(directory) = directories.pop() {
+        let mut paths: Vec<PathBuf> = match fs::read_dir(&directory) {
+            Ok(entries) => entries.filter_map(|e| e.ok().map(|e| e.path())).collect(),
+            Err(e) => {
+                warn!("Could not read MUD repository directory {:?}: {:?}", directory, e);
+                continue;
+            },
+        };
+        // sort the paths, so the file used for duplicate MUD-URLs doesn't depend on the file system
+        paths.sort();
+        for path in paths {
+            if path.is_dir() {
+                directories.push(path);
+            } else if path.extension().map_or(false, |e| e == MUD_EXTENSION) {
+                index_file(&path, &mut index);
+            }
+        }
+    }
+    index
+}
+
+fn index_file(path: &Path, index: &mut MudRepositoryIndex) {
+    let mud_url = match fs::read_to_string(path)
+        .map_err(|e| e.to_string())
+        .and_then(|json| serde_json::from_str::<json_models::MudJson>(&json).map_err(|e| e.to_string()))
+    {
+        Ok(mud_json) => mud_json.mud.mud_url,
+        Err(e) => {
+            debug!("Skipping {:?} in MUD repository: {}", path, e);
+            return;
+        },
+    };
+    if let Some(entry) = index.entries.get(&mud_url) {
+        warn!(
+            "Skipping {:?} in MUD repository, {} already has the MUD-URL {}",
+            path, entry.path, mud_url
+        );
+        return;
+    }
+    let signature_path = Some(path.with_extension(SIGNATURE_EXTENSION)).filter(|p| p.is_file());
+    index.entries.insert(
+        mud_url.clone(),
+        MudRepositoryEntry {
+            mud_url,
+            path: path.to_string_lossy().into_owned(),
+            signature_path: signature_path.map(|p| p.to_string_lossy().into_owned()),
+        },
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scan_directory() {
+        let index = scan_directory(Path::new("tests/mud_tests"));
+
+        assert!(index.scanned_at.is_some());
+        assert_eq!(
+            index.entries.keys().collect::<Vec<_>>(),
+            vec![
+                "https://amazonecho.com/amazonecho",
+                "https://augustdoorbellcam.com/augustdoorbellcam",
+                "https://lighting.example.com/lightbulb2000",
+                "https://ringdoorbell.com/ringdoorbell",
+            ]
+        );
+        assert_eq!(
+            index.entries["https://amazonecho.com/amazonecho"],
+            MudRepositoryEntry {
+                mud_url: "https://amazonecho.com/amazonecho".to_string(),
+                path: "tests/mud_tests/Amazon-Echo.json".to_string(),
+                signature_path: None,
+            }
+        );
+        assert!(scan_directory(Path::new("tests/missing_directory")).entries.is_empty());
+    }
+}
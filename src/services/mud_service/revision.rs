@@ -0,0 +1,322 @@
+// Copyright 2020-2021, Benjamin Ludewig, Florian Bonetti, Jeffrey Munstermann, Luca Nittscher, Hugo Damer, Michael Bach
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use chrono::Utc;
+use paperclip::actix::Apiv2Schema;
+
+use crate::{
+    db::DbConnection,
+    error::Result,
+    models::{parse_stored_mud_data, Ace, Acl, MudData, MudRevisionDbo},
+    services::mud_service::get_mud,
+};
+
+/// The differences between the ACLs of two revisions of a MUD-Profile.
+#[derive(Debug, Clone, PartialEq, Serialize, Apiv2Schema)]
+pub struct MudRevisionDiff {
+    pub from_revision: i64,
+    pub to_revision: i64,
+    /// The differences between the ACLs of the MUD-Files.
+    pub acllist: AclListDiff,
+    /// The differences between the overrides set by the admin.
+    pub acl_override: AclListDiff,
+}
+
+/// The differences between two lists of ACLs, matched by their names.
+/// ACLs whose direction or type changed are treated as removed and added again.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Apiv2Schema)]
+pub struct AclListDiff {
+    pub added: Vec<Acl>,
+    pub removed: Vec<Acl>,
+    pub changed: Vec<AclDiff>,
+}
+
+/// The differences between the ACEs of two ACLs of the same name, matched by their names.
+#[derive(Debug, Clone, PartialEq, Serialize, Apiv2Schema)]
+pub struct AclDiff {
+    pub name: String,
+    pub added: Vec<Ace>,
+    pub removed: Vec<Ace>,
+    pub changed: Vec<AceDiff>,
+}
+
+/// An ACE that differs between two ACLs.
+#[derive(Debug, Clone, PartialEq, Serialize, Apiv2Schema)]
+pub struct AceDiff {
+    pub from: Ace,
+    pub to: Ace,
+}
+
+/// Stores the data of a MUD-Profile as a new revision, unless it equals the latest revision apart from the expiration.
+pub async fn record_revision(url: &str, data: &str, pool: &DbConnection) -> Result<()> {
+    let latest = sqlx::query_as!(
+        MudRevisionDbo,
+        "SELECT * FROM mud_revisions WHERE url = $1 ORDER BY id DESC LIMIT 1",
+        url
+    )
+    .fetch_optional(pool)
+    .await?;
+    let is_unchanged = match (latest.map(|r| r.parse_data()), parse_stored_mud_data(data)) {
+        (Some(Ok(latest)), Ok(mud_data)) => {
+            MudData {
+                expiration: mud_data.expiration,
+                ..latest
+            } == mud_data
+        },
+        _ => false,
+    };
+    if is_unchanged {
+        return Ok(());
+    }
+
+    let created_at = Utc::now().naive_utc();
+    sqlx::query!(
+        "INSERT INTO mud_revisions (url, data, created_at) VALUES ($1, $2, $3)",
+        url,
+        data,
+        created_at,
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Returns all revisions of a MUD-Profile, oldest first.
+pub async fn get_revisions(url: &str, pool: &DbConnection) -> Result<Vec<MudRevisionDbo>> {
+    Ok(sqlx::query_as!(
+        MudRevisionDbo,
+        "SELECT * FROM mud_revisions WHERE url = $1 ORDER BY id",
+        url
+    )
+    .fetch_all(pool)
+    .await?)
+}
+
+/// Returns the revision if it exists.
+pub async fn get_revision(id: i64, pool: &DbConnection) -> Result<Option<MudRevisionDbo>> {
+    Ok(
+        sqlx::query_as!(MudRevisionDbo, "SELECT * FROM mud_revisions WHERE id = $1", id)
+            .fetch_optional(pool)
+            .await?,
+    )
+}
+
+/// Pins the MUD-Profile to a revision, or unpins it if `revision_id` is `None`.
+pub async fn pin_revision(url: &str, revision_id: Option<i64>, pool: &DbConnection) -> Result<bool> {
+    let upd_count = sqlx::query!(
+        "UPDATE mud_data SET pinned_revision_id = $1 WHERE url = $2",
+        revision_id,
+        url
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(upd_count.rows_affected() == 1)
+}
+
+/// Replaces the MUD-File of the MUD-Profile with the one of the pinned revision, if it is pinned.
+/// The overrides and the expiration of the latest revision are kept, since they don't come from the manufacturer.
+pub async fn apply_pinned_revision(mud_data: MudData, pool: &DbConnection) -> Result<MudData> {
+    let pinned_revision_id = match get_mud(&mud_data.url, pool).await.and_then(|m| m.pinned_revision_id) {
+        Some(pinned_revision_id) => pinned_revision_id,
+        None => return Ok(mud_data),
+    };
+    match get_revision(pinned_revision_id, pool).await? {
+        Some(revision) => Ok(MudData {
+            acl_override: mud_data.acl_override,
+            expiration: mud_data.expiration,
+            ..revision.parse_data()?
+        }),
+        None => {
+            warn!(
+                "MUD-Profile {} is pinned to missing revision {}",
+                mud_data.url, pinned_revision_id
+            );
+            Ok(mud_data)
+        },
+    }
+}
+
+/// Compares the ACLs and overrides of two revisions.
+pub fn diff_revisions(from: &MudRevisionDbo, to: &MudRevisionDbo) -> Result<MudRevisionDiff> {
+    let (from_data, to_data) = (from.parse_data()?, to.parse_data()?);
+    Ok(MudRevisionDiff {
+        from_revision: from.id,
+        to_revision: to.id,
+        acllist: diff_acls(&from_data.acllist, &to_data.acllist),
+        acl_override: diff_acls(&from_data.acl_override, &to_data.acl_override),
+    })
+}
+
+fn diff_acls(from: &[Acl], to: &[Acl]) -> AclListDiff {
+    let is_same_acl =
+        |a: &Acl, b: &Acl| a.name == b.name && a.packet_direction == b.packet_direction && a.acl_type == b.acl_type;
+    let mut diff = AclListDiff::default();
+    for to_acl in to {
+        match from.iter().find(|from_acl| is_same_acl(from_acl, to_acl)) {
+            None => diff.added.push(to_acl.clone()),
+            Some(from_acl) if from_acl != to_acl => diff.changed.push(diff_aces(from_acl, to_acl)),
+            Some(_) => {},
+        }
+    }
+    diff.removed = from
+        .iter()
+        .filter(|from_acl| !to.iter().any(|to_acl| is_same_acl(from_acl, to_acl)))
+        .cloned()
+        .collect();
+    diff
+}
+
+fn diff_aces(from: &Acl, to: &Acl) -> AclDiff {
+    let mut diff = AclDiff {
+        name: to.name.clone(),
+        added: Vec::new(),
+        removed: Vec::new(),
+        changed: Vec::new(),
+    };
+    for to_ace in &to.ace {
+        match from.ace.iter().find(|from_ace| from_ace.name == to_ace.name) {
+            None => diff.added.push(to_ace.clone()),
+            Some(from_ace) if from_ace != to_ace => diff.changed.push(AceDiff {
+                from: from_ace.clone(),
+                to: to_ace.clone(),
+            }),
+            Some(_) => {},
+        }
+    }
+    diff.removed = from
+        .ace
+        .iter()
+        .filter(|from_ace| !to.ace.iter().any(|to_ace| to_ace.name == from_ace.name))
+        .cloned()
+        .collect();
+    diff
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        models::{AceAction, AceMatches, AclDirection, AclType},
+        services::mud_service::generate_empty_custom_mud_profile,
+    };
+
+    fn ace(name: &str, action: AceAction) -> Ace {
+        Ace {
+            name: name.to_string(),
+            action,
+            matches: AceMatches {
+                protocol: None,
+                direction_initiated: None,
+                source_network: None,
+                destination_network: None,
+                source_dnsname: None,
+                destination_dnsname: None,
+                source_port: None,
+                destination_port: None,
+                icmp: None,
+                eth: None,
+                mud: None,
+            },
+        }
+    }
+
+    fn acl(name: &str, acl_type: AclType, ace: Vec<Ace>) -> Acl {
+        Acl {
+            name: name.to_string(),
+            packet_direction: AclDirection::FromDevice,
+            acl_type,
+            ace,
+        }
+    }
+
+    fn revision(id: i64, acllist: Vec<Acl>) -> MudRevisionDbo {
+        let mut mud_data = generate_empty_custom_mud_profile("https://example.com/device", Vec::new());
+        mud_data.acllist = acllist;
+        MudRevisionDbo {
+            id,
+            url: mud_data.url.clone(),
+            data: serde_json::to_string(&mud_data).unwrap(),
+            created_at: Utc::now().naive_utc(),
+        }
+    }
+
+    #[test]
+    fn test_diff_revisions() -> Result<()> {
+        let unchanged = acl("unchanged", AclType::IPV4, vec![ace("ace", AceAction::Accept)]);
+        let from = revision(
+            1,
+            vec![
+                unchanged.clone(),
+                acl("removed", AclType::IPV4, vec![]),
+                acl("retyped", AclType::IPV4, vec![]),
+                acl(
+                    "changed",
+                    AclType::IPV6,
+                    vec![
+                        ace("kept", AceAction::Accept),
+                        ace("removed", AceAction::Accept),
+                        ace("changed", AceAction::Accept),
+                    ],
+                ),
+            ],
+        );
+        let to = revision(
+            2,
+            vec![
+                unchanged,
+                acl("retyped", AclType::IPV6, vec![]),
+                acl(
+                    "changed",
+                    AclType::IPV6,
+                    vec![
+                        ace("kept", AceAction::Accept),
+                        ace("changed", AceAction::Deny),
+                        ace("added", AceAction::Accept),
+                    ],
+                ),
+                acl("added", AclType::IPV4, vec![]),
+            ],
+        );
+
+        assert_eq!(
+            diff_revisions(&from, &to)?,
+            MudRevisionDiff {
+                from_revision: 1,
+                to_revision: 2,
+                acllist: AclListDiff {
+                    added: vec![
+                        acl("retyped", AclType::IPV6, vec![]),
+                        acl("added", AclType::IPV4, vec![]),
+                    ],
+                    removed: vec![
+                        acl("removed", AclType::IPV4, vec![]),
+                        acl("retyped", AclType::IPV4, vec![]),
+                    ],
+                    changed: vec![AclDiff {
+                        name: "changed".to_string(),
+                        added: vec![ace("added", AceAction::Accept)],
+                        removed: vec![ace("removed", AceAction::Accept)],
+                        changed: vec![AceDiff {
+                            from: ace("changed", AceAction::Accept),
+                            to: ace("changed", AceAction::Deny),
+                        }],
+                    }],
+                },
+                acl_override: AclListDiff::default(),
+            }
+        );
+        assert_eq!(
+            diff_revisions(&from, &from)?,
+            MudRevisionDiff {
+                from_revision: 1,
+                to_revision: 1,
+                acllist: AclListDiff::default(),
+                acl_override: AclListDiff::default(),
+            }
+        );
+
+        Ok(())
+    }
+}
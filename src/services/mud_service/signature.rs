@@ -0,0 +1,220 @@
+// Copyright 2020-2021, Benjamin Ludewig, Florian Bonetti, Jeffrey Munstermann, Luca Nittscher, Hugo Damer, Michael Bach
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use lazy_static::lazy_static;
+use openssl::{
+    pkcs7::{Pkcs7, Pkcs7Flags},
+    stack::Stack,
+    x509::{
+        store::{X509Store, X509StoreBuilder},
+        X509,
+    },
+};
+use url::Url;
+
+use crate::{
+    app_config::APP_CONFIG,
+    db::DbConnection,
+    error::Result,
+    models::{MudData, MudSignatureStatus},
+    services::{
+        config_service::{get_config_value, ConfigKeys},
+        mud_service::{fetch::fetch_mud_signature, source::MudSourceKind},
+    },
+};
+
+lazy_static! {
+    /// The trust store MUD signatures are verified against, loaded once by `init_trust_store`.
+    /// `None` if it couldn't be loaded, in which case no signature is valid.
+    static ref TRUST_STORE: Option<X509Store> = match load_trust_store() {
+        Ok(store) => Some(store),
+        Err(e) => {
+            error!("Failed to load the MUD trust store: {:?}", e);
+            None
+        },
+    };
+}
+
+/// Loads the trust store, so it doesn't have to be read while verifying signatures.
+pub fn init_trust_store() {
+    lazy_static::initialize(&TRUST_STORE);
+}
+
+/// Decides what happens to MUD-Files whose signature is missing or can't be verified.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, strum::AsRefStr, strum::EnumString)]
+pub enum MudSignaturePolicy {
+    /// The MUD-File is not accepted.
+    Reject,
+    /// The MUD-File is accepted, but a warning is logged.
+    Warn,
+    /// The MUD-File is accepted, but the devices using it only get the default rules and the overrides.
+    Quarantine,
+}
+
+impl Default for MudSignaturePolicy {
+    fn default() -> Self {
+        MudSignaturePolicy::Warn
+    }
+}
+
+/// Returns the signature policy configured by the admin, or the default policy if none is configured.
+pub async fn get_signature_policy(pool: &DbConnection) -> MudSignaturePolicy {
+    get_config_value(ConfigKeys::MudSignaturePolicy.as_ref(), pool)
+        .await
+        .unwrap_or_default()
+}
+
+/// Verifies the signature of the MUD-File against the configured trust store. The signature provided by the MUD source
+/// is used if there is one, otherwise the signature referenced by the MUD-File is fetched, if the `https` source is
+/// configured. Errors while fetching or verifying the signature are logged and result in `MudSignatureStatus::Invalid`.
+pub async fn verify_mud_signature(mud_data: &MudData, mud_json: &str, signature: Option<&[u8]>) -> MudSignatureStatus {
+    if let Some(signature) = signature {
+        return check_signature(mud_data, mud_json, signature, "provided by the MUD source");
+    }
+    let signature_url = match &mud_data.mud_signature {
+        Some(signature_url) => signature_url,
+        None => return MudSignatureStatus::Unsigned,
+    };
+    if !APP_CONFIG.mud_sources.contains(&MudSourceKind::Https) {
+        warn!(
+            "Can't fetch signature {} of {}, since the https MUD source isn't configured",
+            signature_url, mud_data.url
+        );
+        return MudSignatureStatus::Invalid;
+    }
+    // the signature url may be relative to the mud url
+    let signature_url = match Url::parse(&mud_data.url).and_then(|url| url.join(signature_url)) {
+        Ok(url) if url.scheme() == "https" => url,
+        _ => {
+            warn!(
+                "MUD-File {} references invalid signature url {}",
+                mud_data.url, signature_url
+            );
+            return MudSignatureStatus::Invalid;
+        },
+    };
+    let signature = match fetch_mud_signature(signature_url.as_str()).await {
+        Ok(signature) => signature,
+        Err(e) => {
+            warn!(
+                "Failed to fetch signature {} of {}: {:?}",
+                signature_url, mud_data.url, e
+            );
+            return MudSignatureStatus::Invalid;
+        },
+    };
+    check_signature(mud_data, mud_json, &signature, signature_url.as_str())
+}
+
+fn check_signature(mud_data: &MudData, mud_json: &str, signature: &[u8], signature_source: &str) -> MudSignatureStatus {
+    let trust_store = match &*TRUST_STORE {
+        Some(trust_store) => trust_store,
+        None => {
+            warn!(
+                "Can't verify signature {} of {} without a trust store",
+                signature_source, mud_data.url
+            );
+            return MudSignatureStatus::Invalid;
+        },
+    };
+    match verify_signature(mud_json.as_bytes(), signature, trust_store) {
+        Ok(()) => MudSignatureStatus::Valid,
+        Err(e) => {
+            warn!(
+                "Failed to verify signature {} of {}: {:?}",
+                signature_source, mud_data.url, e
+            );
+            MudSignatureStatus::Invalid
+        },
+    }
+}
+
+/// Verifies a DER encoded detached CMS signature of the given content.
+pub fn verify_signature(content: &[u8], signature: &[u8], trust_store: &X509Store) -> Result<()> {
+    let signature = Pkcs7::from_der(signature)?;
+    signature.verify(&Stack::new()?, trust_store, Some(content), None, Pkcs7Flags::BINARY)?;
+    Ok(())
+}
+
+/// Loads the certificates from `MUD_TRUST_STORE`, or the system trust store if it isn't set.
+fn load_trust_store() -> Result<X509Store> {
+    let mut builder = X509StoreBuilder::new()?;
+    match &APP_CONFIG.mud_trust_store {
+        Some(path) => {
+            for cert in X509::stack_from_pem(&std::fs::read(path)?)? {
+                builder.add_cert(cert)?;
+            }
+        },
+        None => builder.set_default_paths()?,
+    }
+    Ok(builder.build())
+}
+
+#[cfg(test)]
+mod tests {
+    use openssl::{
+        asn1::Asn1Time,
+        hash::MessageDigest,
+        pkey::{PKey, Private},
+        rsa::Rsa,
+        x509::{extension::BasicConstraints, X509Builder, X509NameBuilder},
+    };
+
+    use super::*;
+
+    fn self_signed_certificate(common_name: &str) -> (X509, PKey<Private>) {
+        let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+        let mut name = X509NameBuilder::new().unwrap();
+        name.append_entry_by_text("CN", common_name).unwrap();
+        let name = name.build();
+        let mut builder = X509Builder::new().unwrap();
+        builder.set_version(2).unwrap();
+        builder.set_subject_name(&name).unwrap();
+        builder.set_issuer_name(&name).unwrap();
+        builder.set_pubkey(&key).unwrap();
+        builder.set_not_before(&Asn1Time::days_from_now(0).unwrap()).unwrap();
+        builder.set_not_after(&Asn1Time::days_from_now(1).unwrap()).unwrap();
+        builder
+            .append_extension(BasicConstraints::new().critical().ca().build().unwrap())
+            .unwrap();
+        builder.sign(&key, MessageDigest::sha256()).unwrap();
+        (builder.build(), key)
+    }
+
+    fn trust_store(cert: X509) -> X509Store {
+        let mut builder = X509StoreBuilder::new().unwrap();
+        builder.add_cert(cert).unwrap();
+        builder.build()
+    }
+
+    fn sign(content: &[u8], cert: &X509, key: &PKey<Private>) -> Vec<u8> {
+        Pkcs7::sign(
+            cert,
+            key,
+            &Stack::new().unwrap(),
+            content,
+            Pkcs7Flags::DETACHED | Pkcs7Flags::BINARY,
+        )
+        .unwrap()
+        .to_der()
+        .unwrap()
+    }
+
+    #[test]
+    fn test_verify_signature() {
+        let content = br#"{"ietf-mud:mud": {"mud-version": 1}}"#;
+        let (cert, key) = self_signed_certificate("MUD Signer");
+        let (untrusted_cert, _) = self_signed_certificate("Other Signer");
+        let signature = sign(content, &cert, &key);
+
+        assert!(verify_signature(content, &signature, &trust_store(cert.clone())).is_ok());
+        assert!(verify_signature(
+            br#"{"ietf-mud:mud": {"mud-version": 2}}"#,
+            &signature,
+            &trust_store(cert)
+        )
+        .is_err());
+        assert!(verify_signature(content, &signature, &trust_store(untrusted_cert)).is_err());
+        assert!(verify_signature(content, b"not a signature", &X509StoreBuilder::new().unwrap().build()).is_err());
+    }
+}
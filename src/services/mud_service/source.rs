@@ -0,0 +1,201 @@
+// Copyright 2020-2021, Benjamin Ludewig, Florian Bonetti, Jeffrey Munstermann, Luca Nittscher, Hugo Damer, Michael Bach
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use paperclip::actix::Apiv2Schema;
+
+use crate::{
+    app_config::APP_CONFIG,
+    error,
+    error::Result,
+    models::MudDbo,
+    services::{
+        mud_service::{
+            fetch::{fetch_mud, CacheValidators, FetchedMud},
+            repository,
+        },
+        neo4things_service,
+    },
+};
+
+/// The kinds of MUD sources that can be configured in `MUD_SOURCES`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Apiv2Schema)]
+#[serde(rename_all = "lowercase")]
+pub enum MudSourceKind {
+    /// Fetches MUD-Files from their MUD-URL.
+    Https,
+    /// Reads MUD-Files from the directory configured in `MUD_REPOSITORY`.
+    Repository,
+    /// Queries the neo4things service for the MUD-Files it knows.
+    Neo4things,
+}
+
+/// A MUD-File provided by a `MudSource`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourcedMudFile {
+    pub mud_json: String,
+    /// The validators for conditional requests, see `fetch::fetch_mud`.
+    pub validators: CacheValidators,
+    /// The detached signature of the MUD-File, if the source provides it.
+    /// Otherwise the signature referenced by the MUD-File is fetched.
+    pub signature: Option<Vec<u8>>,
+}
+
+/// The result of asking a `MudSource` for a MUD-File.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SourcedMud {
+    Modified(SourcedMudFile),
+    /// The MUD-File didn't change since it was retrieved with the given validators.
+    NotModified,
+}
+
+/// A source of MUD-Files. The configured sources are asked in order, until one of them provides the MUD-File.
+#[async_trait]
+pub trait MudSource: Send + Sync {
+    /// The name recorded in `MudDbo::source` for MUD-Files provided by this source.
+    fn name(&self) -> &'static str;
+
+    /// Retrieves the MUD-File for the MUD-URL, or `None` if the source doesn't know the MUD-URL.
+    /// `validators` are passed if the stored MUD-File was provided by this source.
+    async fn fetch(&self, url: &str, validators: Option<&CacheValidators>) -> Result<Option<SourcedMud>>;
+}
+
+/// Fetches MUD-Files from their MUD-URL as specified by RFC8520.
+pub struct HttpsMudSource;
+
+#[async_trait]
+impl MudSource for HttpsMudSource {
+    fn name(&self) -> &'static str {
+        "https"
+    }
+
+    async fn fetch(&self, url: &str, validators: Option<&CacheValidators>) -> Result<Option<SourcedMud>> {
+        Ok(Some(match fetch_mud(url, validators).await? {
+            FetchedMud::Modified(mud_json, validators) => SourcedMud::Modified(SourcedMudFile {
+                mud_json,
+                validators,
+                signature: None,
+            }),
+            FetchedMud::NotModified => SourcedMud::NotModified,
+        }))
+    }
+}
+
+/// Reads MUD-Files and their signatures from the local MUD repository, see `repository`.
+pub struct RepositoryMudSource;
+
+#[async_trait]
+impl MudSource for RepositoryMudSource {
+    fn name(&self) -> &'static str {
+        "repository"
+    }
+
+    async fn fetch(&self, url: &str, _validators: Option<&CacheValidators>) -> Result<Option<SourcedMud>> {
+        Ok(repository::read_mud(url)?.map(|local_mud| {
+            SourcedMud::Modified(SourcedMudFile {
+                mud_json: local_mud.mud_json,
+                validators: CacheValidators::default(),
+                signature: local_mud.signature,
+            })
+        }))
+    }
+}
+
+/// Queries the neo4things service, which may know newer MUD-Files than the ones served at their MUD-URL.
+pub struct Neo4thingsMudSource;
+
+#[async_trait]
+impl MudSource for Neo4thingsMudSource {
+    fn name(&self) -> &'static str {
+        "neo4things"
+    }
+
+    async fn fetch(&self, url: &str, _validators: Option<&CacheValidators>) -> Result<Option<SourcedMud>> {
+        Ok(neo4things_service::get_mud_json(url).await?.map(|mud_json| {
+            SourcedMud::Modified(SourcedMudFile {
+                mud_json,
+                validators: CacheValidators::default(),
+                signature: None,
+            })
+        }))
+    }
+}
+
+/// Provides a fixed set of MUD-Files by their MUD-URL, e.g. for tests.
+pub struct StaticMudSource {
+    muds: HashMap<String, String>,
+}
+
+impl StaticMudSource {
+    pub fn new(muds: HashMap<String, String>) -> Self {
+        StaticMudSource { muds }
+    }
+}
+
+#[async_trait]
+impl MudSource for StaticMudSource {
+    fn name(&self) -> &'static str {
+        "static"
+    }
+
+    async fn fetch(&self, url: &str, _validators: Option<&CacheValidators>) -> Result<Option<SourcedMud>> {
+        Ok(self.muds.get(url).map(|mud_json| {
+            SourcedMud::Modified(SourcedMudFile {
+                mud_json: mud_json.clone(),
+                validators: CacheValidators::default(),
+                signature: None,
+            })
+        }))
+    }
+}
+
+/// Returns the sources configured in `MUD_SOURCES`, in order.
+pub fn configured_sources() -> Vec<Box<dyn MudSource>> {
+    APP_CONFIG
+        .mud_sources
+        .iter()
+        .map(|kind| -> Box<dyn MudSource> {
+            match kind {
+                MudSourceKind::Https => Box::new(HttpsMudSource),
+                MudSourceKind::Repository => Box::new(RepositoryMudSource),
+                MudSourceKind::Neo4things => Box::new(Neo4thingsMudSource),
+            }
+        })
+        .collect()
+}
+
+/// Asks the sources in order for the MUD-File, until one of them provides it.
+/// Returns the name of the source that provided it. Failing sources are skipped, their last error is returned if no
+/// other source provides the MUD-File.
+pub async fn fetch_from_sources(
+    url: &str,
+    stored_mud: Option<&MudDbo>,
+    sources: &[Box<dyn MudSource>],
+) -> Result<(&'static str, SourcedMud)> {
+    let mut last_error = None;
+    for source in sources {
+        let validators = stored_mud
+            .filter(|mud| mud.source.as_deref() == Some(source.name()))
+            .map(|mud| CacheValidators {
+                etag: mud.etag.clone(),
+                last_modified: mud.last_modified.clone(),
+            });
+        match source.fetch(url, validators.as_ref()).await {
+            Ok(Some(sourced_mud)) => return Ok((source.name(), sourced_mud)),
+            Ok(None) => debug!("MUD source {} doesn't provide {}", source.name(), url),
+            Err(e) => {
+                warn!("MUD source {} failed to provide {}: {:?}", source.name(), url, e);
+                last_error = Some(e);
+            },
+        }
+    }
+    match last_error {
+        Some(e) => Err(e),
+        None => error::MudError {
+            message: format!("No MUD source provides {}", url),
+        }
+        .fail(),
+    }
+}
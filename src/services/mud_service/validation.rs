@@ -0,0 +1,450 @@
+// Copyright 2020-2021, Benjamin Ludewig, Florian Bonetti, Jeffrey Munstermann, Luca Nittscher, Hugo Damer, Michael Bach
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+#![allow(clippy::field_reassign_with_default)]
+
+use paperclip::actix::Apiv2Schema;
+use serde_json::Value;
+
+use super::{json_models, parser};
+use crate::{error::Error, models::AclType};
+
+/// The key of the MUD container in a MUD-File.
+const MUD_KEY: &str = "ietf-mud:mud";
+/// The key of the ACL container in a MUD-File.
+const ACLS_KEY: &str = "ietf-access-control-list:acls";
+/// The key of the ACL container used by older drafts of RFC8519, which is still accepted by the parser.
+const LEGACY_ACLS_KEY: &str = "ietf-access-control-list:access-lists";
+/// The port operators supported by the parser.
+const PORT_OPERATORS: [&str; 4] = ["eq", "lte", "gte", "neq"];
+
+#[derive(Debug, Serialize, Deserialize, Apiv2Schema, Clone, Copy, Eq, PartialEq)]
+pub enum MudDiagnosticSeverity {
+    /// The MUD-File is rejected by the parser.
+    Error,
+    /// The MUD-File is accepted, but probably doesn't do what its author intended.
+    Warning,
+}
+
+/// Identifies the kind of a problem, stays the same even if the message changes.
+#[derive(Debug, Serialize, Deserialize, Apiv2Schema, Clone, Copy, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum MudDiagnosticCode {
+    InvalidStructure,
+    UnsupportedMudVersion,
+    InvalidCacheValidity,
+    MudUrlMismatch,
+    UnknownAcl,
+    UnusedAcl,
+    UnsupportedAclType,
+    NonStandardAclType,
+    AceIpVersionMismatch,
+    InvalidNetwork,
+    InvalidDirection,
+    UnsupportedPortOperator,
+    InvalidPort,
+    InvalidEthertype,
+    InvalidMacAddress,
+}
+
+/// A problem found in a MUD-File.
+#[derive(Debug, Serialize, Deserialize, Apiv2Schema, Clone, Eq, PartialEq)]
+pub struct MudDiagnostic {
+    /// The JSON pointer (RFC6901) to the offending value.
+    pub pointer: String,
+    pub severity: MudDiagnosticSeverity,
+    pub code: MudDiagnosticCode,
+    pub message: String,
+}
+
+impl MudDiagnostic {
+    fn error(pointer: String, code: MudDiagnosticCode, message: String) -> Self {
+        MudDiagnostic {
+            pointer,
+            severity: MudDiagnosticSeverity::Error,
+            code,
+            message,
+        }
+    }
+
+    fn warning(pointer: String, code: MudDiagnosticCode, message: String) -> Self {
+        MudDiagnostic {
+            pointer,
+            severity: MudDiagnosticSeverity::Warning,
+            code,
+            message,
+        }
+    }
+}
+
+/// Checks the MUD-File for all problems that would make `parser::parse_mud` reject it, and for problems that would
+/// make it behave unexpectedly. If `mud_url` is given, the MUD-File has to be served from this url.
+pub fn validate_mud(json: &Value, mud_url: Option<&str>) -> Vec<MudDiagnostic> {
+    let mud_json: json_models::MudJson = match serde_json::from_value(json.clone()) {
+        Ok(mud_json) => mud_json,
+        Err(e) => {
+            return vec![MudDiagnostic::error(
+                String::new(),
+                MudDiagnosticCode::InvalidStructure,
+                e.to_string(),
+            )]
+        },
+    };
+    let acls_key = if json.get(LEGACY_ACLS_KEY).is_some() {
+        LEGACY_ACLS_KEY
+    } else {
+        ACLS_KEY
+    };
+
+    let mut diagnostics = Vec::new();
+    validate_definition(&mud_json.mud, mud_url, &mut diagnostics);
+    validate_policies(&mud_json, acls_key, &mut diagnostics);
+    for (acl_index, acl) in mud_json.acls.acl.iter().enumerate() {
+        validate_acl(acl, &format!("/{}/acl/{}", acls_key, acl_index), &mut diagnostics);
+    }
+    diagnostics
+}
+
+fn validate_definition(mud: &json_models::MudDefinition, mud_url: Option<&str>, diagnostics: &mut Vec<MudDiagnostic>) {
+    if mud.mud_version != 1 {
+        diagnostics.push(MudDiagnostic::error(
+            format!("/{}/mud-version", MUD_KEY),
+            MudDiagnosticCode::UnsupportedMudVersion,
+            format!("Unsupported MUD Version {}", mud.mud_version),
+        ));
+    }
+    if let Some(cache_validity) = mud.cache_validity {
+        if cache_validity < 1 || cache_validity > 168 {
+            diagnostics.push(MudDiagnostic::error(
+                format!("/{}/cache-validity", MUD_KEY),
+                MudDiagnosticCode::InvalidCacheValidity,
+                format!("'cache-validity' {} is not between 1 and 168 hours", cache_validity),
+            ));
+        }
+    }
+    if let Some(mud_url) = mud_url {
+        if mud.mud_url != mud_url {
+            diagnostics.push(MudDiagnostic::error(
+                format!("/{}/mud-url", MUD_KEY),
+                MudDiagnosticCode::MudUrlMismatch,
+                format!("'mud-url' {} doesn't match the MUD-URL {}", mud.mud_url, mud_url),
+            ));
+        }
+    }
+}
+
+fn validate_policies(mud_json: &json_models::MudJson, acls_key: &str, diagnostics: &mut Vec<MudDiagnostic>) {
+    let policies = [
+        ("from-device-policy", &mud_json.mud.from_device_policy),
+        ("to-device-policy", &mud_json.mud.to_device_policy),
+    ];
+    for (policy_key, policy) in &policies {
+        for (index, access_list) in policy.access_lists.access_list.iter().enumerate() {
+            if mud_json.acls.acl.iter().all(|acl| acl.name != access_list.name) {
+                diagnostics.push(MudDiagnostic::error(
+                    format!("/{}/{}/access-lists/access-list/{}/name", MUD_KEY, policy_key, index),
+                    MudDiagnosticCode::UnknownAcl,
+                    format!("Policy references unknown ACL '{}'", access_list.name),
+                ));
+            }
+        }
+    }
+    for (index, acl) in mud_json.acls.acl.iter().enumerate() {
+        let is_used = policies
+            .iter()
+            .any(|(_, policy)| policy.access_lists.access_list.iter().any(|a| a.name == acl.name));
+        if !is_used {
+            diagnostics.push(MudDiagnostic::warning(
+                format!("/{}/acl/{}/name", acls_key, index),
+                MudDiagnosticCode::UnusedAcl,
+                format!("ACL '{}' is not used by any policy", acl.name),
+            ));
+        }
+    }
+}
+
+fn validate_acl(acl: &json_models::Acl, pointer: &str, diagnostics: &mut Vec<MudDiagnostic>) {
+    let acl_type = match parser::parse_acl_type(&acl.type_field) {
+        Ok(acl_type) => {
+            if !parser::is_standard_acl_type(&acl.type_field) {
+                diagnostics.push(MudDiagnostic::warning(
+                    format!("{}/type", pointer),
+                    MudDiagnosticCode::NonStandardAclType,
+                    format!("ACL type '{}' is not defined by RFC 8519", acl.type_field),
+                ));
+            }
+            Some(acl_type)
+        },
+        Err(e) => {
+            diagnostics.push(MudDiagnostic::error(
+                format!("{}/type", pointer),
+                MudDiagnosticCode::UnsupportedAclType,
+                error_message(&e),
+            ));
+            None
+        },
+    };
+
+    for (index, ace) in acl.aces.ace.iter().enumerate() {
+        let pointer = format!("{}/aces/ace/{}/matches", pointer, index);
+        let matches = &ace.matches;
+        if let Some(ipv4) = &matches.ipv4 {
+            validate_ip_version(acl_type, AclType::IPV4, &format!("{}/ipv4", pointer), diagnostics);
+            let networks = [
+                ("source-ipv4-network", &ipv4.source_ipv4_network),
+                ("destination-ipv4-network", &ipv4.destination_ipv4_network),
+            ];
+            validate_networks(&networks, &format!("{}/ipv4", pointer), diagnostics);
+        }
+        if let Some(ipv6) = &matches.ipv6 {
+            validate_ip_version(acl_type, AclType::IPV6, &format!("{}/ipv6", pointer), diagnostics);
+            let networks = [
+                ("source-ipv6-network", &ipv6.source_ipv6_network),
+                ("destination-ipv6-network", &ipv6.destination_ipv6_network),
+            ];
+            validate_networks(&networks, &format!("{}/ipv6", pointer), diagnostics);
+        }
+        if let Some(tcp) = &matches.tcp {
+            let pointer = format!("{}/tcp", pointer);
+            validate_ports(&tcp.source_port, &tcp.destination_port, &pointer, diagnostics);
+            if let Some(direction) = &tcp.direction_initiated {
+                if direction != "from-device" && direction != "to-device" {
+                    diagnostics.push(MudDiagnostic::error(
+                        format!("{}/ietf-mud:direction-initiated", pointer),
+                        MudDiagnosticCode::InvalidDirection,
+                        format!("Invalid direction '{}'", direction),
+                    ));
+                }
+            }
+        }
+        if let Some(udp) = &matches.udp {
+            validate_ports(
+                &udp.source_port,
+                &udp.destination_port,
+                &format!("{}/udp", pointer),
+                diagnostics,
+            );
+        }
+        if let Some(eth) = &matches.eth {
+            let pointer = format!("{}/eth", pointer);
+            validate_ip_version(acl_type, AclType::Ethernet, &pointer, diagnostics);
+            if let Some(Err(e)) = eth.ethertype.as_ref().map(parser::parse_ethertype) {
+                diagnostics.push(MudDiagnostic::error(
+                    format!("{}/ethertype", pointer),
+                    MudDiagnosticCode::InvalidEthertype,
+                    error_message(&e),
+                ));
+            }
+            let mac_addresses = [
+                ("source-mac-address", &eth.source_mac_address),
+                ("destination-mac-address", &eth.destination_mac_address),
+            ];
+            for (key, mac_address) in &mac_addresses {
+                if let Some(Err(e)) = mac_address.as_deref().map(parser::parse_mac_address) {
+                    diagnostics.push(MudDiagnostic::error(
+                        format!("{}/{}", pointer, key),
+                        MudDiagnosticCode::InvalidMacAddress,
+                        error_message(&e),
+                    ));
+                }
+            }
+        }
+    }
+}
+
+fn validate_ip_version(
+    acl_type: Option<AclType>,
+    ace_type: AclType,
+    pointer: &str,
+    diagnostics: &mut Vec<MudDiagnostic>,
+) {
+    if let Some(acl_type) = acl_type {
+        if acl_type != ace_type {
+            diagnostics.push(MudDiagnostic::error(
+                pointer.to_string(),
+                MudDiagnosticCode::AceIpVersionMismatch,
+                format!("{:?} ACE in {:?} ACL", ace_type, acl_type),
+            ));
+        }
+    }
+}
+
+fn validate_networks(networks: &[(&str, &Option<String>)], pointer: &str, diagnostics: &mut Vec<MudDiagnostic>) {
+    for (key, network) in networks {
+        if let Some(Err(e)) = network.as_deref().map(parser::parse_network) {
+            diagnostics.push(MudDiagnostic::error(
+                format!("{}/{}", pointer, key),
+                MudDiagnosticCode::InvalidNetwork,
+                error_message(&e),
+            ));
+        }
+    }
+}
+
+fn validate_ports(
+    source_port: &Option<json_models::Port>,
+    destination_port: &Option<json_models::Port>,
+    pointer: &str,
+    diagnostics: &mut Vec<MudDiagnostic>,
+) {
+    for (key, port) in &[("source-port", source_port), ("destination-port", destination_port)] {
+        if let Some(port) = port {
+            if let Err(e) = parser::parse_mud_port(port) {
+                let pointer = format!("{}/{}", pointer, key);
+                diagnostics.push(match port.operator.as_deref() {
+                    Some(operator) if !PORT_OPERATORS.contains(&operator) => MudDiagnostic::error(
+                        format!("{}/operator", pointer),
+                        MudDiagnosticCode::UnsupportedPortOperator,
+                        error_message(&e),
+                    ),
+                    _ => MudDiagnostic::error(pointer, MudDiagnosticCode::InvalidPort, error_message(&e)),
+                });
+            }
+        }
+    }
+}
+
+/// Returns the message of the errors returned by the parser, without the name of the error.
+fn error_message(error: &Error) -> String {
+    match error {
+        Error::MudError { message, .. } => message.clone(),
+        _ => error.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::error::Result;
+
+    #[test]
+    fn test_valid_examples() -> Result<()> {
+        for (path, url) in &[
+            ("tests/mud_tests/Amazon-Echo.json", "https://amazonecho.com/amazonecho"),
+            (
+                "tests/mud_tests/Ring-Doorbell.json",
+                "https://ringdoorbell.com/ringdoorbell",
+            ),
+            (
+                "tests/mud_tests/August-Doorbell.json",
+                "https://augustdoorbellcam.com/augustdoorbellcam",
+            ),
+        ] {
+            let json: Value = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+            let diagnostics = validate_mud(&json, Some(url));
+            assert!(
+                diagnostics.iter().all(|d| d.severity != MudDiagnosticSeverity::Error),
+                "{:?}",
+                diagnostics
+            );
+        }
+
+        // the ethernet ACLs of these MUD-Files use the type of their generator instead of the one of RFC 8519
+        let json: Value = serde_json::from_str(&std::fs::read_to_string("tests/mud_tests/Amazon-Echo.json")?)?;
+        let non_standard: Vec<_> = validate_mud(&json, None)
+            .into_iter()
+            .filter(|d| d.code == MudDiagnosticCode::NonStandardAclType)
+            .collect();
+        assert_eq!(non_standard.len(), 1);
+        assert_eq!(non_standard[0].severity, MudDiagnosticSeverity::Warning);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_structure() {
+        let diagnostics = validate_mud(&json!({ "ietf-mud:mud": { "mud-version": 1 } }), None);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].code, MudDiagnosticCode::InvalidStructure);
+        assert_eq!(diagnostics[0].pointer, "");
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        let json = json!({
+            "ietf-mud:mud": {
+                "mud-version": 1,
+                "mud-url": "https://example.com/other-device",
+                "last-update": "2021-05-26T09:30:12+02:00",
+                "cache-validity": 200,
+                "is-supported": true,
+                "from-device-policy": { "access-lists": { "access-list": [{ "name": "from-ipv4" }] } },
+                "to-device-policy": { "access-lists": { "access-list": [{ "name": "to-ipv4" }] } }
+            },
+            "ietf-access-control-list:acls": {
+                "acl": [
+                    {
+                        "name": "from-ipv4",
+                        "type": "ipv4-acl-type",
+                        "aces": {
+                            "ace": [
+                                {
+                                    "name": "ipv6-ace",
+                                    "matches": { "ipv6": { "destination-ipv6-network": "2001:db8::/129" } },
+                                    "actions": { "forwarding": "accept" }
+                                },
+                                {
+                                    "name": "tcp-ace",
+                                    "matches": {
+                                        "tcp": {
+                                            "destination-port": { "operator": "range", "port": 443 },
+                                            "ietf-mud:direction-initiated": "sideways"
+                                        }
+                                    },
+                                    "actions": { "forwarding": "accept" }
+                                }
+                            ]
+                        }
+                    },
+                    {
+                        "name": "unused",
+                        "type": "ipv4-acl-type",
+                        "aces": { "ace": [] }
+                    }
+                ]
+            }
+        });
+
+        let diagnostics = validate_mud(&json, Some("https://example.com/device"));
+        let found: Vec<_> = diagnostics
+            .iter()
+            .map(|d| (d.severity, d.code, d.pointer.clone()))
+            .collect();
+        let error = |code, pointer: &str| (MudDiagnosticSeverity::Error, code, pointer.to_string());
+        let acl = "/ietf-access-control-list:acls/acl";
+        assert_eq!(
+            found,
+            vec![
+                error(MudDiagnosticCode::InvalidCacheValidity, "/ietf-mud:mud/cache-validity"),
+                error(MudDiagnosticCode::MudUrlMismatch, "/ietf-mud:mud/mud-url"),
+                error(
+                    MudDiagnosticCode::UnknownAcl,
+                    "/ietf-mud:mud/to-device-policy/access-lists/access-list/0/name"
+                ),
+                (
+                    MudDiagnosticSeverity::Warning,
+                    MudDiagnosticCode::UnusedAcl,
+                    format!("{}/1/name", acl)
+                ),
+                error(
+                    MudDiagnosticCode::AceIpVersionMismatch,
+                    &format!("{}/0/aces/ace/0/matches/ipv6", acl)
+                ),
+                error(
+                    MudDiagnosticCode::InvalidNetwork,
+                    &format!("{}/0/aces/ace/0/matches/ipv6/destination-ipv6-network", acl)
+                ),
+                error(
+                    MudDiagnosticCode::UnsupportedPortOperator,
+                    &format!("{}/0/aces/ace/1/matches/tcp/destination-port/operator", acl)
+                ),
+                error(
+                    MudDiagnosticCode::InvalidDirection,
+                    &format!("{}/0/aces/ace/1/matches/tcp/ietf-mud:direction-initiated", acl)
+                ),
+            ]
+        );
+    }
+}
@@ -105,6 +105,19 @@ pub async fn guess_thing(device: Device) -> Result<Vec<GuessDto>> {
         .collect())
 }
 
+/// Query the neo4jthings service for the MUD-File of a `mud_url`, which can be newer than the one served at the url.
+/// This operation should be run directly, since we are interested in the results.
+pub async fn get_mud_json(mud_url: &str) -> Result<Option<String>> {
+    let result = mud_api::mud_json_list(&N4T_CONFIG, mud_url, None)
+        .await
+        .or_else(|e| error::Neo4ThingsError { message: e.to_string() }.fail())?;
+
+    Ok(result
+        .results
+        .and_then(|results| results.into_iter().next())
+        .map(|mud| mud.json))
+}
+
 /// Notify the neo4jthings service that a `mud_url` was chosen for a given device.
 /// This operation should be run in the background as it is failsafe.
 pub async fn describe_thing(mac_or_duid: String, mud_url: String) {
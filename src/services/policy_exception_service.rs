@@ -0,0 +1,149 @@
+// Copyright 2020-2021, Benjamin Ludewig, Florian Bonetti, Jeffrey Munstermann, Luca Nittscher, Hugo Damer, Michael Bach
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use chrono::{Duration, NaiveDateTime, Utc};
+use tokio::time::sleep;
+
+use crate::{
+    db::DbConnection,
+    error::Result,
+    models::{PolicyException, PolicyExceptionDbo},
+    services::firewall_configuration_service,
+};
+
+/// The maximum time the expiry queue sleeps, so newly created exceptions are picked up.
+const MAX_IDLE_MINUTES: i64 = 1;
+
+/// Retires the policy exceptions when they expire, sleeping until the next one expires in between.
+pub async fn run_expiry_queue(conn: DbConnection) {
+    loop {
+        if let Err(e) = retire_expired_exceptions(&conn).await {
+            warn!("Failed to retire expired policy exceptions: {:?}", e);
+        }
+        let next_expiry = get_next_expiry(&conn).await.ok().flatten();
+        let max_idle = Duration::minutes(MAX_IDLE_MINUTES);
+        let delay = next_expiry.map_or(max_idle, |next_expiry| {
+            (next_expiry - Utc::now().naive_utc()).clamp(Duration::seconds(1), max_idle)
+        });
+        sleep(delay.to_std().unwrap_or_default()).await;
+    }
+}
+
+/// Returns all policy exceptions, including the retired ones, ordered by their expiry.
+pub async fn get_all_exceptions(pool: &DbConnection) -> Result<Vec<PolicyException>> {
+    let exceptions = sqlx::query_as!(
+        PolicyExceptionDbo,
+        "SELECT * FROM policy_exceptions ORDER BY expires_at"
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(exceptions.into_iter().map(PolicyException::from).collect())
+}
+
+/// Returns the policy exceptions that didn't expire yet.
+pub async fn get_active_exceptions(pool: &DbConnection) -> Result<Vec<PolicyException>> {
+    let now = Utc::now().naive_utc();
+    let exceptions = sqlx::query_as!(
+        PolicyExceptionDbo,
+        "SELECT * FROM policy_exceptions WHERE expires_at > $1",
+        now
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(exceptions.into_iter().map(PolicyException::from).collect())
+}
+
+pub async fn find_by_id(id: i64, pool: &DbConnection) -> Result<PolicyException> {
+    let exception = sqlx::query_as!(PolicyExceptionDbo, "SELECT * FROM policy_exceptions WHERE id = $1", id)
+        .fetch_one(pool)
+        .await?;
+
+    Ok(PolicyException::from(exception))
+}
+
+/// Creates a policy exception, which applies to the enforcers right away.
+pub async fn insert_exception(exception: &PolicyException, pool: &DbConnection) -> Result<i64> {
+    let acls = serde_json::to_string(&exception.acls)?;
+
+    #[cfg(not(feature = "postgres"))]
+    let result = sqlx::query!(
+        "INSERT INTO policy_exceptions (device_id, room_id, mud_url, acls, reason, created_by, created_at, expires_at) VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
+        exception.device_id,
+        exception.room_id,
+        exception.mud_url,
+        acls,
+        exception.reason,
+        exception.created_by,
+        exception.created_at,
+        exception.expires_at,
+    )
+    .execute(pool)
+    .await?
+    .last_insert_rowid();
+
+    #[cfg(feature = "postgres")]
+    let result = sqlx::query!(
+        "INSERT INTO policy_exceptions (device_id, room_id, mud_url, acls, reason, created_by, created_at, expires_at) VALUES ($1, $2, $3, $4, $5, $6, $7, $8) RETURNING id",
+        exception.device_id,
+        exception.room_id,
+        exception.mud_url,
+        acls,
+        exception.reason,
+        exception.created_by,
+        exception.created_at,
+        exception.expires_at,
+    )
+    .fetch_one(pool)
+    .await?
+    .id;
+
+    firewall_configuration_service::update_config_version(pool).await?;
+
+    Ok(result)
+}
+
+/// Deletes a policy exception before it expires.
+pub async fn delete_exception(id: i64, pool: &DbConnection) -> Result<bool> {
+    let del_count = sqlx::query!("DELETE FROM policy_exceptions WHERE id = $1", id)
+        .execute(pool)
+        .await?;
+
+    firewall_configuration_service::update_config_version(pool).await?;
+
+    Ok(del_count.rows_affected() == 1)
+}
+
+/// Marks the expired policy exceptions as retired and the firewall configuration as outdated if there were any.
+/// Returns the number of retired exceptions.
+pub async fn retire_expired_exceptions(pool: &DbConnection) -> Result<u64> {
+    let now = Utc::now().naive_utc();
+    let upd_count = sqlx::query!(
+        "UPDATE policy_exceptions SET retired_at = $1 WHERE retired_at IS NULL AND expires_at <= $2",
+        now,
+        now
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+
+    if upd_count > 0 {
+        info!("Retired {} expired policy exceptions", upd_count);
+        firewall_configuration_service::update_config_version(pool).await?;
+    }
+
+    Ok(upd_count)
+}
+
+/// Returns when the next policy exception that wasn't retired yet expires, if there is any.
+async fn get_next_expiry(pool: &DbConnection) -> Result<Option<NaiveDateTime>> {
+    let exceptions = sqlx::query_as!(
+        PolicyExceptionDbo,
+        "SELECT * FROM policy_exceptions WHERE retired_at IS NULL ORDER BY expires_at"
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(exceptions.first().map(|exception| exception.expires_at))
+}
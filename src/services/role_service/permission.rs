@@ -16,6 +16,18 @@ pub enum Permission {
     /// config/delete
     #[strum(serialize = "config/delete")]
     config__delete,
+    /// controller/list
+    #[strum(serialize = "controller/list")]
+    controller__list,
+    /// controller/read
+    #[strum(serialize = "controller/read")]
+    controller__read,
+    /// controller/write
+    #[strum(serialize = "controller/write")]
+    controller__write,
+    /// controller/delete
+    #[strum(serialize = "controller/delete")]
+    controller__delete,
     /// device/list
     #[strum(serialize = "device/list")]
     device__list,
@@ -28,6 +40,18 @@ pub enum Permission {
     /// device/delete
     #[strum(serialize = "device/delete")]
     device__delete,
+    /// exception/list
+    #[strum(serialize = "exception/list")]
+    exception__list,
+    /// exception/read
+    #[strum(serialize = "exception/read")]
+    exception__read,
+    /// exception/write
+    #[strum(serialize = "exception/write")]
+    exception__write,
+    /// exception/delete
+    #[strum(serialize = "exception/delete")]
+    exception__delete,
     /// role/list
     #[strum(serialize = "role/list")]
     role__list,
@@ -1,8 +1,11 @@
 mod lib;
+use lib::assert_patch_status;
 use namib_mud_controller::{
     models::Config,
     services::{config_service, config_service::ConfigKeys, firewall_configuration_service},
 };
+use reqwest::StatusCode;
+use serde_json::json;
 
 #[tokio::test(flavor = "multi_thread")]
 async fn test_version() {
@@ -24,6 +27,48 @@ async fn test_version() {
     );
 }
 
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_policy_changes_update_version() {
+    let ctx = lib::IntegrationTestContext::new("test_policy_changes_update_version")
+        .await
+        .start_test_server()
+        .await;
+    let (client, _auth_token) = lib::create_authorized_http_client(&ctx.server_addr).await;
+    let url = format!("http://{}/config", ctx.server_addr);
+
+    let version = firewall_configuration_service::get_config_version(&ctx.db_conn).await;
+    assert_patch_status(&client, &url, &json!({ "some": "thing" }), StatusCode::OK).await;
+    assert_eq!(
+        firewall_configuration_service::get_config_version(&ctx.db_conn).await,
+        version
+    );
+
+    assert_patch_status(
+        &client,
+        &url,
+        &json!({ (ConfigKeys::MudSignaturePolicy.as_ref()): "Quarantine" }),
+        StatusCode::OK,
+    )
+    .await;
+    assert_ne!(
+        firewall_configuration_service::get_config_version(&ctx.db_conn).await,
+        version
+    );
+
+    let version = firewall_configuration_service::get_config_version(&ctx.db_conn).await;
+    assert_patch_status(
+        &client,
+        &url,
+        &json!({ (ConfigKeys::UnsupportedDevicePolicy.as_ref()): "LocalOnly" }),
+        StatusCode::OK,
+    )
+    .await;
+    assert_ne!(
+        firewall_configuration_service::get_config_version(&ctx.db_conn).await,
+        version
+    );
+}
+
 #[tokio::test(flavor = "multi_thread")]
 async fn get_nothing() {
     let ctx = lib::IntegrationTestContext::new("get_nothing").await;
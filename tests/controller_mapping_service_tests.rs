@@ -0,0 +1,34 @@
+mod lib;
+
+use namib_mud_controller::{error::Result, models::ControllerMapping, services::controller_mapping_service};
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_controller_mappings() -> Result<()> {
+    let ctx = lib::IntegrationTestContext::new("test_controller_mappings").await;
+
+    let mut mapping = ControllerMapping {
+        id: 0,
+        uri: "urn:ietf:params:mud:dns".to_string(),
+        device_id: None,
+        host: Some("192.168.0.53".to_string()),
+    };
+    mapping.id = controller_mapping_service::insert_controller_mapping(&mapping, &ctx.db_conn).await?;
+    assert_eq!(
+        controller_mapping_service::get_all_controller_mappings(&ctx.db_conn).await?,
+        vec![mapping.clone()]
+    );
+
+    mapping.host = Some("dns.example.com".to_string());
+    assert!(controller_mapping_service::update_controller_mapping(&mapping, &ctx.db_conn).await?);
+    assert_eq!(
+        controller_mapping_service::find_by_id(mapping.id, &ctx.db_conn).await?,
+        mapping
+    );
+
+    assert!(controller_mapping_service::delete_controller_mapping(mapping.id, &ctx.db_conn).await?);
+    assert!(controller_mapping_service::get_all_controller_mappings(&ctx.db_conn)
+        .await?
+        .is_empty());
+
+    Ok(())
+}
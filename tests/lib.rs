@@ -209,6 +209,14 @@ pub async fn assert_put_status<B: Serialize>(client: &Client, url: &str, body: &
     assert_eq!(client.put(url).json(body).send().await.unwrap().status(), status_code)
 }
 
+/// Perform a PATCH request to the API using the given client, url, body and expected status code.
+/// Will fail if the status code does not match or the request itself fails.
+// Not actually dead code, wrongly detected as such because it is in lib.rs.
+#[allow(dead_code)]
+pub async fn assert_patch_status<B: Serialize>(client: &Client, url: &str, body: &B, status_code: StatusCode) {
+    assert_eq!(client.patch(url).json(body).send().await.unwrap().status(), status_code)
+}
+
 /// Perform a DELETE request to the API using the given client, url and expected status code.
 /// Will fail if the status code does not match or the request itself fails.
 // Not actually dead code, wrongly detected as such because it is in lib.rs.
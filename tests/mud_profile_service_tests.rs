@@ -1,15 +1,28 @@
 mod lib;
 
-use std::{fs::File, io::Read};
+use std::{collections::HashMap, fs::File, io::Read};
 
 use actix_web::web;
 use chrono::{Duration, NaiveDateTime, Timelike, Utc};
 use namib_mud_controller::{
     auth::AuthToken,
+    db::DbConnection,
     error::Result,
-    models::{MudData, MudDbo},
-    routes::{dtos::MudCreationDto, mud_controller},
-    services::mud_service::{get_or_fetch_mud, json_models, parser::parse_mud, update_outdated_profiles},
+    models::{AceAction, AcePatch, AcePatchOperation, MudData, MudDbo},
+    routes::{
+        dtos::{MudAcePatchDto, MudAceQueryDto, MudCreationDto, MudRevisionDiffQueryDto, MudUpdateQueryDto},
+        mud_controller,
+    },
+    services::mud_service::{
+        ace_patch, authority,
+        authority::MudAuthorityPolicy,
+        create_mud, generate_empty_custom_mud_profile, get_custom_mud_expiration, get_mud, get_next_refresh,
+        get_or_fetch_mud, get_or_fetch_mud_from_sources, inheritance, json_models,
+        parser::parse_mud,
+        refresh, revision,
+        source::{MudSource, StaticMudSource},
+        update_outdated_profiles, update_outdated_profiles_from_sources, upsert_mud,
+    },
 };
 
 #[tokio::test(flavor = "multi_thread")]
@@ -33,9 +46,11 @@ async fn test_create_mud() -> Result<()> {
         "name": "from-ipv4-amazonecho-1",
         "action": "Accept",
         "matches": {
-          "address_mask": null,
+          "source_network": null,
+          "destination_network": null,
           "direction_initiated": "FromDevice",
-          "dnsname": "softwareupdates.amazon.com",
+          "destination_dnsname": "softwareupdates.amazon.com",
+          "source_dnsname": null,
           "protocol": { "name": "Protocol", "num": 6 },
           "destination_port": { "single": 443 },
           "source_port": null
@@ -45,9 +60,11 @@ async fn test_create_mud() -> Result<()> {
         "name": "from-ipv4-amazonecho-2",
         "action": "Accept",
         "matches": {
-          "address_mask": null,
+          "source_network": null,
+          "destination_network": null,
           "direction_initiated": null,
-          "dnsname": "3.north-america.pool.ntp.org",
+          "destination_dnsname": "3.north-america.pool.ntp.org",
+          "source_dnsname": null,
           "protocol": { "name": "Protocol", "num": 17 },
           "destination_port": { "single": 123 },
           "source_port": null
@@ -64,9 +81,11 @@ async fn test_create_mud() -> Result<()> {
         "name": "from-ethernet-amazonecho-0",
         "action": "Accept",
         "matches": {
-          "address_mask": null,
+          "source_network": null,
+          "destination_network": null,
           "direction_initiated": null,
-          "dnsname": null,
+          "destination_dnsname": null,
+          "source_dnsname": null,
           "protocol": null,
           "destination_port": null,
           "source_port": null
@@ -76,9 +95,11 @@ async fn test_create_mud() -> Result<()> {
         "name": "from-ethernet-amazonecho-1",
         "action": "Accept",
         "matches": {
-          "address_mask": null,
+          "source_network": null,
+          "destination_network": null,
           "direction_initiated": null,
-          "dnsname": null,
+          "destination_dnsname": null,
+          "source_dnsname": null,
           "protocol": null,
           "destination_port": null,
           "source_port": null
@@ -95,9 +116,11 @@ async fn test_create_mud() -> Result<()> {
         "name": "to-ipv4-amazonecho-0",
         "action": "Accept",
         "matches": {
-          "address_mask": null,
+          "source_network": null,
+          "destination_network": null,
           "direction_initiated": null,
-          "dnsname": "3.north-america.pool.ntp.org",
+          "source_dnsname": "3.north-america.pool.ntp.org",
+          "destination_dnsname": null,
           "protocol": { "name": "Protocol", "num": 17 },
           "destination_port": null,
           "source_port": { "single": 123 }
@@ -107,9 +130,11 @@ async fn test_create_mud() -> Result<()> {
         "name": "to-ipv4-amazonecho-1",
         "action": "Accept",
         "matches": {
-          "address_mask": null,
+          "source_network": null,
+          "destination_network": null,
           "direction_initiated": null,
-          "dnsname": "ntp-g7g.amazon.com",
+          "source_dnsname": "ntp-g7g.amazon.com",
+          "destination_dnsname": null,
           "protocol": { "name": "Protocol", "num": 17 },
           "destination_port": null,
           "source_port": { "single": 123 }
@@ -119,9 +144,11 @@ async fn test_create_mud() -> Result<()> {
         "name": "to-ipv4-amazonecho-2",
         "action": "Accept",
         "matches": {
-          "address_mask": null,
+          "source_network": null,
+          "destination_network": null,
           "direction_initiated": null,
-          "dnsname": "api.amazonalexa.com",
+          "source_dnsname": "api.amazonalexa.com",
+          "destination_dnsname": null,
           "protocol": { "name": "Protocol", "num": 6 },
           "destination_port": null,
           "source_port": { "single": 443 }
@@ -134,6 +161,8 @@ async fn test_create_mud() -> Result<()> {
                 )
                 .unwrap(),
             ),
+            parent_url: None,
+            ace_patches: None,
         }),
     )
     .await?;
@@ -141,15 +170,13 @@ async fn test_create_mud() -> Result<()> {
     Ok(())
 }
 
-#[ignore]
 #[tokio::test(flavor = "multi_thread")]
-//tests whether update_outdated_profiles() works on expired profiles. Dependent on external Service.
+//tests whether update_outdated_profiles() works on expired profiles.
 async fn test_update_outdated_profiles() -> Result<()> {
     //Sets up an expired Amazon Echo profile
     const PATH: &str = "tests/mud_tests/Amazon-Echo.json";
     let ctx = lib::IntegrationTestContext::new("test_update_outdated_profiles").await;
-    //external URL containing the same contents as in the test file. Makes the test dependent on an external Service
-    let url: String = String::from("https://gitlab.freedesktop.org/sw0rd/MUD-Files/-/raw/master/amazonEchoMud.json");
+    let url: String = String::from("https://amazonecho.com/amazonecho");
     let mut file = File::open(PATH).unwrap_or_else(|_| panic!("Could not open {}", PATH));
     let mut str_data = String::new();
     file.read_to_string(&mut str_data)
@@ -158,6 +185,10 @@ async fn test_update_outdated_profiles() -> Result<()> {
     let duration = mud_json.mud.cache_validity.unwrap_or(48);
     let mud_data: MudData =
         parse_mud(url.clone(), str_data.as_str()).unwrap_or_else(|_| panic!("Could not parse {}", PATH));
+    //serves the same MUD-File when the profile is refreshed
+    let sources: Vec<Box<dyn MudSource>> = vec![Box::new(StaticMudSource::new(
+        vec![(url.clone(), str_data.clone())].into_iter().collect(),
+    ))];
 
     let present = Utc::now();
     let mud_dbo = MudDbo {
@@ -166,6 +197,12 @@ async fn test_update_outdated_profiles() -> Result<()> {
         created_at: Utc::now().naive_utc(),
         //the expiration time is set to an arbitrary value that is guaranteed to be prior to the current date
         expiration: (present - Duration::hours(duration)).naive_utc(),
+        signature_status: None,
+        etag: None,
+        last_modified: None,
+        pinned_revision_id: None,
+        source: None,
+        approved_at: None,
     };
 
     //Puts expired Profile into the Database
@@ -180,7 +217,7 @@ async fn test_update_outdated_profiles() -> Result<()> {
     .await?;
 
     //function call
-    update_outdated_profiles(&&ctx.db_conn).await?;
+    update_outdated_profiles_from_sources(&sources, &ctx.db_conn).await?;
 
     let new_mud_data: MudDbo = sqlx::query_as!(MudDbo, "SELECT * FROM mud_data WHERE url = $1", mud_data.url)
         .fetch_one(&ctx.db_conn)
@@ -228,8 +265,9 @@ async fn test_update_valid_profiles() -> Result<()> {
     file.read_to_string(&mut str_data)
         .unwrap_or_else(|_| panic!("Could not read {}", PATH));
 
-    let mud_data: MudData =
-        parse_mud(url.clone(), str_data.as_str()).unwrap_or_else(|_| panic!("Could not parse {}", PATH));
+    let mut mud_data: MudData = parse_mud(String::from("https://amazonecho.com/amazonecho"), str_data.as_str())
+        .unwrap_or_else(|_| panic!("Could not parse {}", PATH));
+    mud_data.url = url.clone();
 
     let mut mud_dbo = MudDbo {
         url: url.to_owned(),
@@ -238,6 +276,12 @@ async fn test_update_valid_profiles() -> Result<()> {
         created_at: Utc::now().naive_utc(),
         //the expiration time is set to an arbitrary value that is after the current date
         expiration: (Utc::now() + Duration::hours(duration)).naive_utc(),
+        signature_status: None,
+        etag: None,
+        last_modified: None,
+        pinned_revision_id: None,
+        source: None,
+        approved_at: None,
     };
 
     //Puts active Profile into the Database
@@ -277,6 +321,418 @@ async fn test_update_valid_profiles() -> Result<()> {
     Ok(())
 }
 
+#[tokio::test(flavor = "multi_thread")]
+//tests whether changed profiles are recorded as revisions, which can be compared and pinned
+async fn test_mud_revisions() -> Result<()> {
+    const PATH: &str = "tests/mud_tests/Amazon-Echo.json";
+    const URL: &str = "https://amazonecho.com/amazonecho";
+    let ctx = lib::IntegrationTestContext::new("test_mud_revisions").await;
+    let mut mud_data = parse_mud(URL.to_string(), &std::fs::read_to_string(PATH)?)?;
+    //the profile must not expire, otherwise get_or_fetch_mud would fetch it
+    mud_data.expiration = get_custom_mud_expiration();
+    let mut mud_dbo = MudDbo {
+        url: URL.to_string(),
+        data: serde_json::to_string(&mud_data)?,
+        created_at: Utc::now().naive_utc(),
+        expiration: mud_data.expiration.naive_utc(),
+        signature_status: None,
+        etag: None,
+        last_modified: None,
+        pinned_revision_id: None,
+        source: None,
+        approved_at: None,
+    };
+    create_mud(&mud_dbo, &ctx.db_conn).await?;
+
+    //storing unchanged data doesn't record a revision
+    upsert_mud(&mud_dbo, &ctx.db_conn).await?;
+    assert_eq!(revision::get_revisions(URL, &ctx.db_conn).await?.len(), 1);
+
+    //the manufacturer removes an ACL
+    let original_acllist = mud_data.acllist.clone();
+    let removed_acl = mud_data.acllist.remove(0);
+    mud_dbo.data = serde_json::to_string(&mud_data)?;
+    upsert_mud(&mud_dbo, &ctx.db_conn).await?;
+
+    let revisions = revision::get_revisions(URL, &ctx.db_conn).await?;
+    assert_eq!(revisions.len(), 2);
+    let diff = revision::diff_revisions(&revisions[0], &revisions[1])?;
+    assert_eq!(diff.acllist.removed, vec![removed_acl]);
+    assert!(diff.acllist.added.is_empty());
+    assert!(diff.acllist.changed.is_empty());
+
+    //revisions can only be compared through the MUD they belong to
+    let diff_query = |mud_url: &str| {
+        web::Query(MudRevisionDiffQueryDto {
+            mud_url: mud_url.to_string(),
+            from: revisions[0].id,
+            to: revisions[1].id,
+        })
+    };
+    let token = || AuthToken::generate_access_token(1, "admin".to_string(), vec!["**".to_string()]);
+    assert!(
+        mud_controller::diff_mud_revisions(web::Data::new(ctx.db_conn.clone()), token(), diff_query(URL))
+            .await
+            .is_ok()
+    );
+    assert!(mud_controller::diff_mud_revisions(
+        web::Data::new(ctx.db_conn.clone()),
+        token(),
+        diff_query("https://example.com/other-device")
+    )
+    .await
+    .is_err());
+
+    //pinning the first revision restores its ACLs, also after the profile is updated again
+    assert!(revision::pin_revision(URL, Some(revisions[0].id), &ctx.db_conn).await?);
+    upsert_mud(&mud_dbo, &ctx.db_conn).await?;
+    assert_eq!(get_or_fetch_mud(URL, &ctx.db_conn).await?.acllist, original_acllist);
+
+    //unpinning uses the latest revision again
+    assert!(revision::pin_revision(URL, None, &ctx.db_conn).await?);
+    assert_eq!(get_or_fetch_mud(URL, &ctx.db_conn).await?.acllist, mud_data.acllist);
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+//tests whether MUD-Files are retrieved from the first source that provides them and the source is recorded
+async fn test_mud_sources() -> Result<()> {
+    const PATH: &str = "tests/mud_tests/Amazon-Echo.json";
+    const URL: &str = "https://amazonecho.com/amazonecho";
+    let ctx = lib::IntegrationTestContext::new("test_mud_sources").await;
+    let mud_json = std::fs::read_to_string(PATH)?;
+    let sources: Vec<Box<dyn MudSource>> = vec![
+        Box::new(StaticMudSource::new(HashMap::new())),
+        Box::new(StaticMudSource::new(
+            vec![(URL.to_string(), mud_json.clone())].into_iter().collect(),
+        )),
+    ];
+
+    let mud_data = get_or_fetch_mud_from_sources(URL, &sources, &ctx.db_conn).await?;
+    assert_eq!(mud_data.acllist, parse_mud(URL.to_string(), &mud_json)?.acllist);
+    assert_eq!(
+        get_mud(URL, &ctx.db_conn).await.and_then(|mud| mud.source),
+        Some("static".to_string())
+    );
+
+    //MUD-URLs that no source provides can't be retrieved
+    assert!(
+        get_or_fetch_mud_from_sources("https://example.com/unknown", &sources, &ctx.db_conn)
+            .await
+            .is_err()
+    );
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+//tests whether MUD-Files of untrusted authorities are held until they are approved and blocked authorities are refused
+async fn test_mud_authorities() -> Result<()> {
+    const PATH: &str = "tests/mud_tests/Amazon-Echo.json";
+    const URL: &str = "https://amazonecho.com/amazonecho";
+    let ctx = lib::IntegrationTestContext::new("test_mud_authorities").await;
+    let mut mud_data = parse_mud(URL.to_string(), &std::fs::read_to_string(PATH)?)?;
+    //the profile must not expire, otherwise get_or_fetch_mud would fetch it
+    mud_data.expiration = get_custom_mud_expiration();
+    create_mud(
+        &MudDbo {
+            url: URL.to_string(),
+            data: serde_json::to_string(&mud_data)?,
+            created_at: Utc::now().naive_utc(),
+            expiration: mud_data.expiration.naive_utc(),
+            signature_status: None,
+            etag: None,
+            last_modified: None,
+            pinned_revision_id: None,
+            source: None,
+            approved_at: None,
+        },
+        &ctx.db_conn,
+    )
+    .await?;
+
+    //authorities are trusted by default
+    assert_eq!(get_or_fetch_mud(URL, &ctx.db_conn).await?.acllist, mud_data.acllist);
+    assert!(authority::get_pending_muds(&ctx.db_conn).await?.is_empty());
+
+    //untrusted authorities, also of parent domains, require an approval
+    authority::set_authority_policy("AmazonEcho.com", MudAuthorityPolicy::Untrusted, &ctx.db_conn).await?;
+    assert!(get_or_fetch_mud(URL, &ctx.db_conn).await?.acllist.is_empty());
+    let pending = authority::get_pending_muds(&ctx.db_conn).await?;
+    assert_eq!(pending.iter().map(|m| m.url.as_str()).collect::<Vec<_>>(), vec![URL]);
+
+    assert!(authority::approve_mud(URL, &ctx.db_conn).await?);
+    assert_eq!(get_or_fetch_mud(URL, &ctx.db_conn).await?.acllist, mud_data.acllist);
+    assert!(authority::get_pending_muds(&ctx.db_conn).await?.is_empty());
+
+    //refreshing the same MUD-File keeps the approval, a changed MUD-File has to be approved again
+    let mud_json = std::fs::read_to_string(PATH)?;
+    let sources: Vec<Box<dyn MudSource>> = vec![Box::new(StaticMudSource::new(
+        vec![(URL.to_string(), mud_json.clone())].into_iter().collect(),
+    ))];
+    expire_mud(URL, &ctx.db_conn).await?;
+    assert_eq!(
+        get_or_fetch_mud_from_sources(URL, &sources, &ctx.db_conn)
+            .await?
+            .acllist,
+        mud_data.acllist
+    );
+    assert!(authority::get_pending_muds(&ctx.db_conn).await?.is_empty());
+    let sources: Vec<Box<dyn MudSource>> = vec![Box::new(StaticMudSource::new(
+        vec![(
+            URL.to_string(),
+            mud_json.replace("softwareupdates.amazon.com", "updates.example.com"),
+        )]
+        .into_iter()
+        .collect(),
+    ))];
+    expire_mud(URL, &ctx.db_conn).await?;
+    assert!(get_or_fetch_mud_from_sources(URL, &sources, &ctx.db_conn)
+        .await?
+        .acllist
+        .is_empty());
+    assert_eq!(authority::get_pending_muds(&ctx.db_conn).await?.len(), 1);
+
+    //blocked authorities are refused, the policy of the most specific authority applies
+    authority::set_authority_policy("com", MudAuthorityPolicy::Blocked, &ctx.db_conn).await?;
+    assert!(get_or_fetch_mud(URL, &ctx.db_conn).await.is_ok());
+    assert!(authority::delete_authority_policy("amazonecho.com", &ctx.db_conn).await?);
+    assert!(get_or_fetch_mud(URL, &ctx.db_conn).await.is_err());
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+//tests whether failing refreshes are recorded and retried later, without aborting the refresh of other profiles
+async fn test_refresh_failures() -> Result<()> {
+    const BROKEN_URL: &str = "https://127.0.0.1/broken.json";
+    const VALID_URL: &str = "https://example.com/valid.json";
+    let ctx = lib::IntegrationTestContext::new("test_refresh_failures").await;
+    for (url, expiration) in &[
+        (BROKEN_URL, Utc::now() - Duration::hours(1)),
+        (VALID_URL, Utc::now() + Duration::hours(1)),
+    ] {
+        let mut mud_data = generate_empty_custom_mud_profile(url, Vec::new());
+        mud_data.expiration = *expiration;
+        create_mud(
+            &MudDbo {
+                url: url.to_string(),
+                data: serde_json::to_string(&mud_data)?,
+                created_at: Utc::now().naive_utc(),
+                expiration: expiration.naive_utc(),
+                signature_status: None,
+                etag: None,
+                last_modified: None,
+                pinned_revision_id: None,
+                source: None,
+                approved_at: None,
+            },
+            &ctx.db_conn,
+        )
+        .await?;
+    }
+
+    //the loopback address is refused, the failure is recorded instead of aborting the update
+    update_outdated_profiles(&ctx.db_conn).await?;
+    let status = refresh::get_fetch_status(BROKEN_URL, &ctx.db_conn)
+        .await?
+        .expect("Failure is recorded");
+    assert_eq!(status.consecutive_failures, 1);
+    assert!(status.last_error.is_some());
+    assert!(status.last_success.is_none());
+    assert!(status.next_refresh > Utc::now().naive_utc());
+    assert!(refresh::get_fetch_status(VALID_URL, &ctx.db_conn).await?.is_none());
+
+    //the broken profile is kept and isn't retried before its backoff passed
+    assert!(get_mud(BROKEN_URL, &ctx.db_conn).await.is_some());
+    update_outdated_profiles(&ctx.db_conn).await?;
+    let retried_status = refresh::get_fetch_status(BROKEN_URL, &ctx.db_conn).await?.unwrap();
+    assert_eq!(retried_status.consecutive_failures, 1);
+    assert!(get_next_refresh(&ctx.db_conn).await?.is_some());
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+//tests whether custom MUD-Profiles inherit the ACLs of their parent, including later changes of the parent
+async fn test_mud_inheritance() -> Result<()> {
+    const PATH: &str = "tests/mud_tests/Amazon-Echo.json";
+    const PARENT_URL: &str = "https://amazonecho.com/amazonecho";
+    const CHILD_URL: &str = "Restricted Amazon Echo";
+    let ctx = lib::IntegrationTestContext::new("test_mud_inheritance").await;
+    let mut parent_data = parse_mud(PARENT_URL.to_string(), &std::fs::read_to_string(PATH)?)?;
+    //the profile must not expire, otherwise get_or_fetch_mud would fetch it
+    parent_data.expiration = get_custom_mud_expiration();
+    let mut parent_dbo = MudDbo {
+        url: PARENT_URL.to_string(),
+        data: serde_json::to_string(&parent_data)?,
+        created_at: Utc::now().naive_utc(),
+        expiration: parent_data.expiration.naive_utc(),
+        signature_status: None,
+        etag: None,
+        last_modified: None,
+        pinned_revision_id: None,
+        source: None,
+        approved_at: Some(Utc::now().naive_utc()),
+    };
+    create_mud(&parent_dbo, &ctx.db_conn).await?;
+
+    let child = mud_controller::create_mud(
+        web::Data::new(ctx.db_conn.clone()),
+        AuthToken::generate_access_token(1, "admin".to_string(), vec!["**".to_string()]),
+        web::Json(MudCreationDto {
+            mud_url: CHILD_URL.to_string(),
+            acl_override: None,
+            parent_url: Some(PARENT_URL.to_string()),
+            ace_patches: Some(vec![AcePatch {
+                acl_name: "from-ipv4-amazonecho".to_string(),
+                ace_name: "from-ipv4-amazonecho-1".to_string(),
+                operation: AcePatchOperation::Remove,
+                ace: None,
+                base: None,
+            }]),
+        }),
+    )
+    .await?
+    .into_inner();
+    let from_device = |mud_data: &MudData| {
+        mud_data
+            .acllist
+            .iter()
+            .find(|acl| acl.name == "from-ipv4-amazonecho")
+            .map(|acl| acl.ace.clone())
+            .unwrap_or_default()
+    };
+    let parent_aces = from_device(&parent_data);
+    let child_aces = from_device(&child);
+    assert_eq!(child_aces.len(), parent_aces.len() - 1);
+    assert!(child_aces.iter().all(|ace| ace.name != "from-ipv4-amazonecho-1"));
+    //the state of the removed ACE is recorded to detect later changes
+    let stored = get_mud(CHILD_URL, &ctx.db_conn).await.unwrap().parse_data()?;
+    assert_eq!(stored.ace_patches[0].base.as_ref(), Some(&parent_aces[1]));
+
+    //changes of the parent apply to the child immediately
+    for acl in parent_data
+        .acllist
+        .iter_mut()
+        .filter(|acl| acl.name == "from-ipv4-amazonecho")
+    {
+        acl.ace[0].action = AceAction::Deny;
+    }
+    parent_dbo.data = serde_json::to_string(&parent_data)?;
+    upsert_mud(&parent_dbo, &ctx.db_conn).await?;
+    let child = get_or_fetch_mud(CHILD_URL, &ctx.db_conn).await?;
+    assert_eq!(from_device(&child)[0].action, AceAction::Deny);
+    assert!(ace_patch::get_conflicts(CHILD_URL, &ctx.db_conn).await?.is_empty());
+
+    //the children are tracked, so the parent can't be deleted while they exist
+    assert_eq!(
+        inheritance::get_children(PARENT_URL, &ctx.db_conn).await?,
+        vec![CHILD_URL.to_string()]
+    );
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+//tests whether single ACEs of fetched MUD-Profiles can be patched, and whether upstream changes are detected
+async fn test_ace_patches() -> Result<()> {
+    const PATH: &str = "tests/mud_tests/Amazon-Echo.json";
+    const URL: &str = "https://amazonecho.com/amazonecho";
+    const ACL: &str = "from-ipv4-amazonecho";
+    let ctx = lib::IntegrationTestContext::new("test_ace_patches").await;
+    let mut mud_data = parse_mud(URL.to_string(), &std::fs::read_to_string(PATH)?)?;
+    //the profile must not expire, otherwise get_or_fetch_mud would fetch it
+    mud_data.expiration = get_custom_mud_expiration();
+    let mud_dbo = MudDbo {
+        url: URL.to_string(),
+        data: serde_json::to_string(&mud_data)?,
+        created_at: Utc::now().naive_utc(),
+        expiration: mud_data.expiration.naive_utc(),
+        signature_status: None,
+        etag: None,
+        last_modified: None,
+        pinned_revision_id: None,
+        source: None,
+        approved_at: Some(Utc::now().naive_utc()),
+    };
+    create_mud(&mud_dbo, &ctx.db_conn).await?;
+    let auth = || AuthToken::generate_access_token(1, "admin".to_string(), vec!["**".to_string()]);
+    let patch_ace = |ace_name: &str, operation: AcePatchOperation| {
+        mud_controller::patch_ace(
+            web::Data::new(ctx.db_conn.clone()),
+            auth(),
+            web::Query(MudUpdateQueryDto {
+                mud_url: URL.to_string(),
+            }),
+            web::Json(MudAcePatchDto {
+                acl_name: ACL.to_string(),
+                ace_name: ace_name.to_string(),
+                operation,
+                ace: None,
+            }),
+        )
+    };
+    let ace_names = |mud_data: &MudData| {
+        mud_data
+            .acllist
+            .iter()
+            .find(|acl| acl.name == ACL)
+            .map(|acl| acl.ace.iter().map(|ace| ace.name.clone()).collect::<Vec<_>>())
+            .unwrap_or_default()
+    };
+
+    //only the patched ACE changes
+    patch_ace("from-ipv4-amazonecho-1", AcePatchOperation::Remove).await?;
+    let patched = get_or_fetch_mud(URL, &ctx.db_conn).await?;
+    assert_eq!(ace_names(&patched).len(), ace_names(&mud_data).len() - 1);
+    assert!(!ace_names(&patched).contains(&"from-ipv4-amazonecho-1".to_string()));
+    assert_eq!(patched.acllist.len(), mud_data.acllist.len());
+
+    //ACEs missing upstream can't be patched, new ACEs need the ACE
+    assert!(patch_ace("missing", AcePatchOperation::Remove).await.is_err());
+    assert!(patch_ace("added", AcePatchOperation::Add).await.is_err());
+
+    //the patch is kept when the upstream ACE disappears, but reported as conflict
+    for acl in mud_data.acllist.iter_mut().filter(|acl| acl.name == ACL) {
+        acl.ace.retain(|ace| ace.name != "from-ipv4-amazonecho-1");
+    }
+    let mut mud_json: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(PATH)?)?;
+    for acl in mud_json["ietf-access-control-list:access-lists"]["acl"]
+        .as_array_mut()
+        .unwrap()
+    {
+        if let Some(aces) = acl["aces"]["ace"].as_array_mut() {
+            aces.retain(|ace| ace["name"] != "from-ipv4-amazonecho-1");
+        }
+    }
+    let sources: Vec<Box<dyn MudSource>> = vec![Box::new(StaticMudSource::new(
+        vec![(URL.to_string(), mud_json.to_string())].into_iter().collect(),
+    ))];
+    expire_mud(URL, &ctx.db_conn).await?;
+    get_or_fetch_mud_from_sources(URL, &sources, &ctx.db_conn).await?;
+    let conflicts = ace_patch::get_conflicts(URL, &ctx.db_conn).await?;
+    assert_eq!(conflicts.len(), 1);
+    assert_eq!(conflicts[0].reason, ace_patch::AcePatchConflictReason::AceMissing);
+
+    //reverting the ACE drops the patch
+    mud_controller::revert_ace(
+        web::Data::new(ctx.db_conn.clone()),
+        auth(),
+        web::Query(MudAceQueryDto {
+            mud_url: URL.to_string(),
+            acl_name: ACL.to_string(),
+            ace_name: "from-ipv4-amazonecho-1".to_string(),
+        }),
+    )
+    .await?;
+    assert!(ace_patch::get_conflicts(URL, &ctx.db_conn).await?.is_empty());
+    assert_eq!(get_or_fetch_mud(URL, &ctx.db_conn).await?.acllist, mud_data.acllist);
+
+    Ok(())
+}
+
 #[ignore]
 #[tokio::test(flavor = "multi_thread")]
 async fn test_invalid_mud_urls() {
@@ -311,6 +767,18 @@ async fn test_invalid_mud_urls() {
     .is_err());
 }
 
+async fn expire_mud(url: &str, pool: &DbConnection) -> Result<()> {
+    let mud = get_mud(url, pool).await.expect("MUD-Profile exists");
+    upsert_mud(
+        &MudDbo {
+            expiration: Utc::now().naive_utc() - Duration::hours(1),
+            ..mud
+        },
+        pool,
+    )
+    .await
+}
+
 fn strip_nano(ts: &mut NaiveDateTime) {
     *ts = ts.with_nanosecond(0).unwrap();
 }
@@ -0,0 +1,92 @@
+mod lib;
+
+use chrono::{Duration, Timelike, Utc};
+use namib_mud_controller::{
+    error::Result,
+    models::{PolicyException, Room},
+    services::{firewall_configuration_service, policy_exception_service, room_service},
+};
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_policy_exceptions() -> Result<()> {
+    let ctx = lib::IntegrationTestContext::new("test_policy_exceptions").await;
+    room_service::insert_room(
+        &Room {
+            room_id: 0,
+            name: "Office".to_string(),
+            color: "#ffffff".to_string(),
+        },
+        &ctx.db_conn,
+    )
+    .await?;
+    let room = room_service::find_by_name("Office", &ctx.db_conn).await?;
+
+    let now = Utc::now().naive_utc().with_nanosecond(0).unwrap();
+    let exception = |expires_at| PolicyException {
+        id: 0,
+        device_id: None,
+        room_id: Some(room.room_id),
+        mud_url: None,
+        acls: Vec::new(),
+        reason: "remote support".to_string(),
+        created_by: "admin".to_string(),
+        created_at: now,
+        expires_at,
+        retired_at: None,
+    };
+    let mut active = exception(now + Duration::hours(1));
+    active.id = policy_exception_service::insert_exception(&active, &ctx.db_conn).await?;
+    let expired_id =
+        policy_exception_service::insert_exception(&exception(now - Duration::minutes(1)), &ctx.db_conn).await?;
+    assert_eq!(
+        policy_exception_service::get_all_exceptions(&ctx.db_conn).await?.len(),
+        2
+    );
+    assert_eq!(
+        policy_exception_service::get_active_exceptions(&ctx.db_conn).await?,
+        vec![active.clone()]
+    );
+
+    // retiring the expired exception marks the firewall configuration as outdated
+    let version = firewall_configuration_service::get_config_version(&ctx.db_conn).await;
+    assert_eq!(
+        policy_exception_service::retire_expired_exceptions(&ctx.db_conn).await?,
+        1
+    );
+    assert_ne!(
+        firewall_configuration_service::get_config_version(&ctx.db_conn).await,
+        version
+    );
+    // retired exceptions are kept as audit trail, but don't apply anymore
+    assert!(policy_exception_service::find_by_id(expired_id, &ctx.db_conn)
+        .await?
+        .retired_at
+        .is_some());
+    assert_eq!(
+        policy_exception_service::get_active_exceptions(&ctx.db_conn).await?,
+        vec![active.clone()]
+    );
+
+    // nothing to retire leaves the version as is
+    let version = firewall_configuration_service::get_config_version(&ctx.db_conn).await;
+    assert_eq!(
+        policy_exception_service::retire_expired_exceptions(&ctx.db_conn).await?,
+        0
+    );
+    assert_eq!(
+        firewall_configuration_service::get_config_version(&ctx.db_conn).await,
+        version
+    );
+
+    assert!(policy_exception_service::delete_exception(active.id, &ctx.db_conn).await?);
+    assert_eq!(
+        policy_exception_service::get_all_exceptions(&ctx.db_conn)
+            .await?
+            .iter()
+            .map(|exception| exception.id)
+            .collect::<Vec<_>>(),
+        vec![expired_id]
+    );
+
+    Ok(())
+}